@@ -0,0 +1,53 @@
+//! Rough benchmarks without any dependencies, since the usual benchmarking
+//! crates don't support our MSRV.
+//!
+//! Run with `cargo bench --all-features`.
+
+use std::fmt::Write;
+use std::time::Instant;
+
+use os_display::Quoted;
+
+fn main() {
+    // `cargo bench` passes --bench, `cargo test --benches` doesn't. In the
+    // latter case we only check that everything runs.
+    let iterations = if std::env::args().any(|arg| arg == "--bench") {
+        100_000
+    } else {
+        1
+    };
+
+    let long_ascii = "abcdefghijklmnopqrstuvwxyz0123456789_.".repeat(250);
+    let long_ascii_special = "foo bar $baz `qux` \\ \"quux\" ".repeat(250);
+    let long_unicode = "ĳsberg ünïcödé テキスト ".repeat(250);
+    let long_escaped = "foo\nbar\tbaz\x1B[0m ".repeat(250);
+
+    let cases: &[(&str, &str)] = &[
+        ("short", "foo.txt"),
+        ("path", "/usr/share/doc/os_display/README.md"),
+        ("short special", "foo bar.txt"),
+        ("long ascii", &long_ascii),
+        ("long ascii special", &long_ascii_special),
+        ("long unicode", &long_unicode),
+        ("long escaped", &long_escaped),
+    ];
+
+    for &(name, text) in cases {
+        bench(&format!("unix {}", name), iterations, |buf| {
+            write!(buf, "{}", Quoted::unix(text).force(false)).unwrap()
+        });
+        bench(&format!("windows {}", name), iterations, |buf| {
+            write!(buf, "{}", Quoted::windows(text).force(false)).unwrap()
+        });
+    }
+}
+
+fn bench(name: &str, iterations: u32, mut f: impl FnMut(&mut String)) {
+    let mut buf = String::new();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        buf.clear();
+        f(&mut buf);
+    }
+    println!("{:<28} {:?}/iter", name, start.elapsed() / iterations);
+}
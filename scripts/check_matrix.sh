@@ -36,3 +36,48 @@ cargo +1.31 check --no-default-features --features unix,windows,std "$@"
 cargo +1.31 check --no-default-features --features native,unix,windows "$@"
 cargo +1.36 check --no-default-features --features native,unix,windows,alloc "$@"
 cargo +1.31 check --no-default-features --features native,unix,windows,std "$@"
+
+cargo +1.31 check --no-default-features --features c "$@"
+cargo +1.36 check --no-default-features --features c,alloc "$@"
+cargo +1.31 check --no-default-features --features native,c,std "$@"
+
+cargo +1.31 check --no-default-features --features json "$@"
+cargo +1.36 check --no-default-features --features json,alloc "$@"
+cargo +1.31 check --no-default-features --features native,json,std "$@"
+
+cargo +1.31 check --no-default-features --features python "$@"
+cargo +1.36 check --no-default-features --features python,alloc "$@"
+cargo +1.31 check --no-default-features --features native,python,std "$@"
+
+cargo +1.36 check --no-default-features --features unix,roundtrip "$@"
+cargo +1.36 check --no-default-features --features windows,roundtrip "$@"
+
+cargo +1.31 check --no-default-features --features xonsh "$@"
+cargo +1.36 check --no-default-features --features xonsh,alloc "$@"
+cargo +1.31 check --no-default-features --features native,xonsh,std "$@"
+
+cargo +1.31 check --no-default-features --features toml "$@"
+cargo +1.36 check --no-default-features --features toml,alloc "$@"
+cargo +1.31 check --no-default-features --features native,toml,std "$@"
+
+cargo +1.31 check --no-default-features --features yaml "$@"
+cargo +1.36 check --no-default-features --features yaml,alloc "$@"
+cargo +1.31 check --no-default-features --features native,yaml,std "$@"
+
+cargo +1.31 check --no-default-features --features csv "$@"
+cargo +1.36 check --no-default-features --features csv,alloc "$@"
+cargo +1.31 check --no-default-features --features native,csv,std "$@"
+
+cargo +1.31 check --no-default-features --features makefile "$@"
+cargo +1.36 check --no-default-features --features makefile,alloc "$@"
+cargo +1.31 check --no-default-features --features native,makefile,std "$@"
+
+cargo +1.31 check --no-default-features --features verbatim "$@"
+cargo +1.36 check --no-default-features --features verbatim,alloc "$@"
+cargo +1.31 check --no-default-features --features native,verbatim,std "$@"
+
+cargo +1.56 check --no-default-features --features heapless,unix "$@"
+cargo +1.56 check --no-default-features --features native,heapless,std "$@"
+
+cargo +1.57 check --no-default-features --features command "$@"
+cargo +1.57 check --no-default-features --features command,unix,windows "$@"
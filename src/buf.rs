@@ -0,0 +1,185 @@
+use core::fmt::{self, Display, Formatter};
+
+#[cfg(any(
+    feature = "unix",
+    feature = "windows",
+    feature = "native",
+    feature = "python",
+    feature = "toml",
+    feature = "yaml",
+    feature = "csv",
+    feature = "makefile",
+    feature = "verbatim"
+))]
+use crate::alloc::string::String;
+#[cfg(any(feature = "unix", feature = "windows", feature = "c", feature = "json"))]
+use crate::alloc::vec::Vec;
+#[cfg(feature = "native")]
+#[cfg(feature = "std")]
+use std::ffi::OsString;
+
+use crate::{Kind, Options, Quoted};
+
+/// An owned version of [`Quoted`].
+///
+/// [`Quoted`] borrows the text it wraps, so it can't be returned from a
+/// function that builds the text itself. This type owns a copy instead.
+///
+/// It's created using [`Quoted::into_owned`].
+///
+/// # Optional
+/// This requires the (default) `alloc` feature.
+#[derive(Debug, Clone)]
+pub struct QuotedBuf {
+    source: KindBuf,
+    opts: Options,
+}
+
+#[derive(Debug, Clone)]
+enum KindBuf {
+    #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+    Unix(String),
+    #[cfg(feature = "unix")]
+    UnixRaw(Vec<u8>),
+    #[cfg(feature = "unix")]
+    Printf(Vec<u8>),
+    #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+    Windows(String),
+    #[cfg(feature = "windows")]
+    WindowsRaw(Vec<u16>),
+    #[cfg(feature = "windows")]
+    WindowsUtf8(Vec<u8>),
+    #[cfg(feature = "windows")]
+    Wtf8(Vec<u8>),
+    #[cfg(feature = "native")]
+    #[cfg(feature = "std")]
+    NativeRaw(OsString),
+    #[cfg(feature = "native")]
+    NativeChar(char),
+    #[cfg(feature = "c")]
+    C(Vec<u8>),
+    #[cfg(feature = "json")]
+    Json(Vec<u8>),
+    #[cfg(feature = "python")]
+    Python(String),
+    #[cfg(feature = "xonsh")]
+    Xonsh(String),
+    #[cfg(feature = "toml")]
+    Toml(String),
+    #[cfg(feature = "yaml")]
+    Yaml(String),
+    #[cfg(feature = "csv")]
+    Csv(String),
+    #[cfg(feature = "makefile")]
+    Makefile(String),
+    #[cfg(feature = "makefile")]
+    MakefileShell(String),
+    #[cfg(feature = "verbatim")]
+    Verbatim(String),
+}
+
+impl QuotedBuf {
+    fn as_quoted(&self) -> Quoted<'_> {
+        let source = match self.source {
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            KindBuf::Unix(ref text) => Kind::Unix(text),
+            #[cfg(feature = "unix")]
+            KindBuf::UnixRaw(ref bytes) => Kind::UnixRaw(bytes),
+            #[cfg(feature = "unix")]
+            KindBuf::Printf(ref bytes) => Kind::Printf(bytes),
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            KindBuf::Windows(ref text) => Kind::Windows(text),
+            #[cfg(feature = "windows")]
+            KindBuf::WindowsRaw(ref units) => Kind::WindowsRaw(units),
+            #[cfg(feature = "windows")]
+            KindBuf::WindowsUtf8(ref bytes) => Kind::WindowsUtf8(bytes),
+            #[cfg(feature = "windows")]
+            KindBuf::Wtf8(ref bytes) => Kind::Wtf8(bytes),
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            KindBuf::NativeRaw(ref text) => Kind::NativeRaw(text),
+            #[cfg(feature = "native")]
+            KindBuf::NativeChar(ch) => Kind::NativeChar(ch),
+            #[cfg(feature = "c")]
+            KindBuf::C(ref bytes) => Kind::C(bytes),
+            #[cfg(feature = "json")]
+            KindBuf::Json(ref bytes) => Kind::Json(bytes),
+            #[cfg(feature = "python")]
+            KindBuf::Python(ref text) => Kind::Python(text),
+            #[cfg(feature = "xonsh")]
+            KindBuf::Xonsh(ref text) => Kind::Xonsh(text),
+            #[cfg(feature = "toml")]
+            KindBuf::Toml(ref text) => Kind::Toml(text),
+            #[cfg(feature = "yaml")]
+            KindBuf::Yaml(ref text) => Kind::Yaml(text),
+            #[cfg(feature = "csv")]
+            KindBuf::Csv(ref text) => Kind::Csv(text),
+            #[cfg(feature = "makefile")]
+            KindBuf::Makefile(ref text) => Kind::Makefile(text),
+            #[cfg(feature = "makefile")]
+            KindBuf::MakefileShell(ref text) => Kind::MakefileShell(text),
+            #[cfg(feature = "verbatim")]
+            KindBuf::Verbatim(ref text) => Kind::Verbatim(text),
+        };
+        Quoted {
+            source,
+            opts: self.opts,
+        }
+    }
+}
+
+impl<'a> From<Quoted<'a>> for QuotedBuf {
+    fn from(quoted: Quoted<'a>) -> Self {
+        let source = match quoted.source {
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => KindBuf::Unix(text.into()),
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) => KindBuf::UnixRaw(bytes.into()),
+            #[cfg(feature = "unix")]
+            Kind::Printf(bytes) => KindBuf::Printf(bytes.into()),
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => KindBuf::Windows(text.into()),
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(units) => KindBuf::WindowsRaw(units.into()),
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) => KindBuf::WindowsUtf8(bytes.into()),
+            #[cfg(feature = "windows")]
+            Kind::Wtf8(bytes) => KindBuf::Wtf8(bytes.into()),
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => KindBuf::NativeRaw(text.into()),
+            #[cfg(feature = "native")]
+            Kind::NativeChar(ch) => KindBuf::NativeChar(ch),
+            #[cfg(feature = "c")]
+            Kind::C(bytes) => KindBuf::C(bytes.into()),
+            #[cfg(feature = "json")]
+            Kind::Json(bytes) => KindBuf::Json(bytes.into()),
+            #[cfg(feature = "python")]
+            Kind::Python(text) => KindBuf::Python(text.into()),
+            #[cfg(feature = "xonsh")]
+            Kind::Xonsh(text) => KindBuf::Xonsh(text.into()),
+            #[cfg(feature = "toml")]
+            Kind::Toml(text) => KindBuf::Toml(text.into()),
+            #[cfg(feature = "yaml")]
+            Kind::Yaml(text) => KindBuf::Yaml(text.into()),
+            #[cfg(feature = "csv")]
+            Kind::Csv(text) => KindBuf::Csv(text.into()),
+            #[cfg(feature = "makefile")]
+            Kind::Makefile(text) => KindBuf::Makefile(text.into()),
+            #[cfg(feature = "makefile")]
+            Kind::MakefileShell(text) => KindBuf::MakefileShell(text.into()),
+            #[cfg(feature = "verbatim")]
+            Kind::Verbatim(text) => KindBuf::Verbatim(text.into()),
+        };
+        QuotedBuf {
+            source,
+            opts: quoted.opts,
+        }
+    }
+}
+
+impl Display for QuotedBuf {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.as_quoted().fmt(f)
+    }
+}
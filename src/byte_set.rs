@@ -0,0 +1,29 @@
+/// A set of ASCII bytes that's cheap to look up in.
+///
+/// Checking a short byte string with `.contains()` means a scan for every
+/// character, which adds up for long text.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct ByteSet(pub(crate) u128);
+
+/// Bytes that ASCII considers control characters, including DEL.
+pub(crate) const ASCII_CONTROL: ByteSet = ByteSet(((1 << 0x20) - 1) | (1 << 0x7F));
+
+impl ByteSet {
+    pub(crate) const fn union(self, other: ByteSet) -> ByteSet {
+        ByteSet(self.0 | other.0)
+    }
+
+    #[inline]
+    pub(crate) fn contains(self, byte: u8) -> bool {
+        byte < 128 && (self.0 >> byte) & 1 != 0
+    }
+}
+
+/// Build a [`ByteSet`] at compile time.
+///
+/// `const fn` can't loop on our MSRV, so this takes the bytes one by one.
+macro_rules! byte_set {
+    ($($byte:expr),* $(,)*) => {
+        crate::byte_set::ByteSet(0 $(| 1u128 << $byte)*)
+    };
+}
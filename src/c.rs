@@ -0,0 +1,108 @@
+use core::fmt::{self, Formatter, Write};
+
+use crate::{from_utf8_iter, Options};
+
+/// What the previous character was, as far as the next one is concerned.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Prev {
+    Other,
+    /// A \x escape, which takes as many hex digits as it can get.
+    Hex,
+    /// A \0 escape, which is octal and takes up to three digits.
+    Octal,
+    /// A question mark, which might start a trigraph.
+    Question,
+}
+
+/// Write a C string literal.
+///
+/// All of these escapes are in C89, so the output should also work for C++
+/// and for the many other languages that copied C's syntax.
+///
+/// Non-ASCII text is left as UTF-8, which is only correct for plain (narrow)
+/// string literals.
+pub(crate) fn write(f: &mut Formatter<'_>, text: &[u8], opts: &Options) -> fmt::Result {
+    opts.write_opening(f, '"', |f| f.write_char('"'))?;
+    // If a digit follows an escape that would swallow it we end the literal
+    // and start a new one, like in the Unix style. Adjacent string literals
+    // are concatenated by the compiler.
+    let mut prev = Prev::Other;
+    for chunk in from_utf8_iter(text) {
+        match chunk {
+            Ok(chunk) => {
+                for ch in chunk.chars() {
+                    let was = prev;
+                    prev = Prev::Other;
+                    if let Some(escape) = symbolic_escape(ch, opts) {
+                        opts.write_syntax(f, |f| f.write_str(escape))?;
+                        continue;
+                    }
+                    match ch {
+                        '\0' if opts.symbolic_escapes != Some(false) => {
+                            opts.write_syntax(f, |f| f.write_str("\\0"))?;
+                            prev = Prev::Octal;
+                        }
+                        ch if opts.escapes(ch) => {
+                            opts.write_syntax(f, |f| {
+                                for &byte in ch.encode_utf8(&mut [0; 4]).as_bytes() {
+                                    f.write_str("\\x")?;
+                                    opts.write_hex(f, byte.into(), 2)?;
+                                }
+                                Ok(())
+                            })?;
+                            prev = Prev::Hex;
+                        }
+                        '"' => opts.write_syntax(f, |f| f.write_str("\\\""))?,
+                        '\\' => opts.write_syntax(f, |f| f.write_str("\\\\"))?,
+                        // ??/ is a trigraph for \ (and there are others).
+                        // Trigraphs are long gone, but escaping is harmless.
+                        // A ? after an escaped one could still start another
+                        // trigraph, so every ? in a run but the first is
+                        // escaped.
+                        '?' if was == Prev::Question => {
+                            opts.write_syntax(f, |f| f.write_str("\\?"))?;
+                            prev = Prev::Question;
+                        }
+                        '?' => {
+                            f.write_char('?')?;
+                            prev = Prev::Question;
+                        }
+                        ch if was == Prev::Hex && ch.is_ascii_hexdigit() => {
+                            opts.write_syntax(f, |f| f.write_str("\"\""))?;
+                            f.write_char(ch)?;
+                        }
+                        ch @ '0'..='7' if was == Prev::Octal => {
+                            opts.write_syntax(f, |f| f.write_str("\"\""))?;
+                            f.write_char(ch)?;
+                        }
+                        ch => f.write_char(ch)?,
+                    }
+                }
+            }
+            Err(unit) => {
+                opts.write_syntax(f, |f| {
+                    f.write_str("\\x")?;
+                    opts.write_hex(f, unit.into(), 2)
+                })?;
+                prev = Prev::Hex;
+            }
+        }
+    }
+    opts.write_syntax(f, |f| f.write_char('"'))?;
+    Ok(())
+}
+
+/// The short form of an escape, if there is one and we want to use it.
+fn symbolic_escape(ch: char, opts: &Options) -> Option<&'static str> {
+    match (ch, opts.symbolic_escapes) {
+        (_, Some(false)) => None,
+        ('\n', _) => Some("\\n"),
+        ('\t', _) => Some("\\t"),
+        ('\r', _) => Some("\\r"),
+        ('\x07', Some(true)) => Some("\\a"),
+        ('\x08', Some(true)) => Some("\\b"),
+        ('\x0B', Some(true)) => Some("\\v"),
+        ('\x0C', Some(true)) => Some("\\f"),
+        _ => None,
+    }
+}
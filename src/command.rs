@@ -0,0 +1,160 @@
+use core::fmt::{self, Display, Formatter};
+use core::marker::PhantomData;
+
+#[cfg(feature = "command")]
+use std::{fmt::Write, process::Command, string::String, vec::Vec};
+
+use crate::Quoted;
+
+/// A list of arguments that's displayed as a single command line, like
+/// `rm -rf 'my dir'`.
+///
+/// Each argument is quoted only if necessary, as with
+/// [`Quotable::maybe_quote`][crate::Quotable::maybe_quote], and they're
+/// separated by spaces, or by another separator chosen with
+/// [`CommandLine::separator`].
+///
+/// The arguments can be anything that converts into a [`Quoted`]. With the
+/// `native` feature that includes references to strings and paths. For
+/// other styles, map them to [`Quoted`] yourself. That's also the place to
+/// set other options.
+///
+/// The iterator is cloned every time the command line is displayed, so it
+/// should be cheap to clone, like an iterator over a slice.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "unix")] {
+/// use os_display::{CommandLine, Quoted};
+///
+/// let args = ["rm", "-rf", "my dir"];
+/// let command = CommandLine::new(args.iter().map(|arg| Quoted::unix(arg)));
+/// assert_eq!(command.to_string(), "rm -rf 'my dir'");
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CommandLine<'a, I> {
+    args: I,
+    separator: &'a str,
+    marker: PhantomData<Quoted<'a>>,
+}
+
+impl<'a, I> CommandLine<'a, I>
+where
+    I: IntoIterator + Clone,
+    I::Item: Into<Quoted<'a>>,
+{
+    /// Create a command line from a list of arguments.
+    pub fn new(args: I) -> Self {
+        CommandLine {
+            args,
+            separator: " ",
+            marker: PhantomData,
+        }
+    }
+
+    /// Put something other than a space between the arguments, like `"\n"`
+    /// for one argument per line.
+    ///
+    /// The separator is written as it is, without quoting.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::{CommandLine, Quoted};
+    ///
+    /// let commands = ["make", "make install"];
+    /// let line = CommandLine::new(commands.iter().map(|cmd| Quoted::unix(cmd)));
+    /// assert_eq!(line.separator("; ").to_string(), "make; 'make install'");
+    /// # }
+    /// ```
+    pub fn separator(mut self, separator: &'a str) -> Self {
+        self.separator = separator;
+        self
+    }
+}
+
+impl<'a, I> Display for CommandLine<'a, I>
+where
+    I: IntoIterator + Clone,
+    I::Item: Into<Quoted<'a>>,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        for (i, arg) in self.args.clone().into_iter().enumerate() {
+            if i > 0 {
+                f.write_str(self.separator)?;
+            }
+            let arg = arg.into();
+            // bash doesn't expand aliases in quoted command names.
+            let force = i == 0 && arg.opts.avoid_aliases && !arg.source.is_powershell();
+            write!(f, "{}", arg.force(force))?;
+        }
+        Ok(())
+    }
+}
+
+/// Format a [`Command`] as a line that can be pasted into a shell, for
+/// example to log it when it fails.
+///
+/// The program and arguments are quoted in the native style, but only if
+/// necessary. Environment variables that are set for the command are put in
+/// front of it, with their values quoted the same way (except in PowerShell,
+/// which always needs quotes there). Variables that are removed, or whose
+/// names would need quoting, are left out.
+///
+/// In PowerShell a quoted program name is a plain string, so it's preceded
+/// by the call operator `&`.
+///
+/// This requires the `command` feature and Rust 1.57.
+///
+/// # Examples
+/// ```
+/// use std::process::Command;
+/// use os_display::quote_command;
+///
+/// let mut cmd = Command::new("ls");
+/// cmd.arg("my dir").env("LC_ALL", "C");
+/// // Unix: LC_ALL=C ls 'my dir'
+/// // Windows: $env:LC_ALL='C'; ls 'my dir'
+/// println!("{}", quote_command(&cmd));
+/// ```
+#[cfg(feature = "command")]
+// The feature has its own MSRV.
+#[allow(clippy::incompatible_msrv)]
+pub fn quote_command(cmd: &Command) -> String {
+    let mut out = String::new();
+
+    for (key, value) in cmd.get_envs() {
+        let assignment = match (key.to_str(), value) {
+            (Some(key), Some(value)) => Quoted::native_raw(value).force(false).env_assignment(key),
+            _ => None,
+        };
+        if let Some(assignment) = assignment {
+            let sep = if assignment.is_powershell() {
+                "; "
+            } else {
+                " "
+            };
+            write!(out, "{}{}", assignment, sep).unwrap();
+        }
+    }
+
+    let program = Quoted::native_raw(cmd.get_program()).force(false);
+    let start = out.len();
+    write!(out, "{}", program).unwrap();
+    if program.source.is_powershell() && out[start..].starts_with(|ch| ch == '\'' || ch == '"') {
+        out.insert_str(start, "& ");
+    }
+
+    let args: Vec<_> = cmd.get_args().collect();
+    if !args.is_empty() {
+        write!(
+            out,
+            " {}",
+            CommandLine::new(args.into_iter().map(Quoted::native_raw))
+        )
+        .unwrap();
+    }
+
+    out
+}
@@ -0,0 +1,35 @@
+use core::fmt::{self, Formatter, Write};
+
+use crate::Options;
+
+/// Write a CSV field.
+///
+/// https://www.rfc-editor.org/rfc/rfc4180#section-2
+///
+/// A field only needs quotes if it contains the delimiter, a double quote or
+/// a line break, and a double quote inside quotes is doubled. There are no
+/// other escapes, so control codes are written as they are.
+///
+/// An empty field is quoted anyway. A line that consists of nothing but an
+/// empty field would be empty itself, and many parsers skip empty lines.
+pub(crate) fn write(f: &mut Formatter<'_>, text: &str, opts: &Options) -> fmt::Result {
+    let delimiter = opts.delimiter;
+    let requires_quote = opts.force_quote
+        || text.is_empty()
+        || text.contains(|ch| ch == delimiter || ch == '"' || ch == '\r' || ch == '\n');
+    if !requires_quote {
+        return f.write_str(text);
+    }
+
+    opts.write_opening(f, '"', |f| f.write_char('"'))?;
+    let mut parts = text.split('"');
+    if let Some(part) = parts.next() {
+        f.write_str(part)?;
+    }
+    for part in parts {
+        opts.write_syntax(f, |f| f.write_str("\"\""))?;
+        f.write_str(part)?;
+    }
+    opts.write_syntax(f, |f| f.write_char('"'))?;
+    Ok(())
+}
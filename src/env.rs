@@ -0,0 +1,55 @@
+use core::fmt::{self, Display, Formatter};
+
+use crate::Quoted;
+
+/// An assignment to an environment variable, like `FOO='bar baz'`.
+///
+/// Only the value is quoted. In the PowerShell style this looks like
+/// `$env:FOO='bar baz'` instead, and the value is always quoted because
+/// PowerShell would otherwise try to run it as a command.
+///
+/// It's created using [`Quoted::env_assignment`].
+#[derive(Debug, Copy, Clone)]
+pub struct EnvAssignment<'a> {
+    key: &'a str,
+    value: Quoted<'a>,
+}
+
+impl<'a> EnvAssignment<'a> {
+    pub(crate) fn new(key: &'a str, value: Quoted<'a>) -> Option<Self> {
+        if is_identifier(key) {
+            Some(EnvAssignment { key, value })
+        } else {
+            None
+        }
+    }
+
+    #[cfg(feature = "command")]
+    pub(crate) fn is_powershell(&self) -> bool {
+        self.value.source.is_powershell()
+    }
+}
+
+impl<'a> Display for EnvAssignment<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.value.source.is_powershell() {
+            write!(f, "$env:{}={}", self.key, self.value.force(true))
+        } else {
+            write!(f, "{}={}", self.key, self.value)
+        }
+    }
+}
+
+/// Check for a name that can be assigned to without any quoting.
+///
+/// POSIX allows only ASCII letters, digits and underscores, and the name
+/// can't start with a digit. PowerShell is more lenient but this is safe
+/// there too.
+fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(first) if first == '_' || first.is_ascii_alphabetic() => {}
+        _ => return false,
+    }
+    chars.all(|ch| ch == '_' || ch.is_ascii_alphanumeric())
+}
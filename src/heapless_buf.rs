@@ -0,0 +1,50 @@
+use core::fmt::{self, Display, Formatter, Write};
+
+use crate::Quoted;
+
+/// The error returned by [`Quoted::quote_into_heapless`] if the buffer is
+/// too small.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CapacityError;
+
+impl Display for CapacityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("buffer is too small")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for CapacityError {}
+
+impl<'a> Quoted<'a> {
+    /// Append the quoted text to a fixed-size [`heapless::String`].
+    ///
+    /// This works without an allocator. If the text doesn't fit, the buffer
+    /// is left as it was and an error is returned.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let mut buf = heapless::String::<8>::new();
+    /// Quoted::unix("foo").quote_into_heapless(&mut buf).unwrap();
+    /// assert_eq!(buf, "'foo'");
+    /// assert!(Quoted::unix("bar").quote_into_heapless(&mut buf).is_err());
+    /// assert_eq!(buf, "'foo'");
+    /// # }
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the optional `heapless` feature.
+    pub fn quote_into_heapless<const N: usize>(
+        &self,
+        buf: &mut heapless::String<N>,
+    ) -> Result<(), CapacityError> {
+        let len = buf.len();
+        write!(buf, "{}", self).map_err(|_| {
+            buf.truncate(len);
+            CapacityError
+        })
+    }
+}
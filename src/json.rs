@@ -0,0 +1,64 @@
+use core::fmt::{self, Formatter, Write};
+
+use crate::{from_utf8_iter, Options};
+
+/// Write a JSON string.
+///
+/// https://www.rfc-editor.org/rfc/rfc8259#section-7
+///
+/// JSON only requires escapes for " and \ and for control codes below 0x20,
+/// but we also escape the other characters that could mess up a terminal.
+///
+/// JSON strings can't hold arbitrary bytes, so invalid UTF-8 is encoded the
+/// same way as Python's surrogateescape error handler does it: each bad byte
+/// becomes a lone surrogate between U+DC80 and U+DCFF. Most JSON parsers
+/// accept those, though some will replace them by U+FFFD.
+pub(crate) fn write(f: &mut Formatter<'_>, text: &[u8], opts: &Options) -> fmt::Result {
+    opts.write_opening(f, '"', |f| f.write_char('"'))?;
+    for chunk in from_utf8_iter(text) {
+        match chunk {
+            Ok(chunk) => {
+                for ch in chunk.chars() {
+                    match symbolic_escape(ch, opts) {
+                        Some(escape) => opts.write_syntax(f, |f| f.write_str(escape))?,
+                        None if opts.escapes(ch) => opts.write_syntax(f, |f| {
+                            // Characters outside the BMP are written as a
+                            // surrogate pair.
+                            for &unit in ch.encode_utf16(&mut [0; 2]).iter() {
+                                f.write_str("\\u")?;
+                                opts.write_hex(f, unit.into(), 4)?;
+                            }
+                            Ok(())
+                        })?,
+                        None if ch == '"' => opts.write_syntax(f, |f| f.write_str("\\\""))?,
+                        None if ch == '\\' => opts.write_syntax(f, |f| f.write_str("\\\\"))?,
+                        None => f.write_char(ch)?,
+                    }
+                }
+            }
+            Err(byte) => opts.write_syntax(f, |f| {
+                f.write_str("\\u")?;
+                opts.write_hex(f, 0xDC00 + u32::from(byte), 4)
+            })?,
+        }
+    }
+    opts.write_syntax(f, |f| f.write_char('"'))?;
+    Ok(())
+}
+
+/// The short form of an escape, if there is one and we want to use it.
+///
+/// These are all the ones JSON has, apart from \/.
+fn symbolic_escape(ch: char, opts: &Options) -> Option<&'static str> {
+    if opts.symbolic_escapes == Some(false) {
+        return None;
+    }
+    match ch {
+        '\n' => Some("\\n"),
+        '\t' => Some("\\t"),
+        '\r' => Some("\\r"),
+        '\x08' => Some("\\b"),
+        '\x0C' => Some("\\f"),
+        _ => None,
+    }
+}
@@ -4,6 +4,11 @@
 //! They will wrap quotes around them and add the necessary escapes to make
 //! them copy/paste-able into a shell.
 //!
+//! Control codes are always escaped, except by `Quoted::csv` and
+//! `Quoted::verbatim`. That includes the escape character that starts
+//! terminal escape sequences, so text can't recolor or otherwise mess with
+//! the terminal.
+//!
 //! The [`Quotable`] trait adds `quote` and `maybe_quote` methods to string
 //! types. The [`Quoted`] type has constructors for more explicit control.
 //!
@@ -23,12 +28,14 @@
 //! If the `windows`/`unix` features are enabled:
 //!
 //! ```
+//! # #[cfg(all(feature = "windows", feature = "unix"))] {
 //! use os_display::Quoted;
 //!
 //! // "foo`nbar"
 //! println!("{}", Quoted::windows("foo\nbar"));
 //! // $'foo\nbar'
 //! println!("{}", Quoted::unix("foo\nbar"));
+//! # }
 //! ```
 
 #![no_std]
@@ -37,6 +44,8 @@
 
 use core::fmt::{self, Display, Formatter};
 
+use crate::byte_set::ByteSet;
+
 #[cfg(feature = "std")]
 extern crate std;
 
@@ -46,7 +55,6 @@ extern crate std;
 #[cfg(all(feature = "alloc", not(feature = "std")))]
 extern crate alloc;
 
-#[cfg(feature = "windows")]
 #[cfg(feature = "std")]
 use std as alloc;
 
@@ -54,16 +62,588 @@ use std as alloc;
 #[cfg(feature = "std")]
 use std::{ffi::OsStr, path::Path};
 
-#[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+// Options always has a ByteSet, even if no style that looks in one is
+// enabled.
+#[cfg_attr(
+    not(any(
+        feature = "unix",
+        feature = "windows",
+        feature = "makefile",
+        feature = "native"
+    )),
+    allow(dead_code)
+)]
+#[macro_use]
+mod byte_set;
+#[cfg(feature = "c")]
+mod c;
+#[cfg(feature = "csv")]
+mod csv;
+#[cfg(feature = "json")]
+mod json;
+#[cfg(feature = "makefile")]
+mod makefile;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "toml")]
+mod toml;
+#[cfg(any(
+    feature = "unix",
+    feature = "makefile",
+    all(feature = "native", not(windows))
+))]
 mod unix;
+#[cfg(feature = "verbatim")]
+mod verbatim;
 #[cfg(any(feature = "windows", all(feature = "native", windows)))]
 mod windows;
+#[cfg(feature = "xonsh")]
+mod xonsh;
+#[cfg(feature = "yaml")]
+mod yaml;
+
+#[cfg(feature = "alloc")]
+mod buf;
+mod command;
+mod env;
+// In a separate file because older compilers can't parse const generics.
+#[cfg(feature = "heapless")]
+mod heapless_buf;
+#[cfg(all(feature = "alloc", any(feature = "unix", feature = "windows")))]
+mod nested;
+#[cfg(all(feature = "alloc", any(feature = "unix", feature = "windows")))]
+mod parse;
+mod truncate;
+#[cfg(any(
+    feature = "unix",
+    feature = "windows",
+    feature = "makefile",
+    feature = "native"
+))]
+mod whitespace;
+mod width;
+
+#[cfg(feature = "alloc")]
+pub use crate::buf::QuotedBuf;
+#[cfg(feature = "command")]
+pub use crate::command::quote_command;
+pub use crate::command::CommandLine;
+pub use crate::env::EnvAssignment;
+#[cfg(feature = "heapless")]
+pub use crate::heapless_buf::CapacityError;
+#[cfg(all(feature = "alloc", any(feature = "unix", feature = "windows")))]
+pub use crate::nested::{quote_all, quote_nested, QuoteStyle};
+#[cfg(all(feature = "alloc", feature = "unix"))]
+pub use crate::parse::unquote_unix;
+#[cfg(all(feature = "roundtrip", any(feature = "unix", feature = "windows")))]
+pub use crate::parse::verify_roundtrip;
+#[cfg(all(feature = "alloc", any(feature = "unix", feature = "windows")))]
+pub use crate::parse::ParseError;
+#[cfg(all(feature = "alloc", feature = "windows"))]
+pub use crate::parse::{unquote_windows, unquote_windows_raw};
 
 /// A wrapper around string types for displaying with quoting and escaping applied.
 #[derive(Debug, Copy, Clone)]
 pub struct Quoted<'a> {
     source: Kind<'a>,
+    opts: Options,
+}
+
+/// Settings that are shared by all the styles.
+///
+/// Not every style looks at every setting.
+#[derive(Debug, Copy, Clone)]
+struct Options {
     force_quote: bool,
+    empty_as: Option<&'static str>,
+    prefer: Option<QuoteChar>,
+    quote_order: Option<QuoteOrder>,
+    minimize: bool,
+    bidi: BidiPolicy,
+    ascii_only: bool,
+    allowed: Option<fn(char) -> bool>,
+    escape_invisible: bool,
+    escape_dialect: EscapeDialect,
+    always_ansi_c: bool,
+    /// Set by Quoted::escape_only().
+    escape_only: bool,
+    /// Set by Quoted::rendering(). Formatting stops at the opening quote.
+    find_delimiter: bool,
+    here_string: bool,
+    heredoc: bool,
+    control_style: ControlStyle,
+    newline_style: NewlineStyle,
+    invalid: InvalidPolicy,
+    replacement: Utf8Char,
+    strip_bom: bool,
+    #[cfg(feature = "nfc")]
+    normalize_nfc: bool,
+    glob_safe: bool,
+    protect_dashes: bool,
+    quote_keywords: bool,
+    protect_devices: bool,
+    quote_numbers: bool,
+    powershell_context: PowerShellContext,
+    /// `None` means the default mix.
+    symbolic_escapes: Option<bool>,
+    /// `None` means the style's default, usually uppercase.
+    lowercase_hex: Option<bool>,
+    interactive: bool,
+    avoid_aliases: bool,
+    color: bool,
+    max_width: Option<usize>,
+    max_output_len: Option<usize>,
+    east_asian: bool,
+    delimiter: char,
+    extra_specials: ByteSet,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            force_quote: true,
+            empty_as: None,
+            prefer: None,
+            quote_order: None,
+            minimize: false,
+            bidi: BidiPolicy::Escape,
+            ascii_only: false,
+            allowed: None,
+            escape_invisible: false,
+            escape_dialect: EscapeDialect::AnsiC,
+            always_ansi_c: false,
+            escape_only: false,
+            find_delimiter: false,
+            here_string: false,
+            heredoc: false,
+            control_style: ControlStyle::Escape,
+            newline_style: NewlineStyle::Control,
+            invalid: InvalidPolicy::Escape,
+            replacement: Utf8Char::new(core::char::REPLACEMENT_CHARACTER),
+            strip_bom: false,
+            #[cfg(feature = "nfc")]
+            normalize_nfc: false,
+            glob_safe: true,
+            protect_dashes: false,
+            quote_keywords: false,
+            protect_devices: false,
+            quote_numbers: true,
+            powershell_context: PowerShellContext::External,
+            symbolic_escapes: None,
+            lowercase_hex: None,
+            interactive: false,
+            avoid_aliases: false,
+            color: false,
+            max_width: None,
+            max_output_len: None,
+            east_asian: false,
+            delimiter: ',',
+            extra_specials: ByteSet(0),
+        }
+    }
+}
+
+/// A character stored as UTF-8, so that it can be borrowed as a `&str`.
+#[derive(Debug, Copy, Clone)]
+struct Utf8Char {
+    bytes: [u8; 4],
+    len: u8,
+}
+
+#[allow(dead_code)]
+impl Utf8Char {
+    fn new(ch: char) -> Self {
+        let mut bytes = [0; 4];
+        let len = ch.encode_utf8(&mut bytes).len() as u8;
+        Utf8Char { bytes, len }
+    }
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len as usize]).unwrap()
+    }
+
+    fn as_char(&self) -> char {
+        self.as_str().chars().next().unwrap()
+    }
+}
+
+impl Options {
+    /// Whether a character means the whole text has to be escaped.
+    #[cfg_attr(
+        not(any(
+            feature = "c",
+            feature = "json",
+            feature = "python",
+            feature = "toml",
+            feature = "yaml",
+            feature = "unix",
+            feature = "windows",
+            feature = "makefile",
+            feature = "native"
+        )),
+        allow(dead_code)
+    )]
+    fn requires_escape(&self, ch: char) -> bool {
+        requires_escape(ch)
+            || (self.ascii_only && !ch.is_ascii())
+            || self.allowed.map_or(false, |allowed| !allowed(ch))
+            || (self.escape_invisible && is_invisible(ch))
+    }
+
+    /// Whether a character should be escaped if we're escaping anyway.
+    #[cfg_attr(
+        not(any(
+            feature = "c",
+            feature = "json",
+            feature = "python",
+            feature = "toml",
+            feature = "yaml",
+            feature = "unix",
+            feature = "windows",
+            feature = "makefile",
+            feature = "native"
+        )),
+        allow(dead_code)
+    )]
+    fn escapes(&self, ch: char) -> bool {
+        self.requires_escape(ch) || (self.bidi != BidiPolicy::Allow && is_bidi(ch))
+    }
+
+    /// Write quotes or an escape, in color if that's enabled.
+    ///
+    /// This resets only the foreground color afterwards, so that it doesn't
+    /// interfere with any styling around the text.
+    fn write_syntax(
+        &self,
+        f: &mut Formatter<'_>,
+        write: impl FnOnce(&mut Formatter<'_>) -> fmt::Result,
+    ) -> fmt::Result {
+        if self.color {
+            f.write_str("\x1B[36m")?;
+            write(f)?;
+            f.write_str("\x1B[39m")
+        } else {
+            write(f)
+        }
+    }
+
+    /// Write the syntax that opens a quoted string, like `'` or `$'`, with
+    /// `quote` as its delimiter.
+    ///
+    /// For Quoted::rendering() only the delimiter itself is written, and
+    /// then we fail so that the rest of the text doesn't get formatted.
+    fn write_opening(
+        &self,
+        f: &mut Formatter<'_>,
+        quote: char,
+        write: impl FnOnce(&mut Formatter<'_>) -> fmt::Result,
+    ) -> fmt::Result {
+        if self.find_delimiter {
+            f.write_str(quote.encode_utf8(&mut [0; 4]))?;
+            return Err(fmt::Error);
+        }
+        self.write_syntax(f, write)
+    }
+
+    /// Options for formatting text just to measure it.
+    #[cfg_attr(not(any(feature = "unix", feature = "windows")), allow(dead_code))]
+    fn plain(&self) -> Options {
+        Options {
+            color: false,
+            find_delimiter: false,
+            ..*self
+        }
+    }
+
+    /// Write a number for an escape code, zero-padded to `width` digits.
+    #[cfg_attr(
+        not(any(
+            feature = "c",
+            feature = "json",
+            feature = "python",
+            feature = "toml",
+            feature = "yaml",
+            feature = "unix",
+            feature = "windows",
+            feature = "makefile",
+            feature = "native"
+        )),
+        allow(dead_code)
+    )]
+    fn write_hex(&self, f: &mut Formatter<'_>, value: u32, width: usize) -> fmt::Result {
+        if self.lowercase_hex == Some(true) {
+            write!(f, "{:01$x}", value, width)
+        } else {
+            write!(f, "{:01$X}", value, width)
+        }
+    }
+
+    /// Whether the bidi control codes in a text are a reason to escape it.
+    #[cfg(any(
+        feature = "unix",
+        feature = "windows",
+        feature = "native",
+        feature = "makefile"
+    ))]
+    fn escapes_bidi(&self, text: impl IntoIterator<Item = char>) -> bool {
+        self.bidi == BidiPolicy::Escape && is_suspicious_bidi(text)
+    }
+
+    /// Whether the bidi control codes in a text are a reason to wrap it in
+    /// isolate marks.
+    #[cfg(any(
+        feature = "unix",
+        feature = "windows",
+        feature = "native",
+        feature = "makefile"
+    ))]
+    fn isolates_bidi(&self, text: impl IntoIterator<Item = char>) -> bool {
+        self.bidi == BidiPolicy::Isolate && is_suspicious_bidi(text)
+    }
+
+    /// Write text between LEFT-TO-RIGHT ISOLATE and POP DIRECTIONAL ISOLATE,
+    /// leaving the bidi control codes inside as they are.
+    ///
+    /// Unicode closes everything that's still open inside an isolate at its
+    /// end, so they can't leak out.
+    #[cfg(any(
+        feature = "unix",
+        feature = "windows",
+        feature = "native",
+        feature = "makefile"
+    ))]
+    fn write_isolated(
+        &self,
+        f: &mut Formatter<'_>,
+        write: impl FnOnce(&mut Formatter<'_>, &Options) -> fmt::Result,
+    ) -> fmt::Result {
+        let opts = Options {
+            bidi: BidiPolicy::Allow,
+            ..*self
+        };
+        f.write_str("\u{2066}")?;
+        write(f, &opts)?;
+        f.write_str("\u{2069}")
+    }
+}
+
+/// How to handle control codes for bidirectional text, for use with
+/// [`Quoted::bidi`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BidiPolicy {
+    /// Escape the control codes if they're not properly terminated, so that
+    /// they can't affect the surrounding text.
+    Escape,
+    /// Leave the control codes as they are.
+    ///
+    /// This is only safe if you trust the text, or if nothing is printed
+    /// after it on the same line.
+    Allow,
+    /// Leave the control codes as they are, but if they're not properly
+    /// terminated, wrap the text in isolate marks (U+2066 and U+2069) so
+    /// that they can't affect the surrounding text.
+    ///
+    /// This keeps right-to-left text readable. But it's only meant for
+    /// display: the marks become part of the text if it's pasted into a
+    /// shell. It only applies to the Unix and Windows styles, the other
+    /// styles escape the control codes as with [`BidiPolicy::Escape`].
+    Isolate,
+}
+
+/// The syntax for escaping control codes in the Unix style, for use with
+/// [`Quoted::escape_dialect`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EscapeDialect {
+    /// `$'foo\nbar'`, as understood by bash, zsh, ksh and busybox sh.
+    AnsiC,
+    /// `"foo\nbar"`, as in C and many other languages.
+    ///
+    /// Shells do *not* process these escapes inside double quotes, so this
+    /// is not suitable for pasting into a shell.
+    DoubleQuoteBackslash,
+}
+
+/// Where text in the Windows style is going to be used, for use with
+/// [`Quoted::powershell_context`].
+///
+/// PowerShell treats some characters differently depending on whether it
+/// passes them to an external program or to a cmdlet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PowerShellContext {
+    /// Arguments to external programs. A `~` at the start is expanded to
+    /// the home directory unless it's quoted, so it gets quotes.
+    External,
+    /// Arguments to cmdlets like `Get-ChildItem`. These expand a `~` at the
+    /// start whether it's quoted or not, so it's left alone.
+    Cmdlet,
+}
+
+/// How to show ASCII control codes in the Unix style, for use with
+/// [`Quoted::control_style`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ControlStyle {
+    /// Escape them, as chosen by [`Quoted::escape_dialect`].
+    Escape,
+    /// Use caret notation, like `cat -v`: `^I` for a tab, `^@` for a null
+    /// byte, `^?` for DEL.
+    ///
+    /// Bytes that aren't valid UTF-8 use meta notation: `M-` followed by
+    /// the byte without its high bit, e.g. `M-A` for 0xC1 and `M-^?` for
+    /// 0xFF.
+    ///
+    /// This is meant for humans to read, the result can't be pasted into
+    /// a shell. The control codes don't cause quoting by themselves.
+    Caret,
+    /// Leave them out. The rest of the text is quoted as if they had never
+    /// been there.
+    ///
+    /// Like [`ControlStyle::Caret`] this is only meant for humans to read.
+    /// Unlike it, it also works in the PowerShell style.
+    Strip,
+    /// Replace each run of them by a single `?`. The rest of the text is
+    /// quoted as if it had been written that way, so the `?` may cause
+    /// quoting as a glob character.
+    ///
+    /// Like [`ControlStyle::Caret`] this is only meant for humans to read.
+    /// Unlike it, it also works in the PowerShell style.
+    Replace,
+}
+
+/// What to do with line breaks in the Unix and PowerShell styles, for use
+/// with [`Quoted::newline_style`].
+///
+/// This happens before [`Quoted::control_style`] is applied, so a line
+/// break that's left in is still escaped, shown in caret notation or left
+/// out like any other control code.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NewlineStyle {
+    /// Leave them as they are.
+    Control,
+    /// Turn `\r\n` into a single `\n`. A lone `\r` or `\n` is left as it is.
+    JoinCrlf,
+    /// Turn `\r\n` and a lone `\r` into `\n`, so that every line break
+    /// looks the same.
+    Normalize,
+    /// Leave them out, even if other control codes are escaped. The rest of
+    /// the text is quoted as if they had never been there.
+    ///
+    /// This is only meant for humans to read.
+    Strip,
+}
+
+/// What to do with bytes that aren't valid UTF-8 and code units that aren't
+/// valid UTF-16, for use with [`Quoted::invalid`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InvalidPolicy {
+    /// Escape them, so that no information is lost.
+    Escape,
+    /// Replace each of them with U+FFFD REPLACEMENT CHARACTER (`�`), or
+    /// the character chosen with [`Quoted::replacement_char`].
+    Replace,
+    /// Leave them out.
+    Strip,
+}
+
+/// A way of quoting, for use with [`Quoted::quote_preference`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QuotePreference {
+    /// Single quotes (`'`), with any single quotes inside escaped.
+    Single,
+    /// Double quotes (`"`), with any characters that are special inside
+    /// them escaped.
+    Double,
+    /// The `$'...'` syntax in the Unix style, which can hold anything.
+    ///
+    /// PowerShell has no equivalent, so in the Windows style this means
+    /// double quotes with backtick escapes, like for control codes.
+    AnsiC,
+}
+
+/// Up to three different [`QuotePreference`]s, in order.
+#[derive(Debug, Copy, Clone)]
+#[cfg_attr(
+    not(any(
+        feature = "unix",
+        feature = "windows",
+        feature = "makefile",
+        feature = "native"
+    )),
+    allow(dead_code)
+)]
+struct QuoteOrder {
+    styles: [QuotePreference; 3],
+    len: usize,
+}
+
+#[cfg_attr(
+    not(any(
+        feature = "unix",
+        feature = "windows",
+        feature = "makefile",
+        feature = "native"
+    )),
+    allow(dead_code)
+)]
+impl QuoteOrder {
+    fn iter(&self) -> core::slice::Iter<'_, QuotePreference> {
+        self.styles[..self.len].iter()
+    }
+
+    /// The one to use if none of them work without escapes.
+    fn first(&self) -> QuotePreference {
+        self.styles[0]
+    }
+}
+
+/// A kind of quote, for use with [`Quoted::prefer`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QuoteChar {
+    /// Single quotes (`'`).
+    Single,
+    /// Double quotes (`"`).
+    Double,
+}
+
+/// Why text has to be quoted or escaped, as returned by [`Quoted::analyze`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Classification {
+    /// The text can be written as it is.
+    Safe,
+    /// The text has to be quoted.
+    ///
+    /// The fields say why. They're all `false` if the text is empty, or if
+    /// the style always uses quotes.
+    NeedsQuote {
+        /// The text contains whitespace.
+        whitespace: bool,
+        /// The text contains characters that are special to the shell.
+        special: bool,
+        /// The text starts with something that's special at the start, like
+        /// `~`, `#` or `-`.
+        leading: bool,
+    },
+    /// The text has to be escaped.
+    NeedsEscape {
+        /// The text contains control codes, or other characters that are
+        /// always escaped.
+        control: bool,
+        /// The text isn't valid unicode.
+        invalid_unicode: bool,
+        /// The text contains bidi control codes that aren't properly
+        /// terminated. See [`Quoted::bidi`].
+        bidi: bool,
+    },
+}
+
+/// How text gets written, as returned by [`Quoted::rendering`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Rendering {
+    /// The quote that the output starts with, or `None` if it's unquoted.
+    ///
+    /// Prefixes like the `$` in `$'...'` are skipped.
+    pub delimiter: Option<char>,
+    /// The output uses escape sequences.
+    pub escaped: bool,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -72,24 +652,406 @@ enum Kind<'a> {
     Unix(&'a str),
     #[cfg(feature = "unix")]
     UnixRaw(&'a [u8]),
+    #[cfg(feature = "unix")]
+    Printf(&'a [u8]),
     #[cfg(any(feature = "windows", all(feature = "native", windows)))]
     Windows(&'a str),
     #[cfg(feature = "windows")]
-    #[cfg(feature = "alloc")]
     WindowsRaw(&'a [u16]),
+    #[cfg(feature = "windows")]
+    WindowsUtf8(&'a [u8]),
+    #[cfg(feature = "windows")]
+    Wtf8(&'a [u8]),
     #[cfg(feature = "native")]
     #[cfg(feature = "std")]
     NativeRaw(&'a std::ffi::OsStr),
+    #[cfg(feature = "native")]
+    NativeChar(char),
+    #[cfg(feature = "c")]
+    C(&'a [u8]),
+    #[cfg(feature = "json")]
+    Json(&'a [u8]),
+    #[cfg(feature = "python")]
+    Python(&'a str),
+    #[cfg(feature = "xonsh")]
+    Xonsh(&'a str),
+    #[cfg(feature = "toml")]
+    Toml(&'a str),
+    #[cfg(feature = "yaml")]
+    Yaml(&'a str),
+    #[cfg(feature = "csv")]
+    Csv(&'a str),
+    #[cfg(feature = "makefile")]
+    Makefile(&'a str),
+    #[cfg(feature = "makefile")]
+    MakefileShell(&'a str),
+    #[cfg(feature = "verbatim")]
+    Verbatim(&'a str),
+}
+
+/// A constructor for a kind, like `Kind::Unix`.
+#[cfg(feature = "nfc")]
+type MakeKind = for<'b> fn(&'b str) -> Kind<'b>;
+
+impl<'a> Kind<'a> {
+    fn is_powershell(&self) -> bool {
+        match *self {
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(_) => true,
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(_) | Kind::WindowsUtf8(_) | Kind::Wtf8(_) => true,
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(_) => cfg!(windows),
+            #[cfg(feature = "native")]
+            Kind::NativeChar(_) => cfg!(windows),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// Check whether any character of the text matches. Invalid parts of
+    /// the text are skipped.
+    fn any_char(&self, mut pred: impl FnMut(char) -> bool) -> bool {
+        match *self {
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => {
+                #[cfg(unix)]
+                use std::os::unix::ffi::OsStrExt;
+                #[cfg(target_os = "wasi")]
+                use std::os::wasi::ffi::OsStrExt;
+                #[cfg(windows)]
+                use std::os::windows::ffi::OsStrExt;
+
+                #[cfg(windows)]
+                return decode_utf16(text.encode_wide())
+                    .any(|res| res.ok().map_or(false, &mut pred));
+                #[cfg(any(unix, target_os = "wasi"))]
+                return from_utf8_iter(text.as_bytes()).any(|chunk| {
+                    chunk
+                        .ok()
+                        .map_or(false, |chunk| chunk.chars().any(&mut pred))
+                });
+                #[cfg(not(any(windows, unix, target_os = "wasi")))]
+                return text.to_string_lossy().chars().any(&mut pred);
+            }
+            #[cfg(feature = "native")]
+            Kind::NativeChar(ch) => pred(ch),
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => text.chars().any(&mut pred),
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => text.chars().any(&mut pred),
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(units) => {
+                decode_utf16(units.iter().cloned()).any(|res| res.ok().map_or(false, &mut pred))
+            }
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) => {
+                use crate::windows::Text;
+
+                windows::Utf8Lossy(bytes, core::char::REPLACEMENT_CHARACTER)
+                    .chars()
+                    .any(&mut pred)
+            }
+            #[cfg(feature = "windows")]
+            Kind::Wtf8(bytes) => windows::decode_wtf8(bytes, core::char::REPLACEMENT_CHARACTER)
+                .any(|res| res.ok().map_or(false, &mut pred)),
+            #[cfg(feature = "python")]
+            Kind::Python(text) => text.chars().any(&mut pred),
+            #[cfg(feature = "xonsh")]
+            Kind::Xonsh(text) => text.chars().any(&mut pred),
+            #[cfg(feature = "toml")]
+            Kind::Toml(text) => text.chars().any(&mut pred),
+            #[cfg(feature = "yaml")]
+            Kind::Yaml(text) => text.chars().any(&mut pred),
+            #[cfg(feature = "csv")]
+            Kind::Csv(text) => text.chars().any(&mut pred),
+            #[cfg(feature = "makefile")]
+            Kind::Makefile(text) | Kind::MakefileShell(text) => text.chars().any(&mut pred),
+            #[cfg(feature = "verbatim")]
+            Kind::Verbatim(text) => text.chars().any(&mut pred),
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) | Kind::Printf(bytes) => from_utf8_iter(bytes).any(|chunk| {
+                chunk
+                    .ok()
+                    .map_or(false, |chunk| chunk.chars().any(&mut pred))
+            }),
+            #[cfg(feature = "c")]
+            Kind::C(bytes) => from_utf8_iter(bytes).any(|chunk| {
+                chunk
+                    .ok()
+                    .map_or(false, |chunk| chunk.chars().any(&mut pred))
+            }),
+            #[cfg(feature = "json")]
+            Kind::Json(bytes) => from_utf8_iter(bytes).any(|chunk| {
+                chunk
+                    .ok()
+                    .map_or(false, |chunk| chunk.chars().any(&mut pred))
+            }),
+        }
+    }
+
+    /// The text as bytes, if it's stored as UTF-8 or as raw bytes.
+    #[cfg(feature = "alloc")]
+    fn as_bytes(&self) -> Option<&'a [u8]> {
+        match *self {
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => {
+                #[cfg(unix)]
+                use std::os::unix::ffi::OsStrExt;
+                #[cfg(target_os = "wasi")]
+                use std::os::wasi::ffi::OsStrExt;
+
+                #[cfg(any(unix, target_os = "wasi"))]
+                return Some(text.as_bytes());
+                #[cfg(not(any(unix, target_os = "wasi")))]
+                return text.to_str().map(str::as_bytes);
+            }
+            #[cfg(feature = "native")]
+            Kind::NativeChar(_) => None,
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => Some(text.as_bytes()),
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) | Kind::Printf(bytes) => Some(bytes),
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => Some(text.as_bytes()),
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(_) => None,
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) | Kind::Wtf8(bytes) => Some(bytes),
+            #[cfg(feature = "c")]
+            Kind::C(bytes) => Some(bytes),
+            #[cfg(feature = "json")]
+            Kind::Json(bytes) => Some(bytes),
+            #[cfg(feature = "python")]
+            Kind::Python(text) => Some(text.as_bytes()),
+            #[cfg(feature = "xonsh")]
+            Kind::Xonsh(text) => Some(text.as_bytes()),
+            #[cfg(feature = "toml")]
+            Kind::Toml(text) => Some(text.as_bytes()),
+            #[cfg(feature = "yaml")]
+            Kind::Yaml(text) => Some(text.as_bytes()),
+            #[cfg(feature = "csv")]
+            Kind::Csv(text) => Some(text.as_bytes()),
+            #[cfg(feature = "makefile")]
+            Kind::Makefile(text) | Kind::MakefileShell(text) => Some(text.as_bytes()),
+            #[cfg(feature = "verbatim")]
+            Kind::Verbatim(text) => Some(text.as_bytes()),
+        }
+    }
+
+    /// The text normalized to NFC, with a way to make a kind of the same
+    /// style out of it. Text that's already normalized and invalid text are
+    /// left alone.
+    #[cfg(feature = "nfc")]
+    fn nfc(&self) -> Option<(alloc::string::String, MakeKind)> {
+        use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+        #[allow(unused)]
+        let mut buf = [0; 4];
+        let (text, kind): (&str, MakeKind) = match *self {
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => {
+                #[cfg(windows)]
+                let kind: MakeKind = |text| Kind::Windows(text);
+                #[cfg(not(windows))]
+                let kind: MakeKind = |text| Kind::Unix(text);
+                (text.to_str()?, kind)
+            }
+            #[cfg(feature = "native")]
+            Kind::NativeChar(ch) => {
+                #[cfg(windows)]
+                let kind: MakeKind = |text| Kind::Windows(text);
+                #[cfg(not(windows))]
+                let kind: MakeKind = |text| Kind::Unix(text);
+                (ch.encode_utf8(&mut buf), kind)
+            }
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => (text, |text| Kind::Unix(text)),
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) => (core::str::from_utf8(bytes).ok()?, |text| {
+                Kind::UnixRaw(text.as_bytes())
+            }),
+            #[cfg(feature = "unix")]
+            Kind::Printf(bytes) => (core::str::from_utf8(bytes).ok()?, |text| {
+                Kind::Printf(text.as_bytes())
+            }),
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => (text, |text| Kind::Windows(text)),
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(units) => {
+                let text: alloc::string::String = core::char::decode_utf16(units.iter().cloned())
+                    .collect::<Result<_, _>>()
+                    .ok()?;
+                return Some((text.nfc().collect(), |text| Kind::Windows(text)));
+            }
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) => {
+                let text = alloc::string::String::from_utf8_lossy(bytes);
+                return Some((text.nfc().collect(), |text| Kind::Windows(text)));
+            }
+            #[cfg(feature = "windows")]
+            Kind::Wtf8(bytes) => (core::str::from_utf8(bytes).ok()?, |text| {
+                Kind::Wtf8(text.as_bytes())
+            }),
+            #[cfg(feature = "c")]
+            Kind::C(bytes) => (core::str::from_utf8(bytes).ok()?, |text| {
+                Kind::C(text.as_bytes())
+            }),
+            #[cfg(feature = "json")]
+            Kind::Json(bytes) => (core::str::from_utf8(bytes).ok()?, |text| {
+                Kind::Json(text.as_bytes())
+            }),
+            #[cfg(feature = "python")]
+            Kind::Python(text) => (text, |text| Kind::Python(text)),
+            #[cfg(feature = "xonsh")]
+            Kind::Xonsh(text) => (text, |text| Kind::Xonsh(text)),
+            #[cfg(feature = "toml")]
+            Kind::Toml(text) => (text, |text| Kind::Toml(text)),
+            #[cfg(feature = "yaml")]
+            Kind::Yaml(text) => (text, |text| Kind::Yaml(text)),
+            #[cfg(feature = "csv")]
+            Kind::Csv(text) => (text, |text| Kind::Csv(text)),
+            #[cfg(feature = "makefile")]
+            Kind::Makefile(text) => (text, |text| Kind::Makefile(text)),
+            #[cfg(feature = "makefile")]
+            Kind::MakefileShell(text) => (text, |text| Kind::MakefileShell(text)),
+            #[cfg(feature = "verbatim")]
+            Kind::Verbatim(text) => (text, |text| Kind::Verbatim(text)),
+        };
+        if is_nfc_quick(text.chars()) == IsNormalized::Yes {
+            return None;
+        }
+        Some((text.nfc().collect(), kind))
+    }
+
+    /// The text without its leading byte order mark, if it has one.
+    fn strip_bom(&self) -> Option<Kind<'a>> {
+        #[allow(dead_code)]
+        fn strip_str(text: &str) -> Option<&str> {
+            if text.starts_with('\u{FEFF}') {
+                Some(&text['\u{FEFF}'.len_utf8()..])
+            } else {
+                None
+            }
+        }
+
+        #[allow(dead_code)]
+        fn strip_bytes(bytes: &[u8]) -> Option<&[u8]> {
+            if bytes.starts_with(b"\xEF\xBB\xBF") {
+                Some(&bytes[3..])
+            } else {
+                None
+            }
+        }
+
+        match *self {
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => {
+                #[cfg(unix)]
+                use std::os::unix::ffi::OsStrExt;
+                #[cfg(target_os = "wasi")]
+                use std::os::wasi::ffi::OsStrExt;
+
+                #[cfg(any(unix, target_os = "wasi"))]
+                return strip_bytes(text.as_bytes())
+                    .map(|bytes| Kind::NativeRaw(std::ffi::OsStr::from_bytes(bytes)));
+                // If the text isn't valid we can't borrow part of it, so
+                // windows::write_raw() takes care of that.
+                #[cfg(windows)]
+                return text.to_str().and_then(strip_str).map(Kind::Windows);
+                #[cfg(not(any(windows, unix, target_os = "wasi")))]
+                return text.to_str().and_then(strip_str).map(Kind::Unix);
+            }
+            #[cfg(feature = "native")]
+            Kind::NativeChar('\u{FEFF}') => {
+                #[cfg(windows)]
+                return Some(Kind::Windows(""));
+                #[cfg(not(windows))]
+                return Some(Kind::Unix(""));
+            }
+            #[cfg(feature = "native")]
+            Kind::NativeChar(_) => None,
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => strip_str(text).map(Kind::Unix),
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) => strip_bytes(bytes).map(Kind::UnixRaw),
+            #[cfg(feature = "unix")]
+            Kind::Printf(bytes) => strip_bytes(bytes).map(Kind::Printf),
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => strip_str(text).map(Kind::Windows),
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(units) => {
+                if units.first() == Some(&0xFEFF) {
+                    Some(Kind::WindowsRaw(&units[1..]))
+                } else {
+                    None
+                }
+            }
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) => strip_bytes(bytes).map(Kind::WindowsUtf8),
+            #[cfg(feature = "windows")]
+            Kind::Wtf8(bytes) => strip_bytes(bytes).map(Kind::Wtf8),
+            #[cfg(feature = "c")]
+            Kind::C(bytes) => strip_bytes(bytes).map(Kind::C),
+            #[cfg(feature = "json")]
+            Kind::Json(bytes) => strip_bytes(bytes).map(Kind::Json),
+            #[cfg(feature = "python")]
+            Kind::Python(text) => strip_str(text).map(Kind::Python),
+            #[cfg(feature = "xonsh")]
+            Kind::Xonsh(text) => strip_str(text).map(Kind::Xonsh),
+            #[cfg(feature = "toml")]
+            Kind::Toml(text) => strip_str(text).map(Kind::Toml),
+            #[cfg(feature = "yaml")]
+            Kind::Yaml(text) => strip_str(text).map(Kind::Yaml),
+            #[cfg(feature = "csv")]
+            Kind::Csv(text) => strip_str(text).map(Kind::Csv),
+            #[cfg(feature = "makefile")]
+            Kind::Makefile(text) => strip_str(text).map(Kind::Makefile),
+            #[cfg(feature = "makefile")]
+            Kind::MakefileShell(text) => strip_str(text).map(Kind::MakefileShell),
+            #[cfg(feature = "verbatim")]
+            Kind::Verbatim(text) => strip_str(text).map(Kind::Verbatim),
+        }
+    }
 }
 
 impl<'a> Quoted<'a> {
     fn new(source: Kind<'a>) -> Self {
         Quoted {
             source,
-            force_quote: true,
+            opts: Options::default(),
         }
     }
 
+    /// Quote anything that implements [`Quotable`], with its default style.
+    ///
+    /// This is the same as calling [`Quotable::quote`], but generic code can
+    /// use it without naming the type. A `Quoted` is accepted as well, and
+    /// passed through unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "native")] {
+    /// use std::path::Path;
+    /// use os_display::{Quotable, Quoted};
+    ///
+    /// fn show<'a>(text: impl Into<Quoted<'a>>) -> String {
+    ///     Quoted::of(text).to_string()
+    /// }
+    ///
+    /// assert_eq!(show("foo"), "foo".quote().to_string());
+    /// assert_eq!(show(Path::new("foo")), "foo".quote().to_string());
+    /// # }
+    /// ```
+    pub fn of(text: impl Into<Quoted<'a>>) -> Self {
+        text.into()
+    }
+
     /// Quote a string with the default style for the platform.
     ///
     /// On Windows this is PowerShell syntax, on all other platforms this is
@@ -123,6 +1085,10 @@ impl<'a> Quoted<'a> {
 
     /// Quote possibly invalid UTF-8 using bash/ksh syntax.
     ///
+    /// This is also the right choice for raw bytes on WASI, where paths are
+    /// arbitrary bytes just like on Unix. `Quoted::native_raw` uses the
+    /// same rules there.
+    ///
     /// # Optional
     /// This requires the optional `unix` feature.
     #[cfg(feature = "unix")]
@@ -130,6 +1096,40 @@ impl<'a> Quoted<'a> {
         Quoted::new(Kind::UnixRaw(bytes))
     }
 
+    /// Escape a string as an argument for `printf '%b'`.
+    ///
+    /// Control codes are written as octal escapes like `\0011`, which POSIX
+    /// `printf` understands, instead of bash's `$'...'`. So this works in
+    /// any `sh`. Backslashes are escaped as well, and the result is quoted
+    /// for the shell.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let arg = Quoted::printf("foo\tbar");
+    /// assert_eq!(format!("printf '%b' {}", arg), r"printf '%b' 'foo\0011bar'");
+    /// # }
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the optional `unix` feature.
+    #[cfg(feature = "unix")]
+    pub fn printf(text: &'a str) -> Self {
+        Quoted::new(Kind::Printf(text.as_bytes()))
+    }
+
+    /// Escape a possibly invalid UTF-8 string as an argument for
+    /// `printf '%b'`. Invalid bytes are written as octal escapes too.
+    ///
+    /// # Optional
+    /// This requires the optional `unix` feature.
+    #[cfg(feature = "unix")]
+    pub fn printf_raw(bytes: &'a [u8]) -> Self {
+        Quoted::new(Kind::Printf(bytes))
+    }
+
     /// Quote a string using PowerShell syntax.
     ///
     /// # Optional
@@ -142,394 +1142,5536 @@ impl<'a> Quoted<'a> {
     /// Quote possibly invalid UTF-16 using PowerShell syntax.
     ///
     /// # Optional
-    /// This requires the optional `windows` feature and the (default) `alloc` feature.
+    /// This requires the optional `windows` feature.
     #[cfg(feature = "windows")]
-    #[cfg(feature = "alloc")]
     pub fn windows_raw(units: &'a [u16]) -> Self {
         Quoted::new(Kind::WindowsRaw(units))
     }
 
-    /// Toggle forced quoting. If `true`, quotes are added even if no special
-    /// characters are present.
+    /// Quote possibly invalid UTF-8 using PowerShell syntax.
     ///
-    /// Defaults to `true`.
-    pub fn force(mut self, force: bool) -> Self {
-        self.force_quote = force;
-        self
+    /// Unlike [`Quoted::windows_raw`] this takes bytes, like the contents of
+    /// a file. PowerShell strings can't hold stray bytes, so invalid
+    /// sequences are replaced by U+FFFD REPLACEMENT CHARACTER, as with
+    /// `String::from_utf8_lossy`.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "windows")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::windows_raw_utf8(b"my file").to_string(), "'my file'");
+    /// assert_eq!(Quoted::windows_raw_utf8(b"foo\xFF").to_string(), "'foo\u{FFFD}'");
+    /// # }
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the optional `windows` feature.
+    #[cfg(feature = "windows")]
+    pub fn windows_raw_utf8(bytes: &'a [u8]) -> Self {
+        Quoted::new(Kind::WindowsUtf8(bytes))
     }
-}
 
-impl<'a> Display for Quoted<'a> {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        match self.source {
-            #[cfg(feature = "native")]
-            #[cfg(feature = "std")]
-            Kind::NativeRaw(text) => {
-                #[cfg(unix)]
-                use std::os::unix::ffi::OsStrExt;
-                #[cfg(target_os = "wasi")]
-                use std::os::wasi::ffi::OsStrExt;
+    /// Quote WTF-8 using PowerShell syntax.
+    ///
+    /// WTF-8 is how Rust stores an `OsStr` on Windows: UTF-8 that can also
+    /// hold unpaired surrogates. Those are handled like invalid code units
+    /// in [`Quoted::windows_raw`], without converting to UTF-16 first. Bytes
+    /// that aren't valid WTF-8 are replaced by U+FFFD REPLACEMENT CHARACTER.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "windows")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::wtf8(b"my file").to_string(), "'my file'");
+    /// assert_eq!(Quoted::wtf8(b"x\xED\xA0\x80").to_string(), "\"x`u{D800}\"");
+    /// # }
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the optional `windows` feature.
+    #[cfg(feature = "windows")]
+    pub fn wtf8(bytes: &'a [u8]) -> Self {
+        Quoted::new(Kind::Wtf8(bytes))
+    }
+
+    /// Quote a string as a C string literal.
+    ///
+    /// Quotes are always added.
+    ///
+    /// # Optional
+    /// This requires the optional `c` feature.
+    #[cfg(feature = "c")]
+    pub fn c(text: &'a str) -> Self {
+        Quoted::new(Kind::C(text.as_bytes()))
+    }
+
+    /// Quote possibly invalid UTF-8 as a C string literal.
+    ///
+    /// # Optional
+    /// This requires the optional `c` feature.
+    #[cfg(feature = "c")]
+    pub fn c_raw(bytes: &'a [u8]) -> Self {
+        Quoted::new(Kind::C(bytes))
+    }
+
+    /// Quote a string as a JSON string.
+    ///
+    /// Quotes are always added.
+    ///
+    /// # Optional
+    /// This requires the optional `json` feature.
+    #[cfg(feature = "json")]
+    pub fn json(text: &'a str) -> Self {
+        Quoted::new(Kind::Json(text.as_bytes()))
+    }
+
+    /// Quote possibly invalid UTF-8 as a JSON string.
+    ///
+    /// JSON can't represent invalid UTF-8 directly, so each invalid byte
+    /// is escaped as a lone surrogate, like Python's `surrogateescape`:
+    /// `b"\xFF"` becomes `"\uDCFF"`.
+    ///
+    /// # Optional
+    /// This requires the optional `json` feature.
+    #[cfg(feature = "json")]
+    pub fn json_raw(bytes: &'a [u8]) -> Self {
+        Quoted::new(Kind::Json(bytes))
+    }
+
+    /// Quote a string as a Python string literal, the way `repr()` does.
+    ///
+    /// Quotes are always added. Combine with [`Quoted::ascii_only`] to get
+    /// something like `ascii()`.
+    ///
+    /// # Optional
+    /// This requires the optional `python` feature.
+    #[cfg(feature = "python")]
+    pub fn python(text: &'a str) -> Self {
+        Quoted::new(Kind::Python(text))
+    }
+
+    /// Quote a string as an argument for a command in xonsh, a shell that
+    /// mixes in Python.
+    ///
+    /// If [`Quoted::force`] is turned off, text is left bare if it only has
+    /// letters, digits and characters like `-`, `.` and `/`. Otherwise it
+    /// becomes a Python string literal, between single quotes if it
+    /// contains a `$` so that environment variables aren't expanded.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "xonsh")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::xonsh("$HOME").force(false).to_string(), "'$HOME'");
+    /// assert_eq!(Quoted::xonsh("a.txt").force(false).to_string(), "a.txt");
+    /// # }
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the optional `xonsh` feature.
+    #[cfg(feature = "xonsh")]
+    pub fn xonsh(text: &'a str) -> Self {
+        Quoted::new(Kind::Xonsh(text))
+    }
+
+    /// Quote a string as a TOML string.
+    ///
+    /// Quotes are always added. If the text contains `"` or `\` but nothing
+    /// that needs escaping, it's written as a literal string between single
+    /// quotes, like `'C:\Users'`. Otherwise it's a basic string between
+    /// double quotes. [`Quoted::prefer`] can change that choice.
+    ///
+    /// # Optional
+    /// This requires the optional `toml` feature.
+    #[cfg(feature = "toml")]
+    pub fn toml(text: &'a str) -> Self {
+        Quoted::new(Kind::Toml(text))
+    }
+
+    /// Quote a string as a YAML scalar.
+    ///
+    /// If [`Quoted::force`] is turned off, text is left bare if it can't be
+    /// mistaken for anything else. That rules out text like `true`, `123`
+    /// and `- x`. Otherwise it's put between single quotes, or between
+    /// double quotes if it needs escapes.
+    ///
+    /// # Optional
+    /// This requires the optional `yaml` feature.
+    #[cfg(feature = "yaml")]
+    pub fn yaml(text: &'a str) -> Self {
+        Quoted::new(Kind::Yaml(text))
+    }
+
+    /// Quote a string as a CSV field.
+    ///
+    /// Unlike the other styles, quotes are only added if the field contains
+    /// the delimiter (see [`Quoted::delimiter`]), a double quote or a line
+    /// break, or if it's empty. Use [`Quoted::force`] to always add them.
+    ///
+    /// CSV has no escapes, so control codes are written as they are. That
+    /// includes the escape character, so don't send the output of this
+    /// style to a terminal if the text can't be trusted.
+    ///
+    /// # Optional
+    /// This requires the optional `csv` feature.
+    #[cfg(feature = "csv")]
+    pub fn csv(text: &'a str) -> Self {
+        // Most CSV writers leave fields bare when they can.
+        Quoted::new(Kind::Csv(text)).force(false)
+    }
+
+    /// Escape a string for a Makefile, e.g. for a variable assignment.
+    ///
+    /// `$` becomes `$$` and `#` becomes `\#`. Other characters are left
+    /// alone, so if the value is used in a recipe the shell will still
+    /// interpret it. Quotes are never added, unless the text contains
+    /// control codes, which Make can't represent.
+    ///
+    /// # Optional
+    /// This requires the optional `makefile` feature.
+    #[cfg(feature = "makefile")]
+    pub fn makefile(text: &'a str) -> Self {
+        Quoted::new(Kind::Makefile(text))
+    }
+
+    /// Quote a string using bash/ksh syntax, then escape it for a Makefile.
+    ///
+    /// This is appropriate for variables that are used in recipes.
+    ///
+    /// # Optional
+    /// This requires the optional `makefile` feature.
+    #[cfg(feature = "makefile")]
+    pub fn makefile_shell(text: &'a str) -> Self {
+        Quoted::new(Kind::MakefileShell(text))
+    }
+
+    /// Show text between `«` and `»` with nothing escaped, for debugging.
+    ///
+    /// Only a `»` in the text is escaped, by doubling it. Control codes are
+    /// written as they are, so you can see what they do to the terminal.
+    /// This makes it unsafe for untrusted text, and it can't be pasted
+    /// anywhere.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "verbatim")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::verbatim("a\tb").to_string(), "«a\tb»");
+    /// assert_eq!(Quoted::verbatim("»x«").to_string(), "«»»x«»");
+    /// # }
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the optional `verbatim` feature.
+    #[cfg(feature = "verbatim")]
+    pub fn verbatim(text: &'a str) -> Self {
+        Quoted::new(Kind::Verbatim(text))
+    }
+
+    /// Toggle forced quoting. If `true`, quotes are added even if no special
+    /// characters are present.
+    ///
+    /// Defaults to `true`.
+    ///
+    /// Formatting with the alternate flag (`{:#}`) also turns it off, so
+    /// `format!("{:#}", text.quote())` is the same as
+    /// `text.maybe_quote().to_string()`.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(format!("{}", Quoted::unix("foo")), "'foo'");
+    /// assert_eq!(format!("{:#}", Quoted::unix("foo")), "foo");
+    /// # }
+    /// ```
+    pub fn force(mut self, force: bool) -> Self {
+        self.opts.force_quote = force;
+        self
+    }
+
+    /// Write a placeholder instead of quotes if the text is empty.
+    ///
+    /// Empty text is always quoted, like `''`, so that it doesn't disappear.
+    /// That's right for a command line but can look odd in a message. With
+    /// `Some` the placeholder is written as is, without quoting, so
+    /// `Some("")` writes nothing at all. `None` goes back to the default.
+    ///
+    /// Text that isn't empty is not affected.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::unix("").to_string(), "''");
+    /// assert_eq!(Quoted::unix("").empty_as(Some("(empty)")).to_string(), "(empty)");
+    /// # }
+    /// ```
+    pub fn empty_as(mut self, placeholder: Option<&'static str>) -> Self {
+        self.opts.empty_as = placeholder;
+        self
+    }
+
+    /// Use a particular kind of quote whenever quotes are added, even if the
+    /// other kind would be simpler.
+    ///
+    /// By default single quotes are used when possible, and double quotes if
+    /// that avoids escapes.
+    ///
+    /// Text that contains control codes is escaped the same way regardless.
+    pub fn prefer(mut self, quote: QuoteChar) -> Self {
+        self.opts.prefer = Some(quote);
+        self
+    }
+
+    /// Try ways of quoting in order, for the Unix and Windows styles.
+    ///
+    /// The first one that works without escaping anything is used. If none
+    /// of them do, the first one is used with escapes. [`QuotePreference::AnsiC`]
+    /// can hold anything, so nothing after it is ever used.
+    ///
+    /// Later repeats are ignored, and an empty list restores the default.
+    /// This takes precedence over [`Quoted::prefer`]. Text that contains
+    /// control codes is escaped the same way regardless.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::{QuotePreference, Quoted};
+    ///
+    /// let prefs = [QuotePreference::Double, QuotePreference::Single];
+    /// assert_eq!(Quoted::unix("foo").quote_preference(&prefs).to_string(), "\"foo\"");
+    /// assert_eq!(Quoted::unix("$foo").quote_preference(&prefs).to_string(), "'$foo'");
+    /// assert_eq!(Quoted::unix("it's $x").quote_preference(&prefs).to_string(), r#""it's \$x""#);
+    /// # }
+    /// ```
+    pub fn quote_preference(mut self, order: &[QuotePreference]) -> Self {
+        let mut styles = [QuotePreference::Single; 3];
+        let mut len = 0;
+        for &style in order {
+            if !styles[..len].contains(&style) {
+                styles[len] = style;
+                len += 1;
+            }
+        }
+        self.opts.quote_order = if len == 0 {
+            None
+        } else {
+            Some(QuoteOrder { styles, len })
+        };
+        self
+    }
+
+    /// Try every way of quoting the text and pick the shortest, for the
+    /// Unix and Windows styles.
+    ///
+    /// By default the kind of quotes is chosen by a quick look at the text,
+    /// which usually gives the shortest result but not always. This is
+    /// slower because it writes the text up to three times.
+    ///
+    /// [`Quoted::prefer`] decides between results of the same length.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let quoted = Quoted::unix(r#"it's "x""#);
+    /// assert_eq!(quoted.to_string(), r#"'it'\''s "x"'"#);
+    /// assert_eq!(quoted.minimize(true).to_string(), r#""it's \"x\"""#);
+    /// # }
+    /// ```
+    pub fn minimize(mut self, minimize: bool) -> Self {
+        self.opts.minimize = minimize;
+        self
+    }
+
+    /// Choose what to do with control codes for bidirectional text.
+    ///
+    /// By default they're escaped if they're not properly terminated, because
+    /// they could otherwise be used to rearrange the surrounding text.
+    /// See [`BidiPolicy`].
+    pub fn bidi(mut self, policy: BidiPolicy) -> Self {
+        self.opts.bidi = policy;
+        self
+    }
+
+    /// Toggle escaping of all non-ASCII characters, for terminals and logs
+    /// that can't handle them.
+    ///
+    /// Defaults to `false`.
+    pub fn ascii_only(mut self, ascii_only: bool) -> Self {
+        self.opts.ascii_only = ascii_only;
+        self
+    }
+
+    /// Escape every character that `allowed` returns `false` for, for
+    /// terminals that can only show a known set of characters.
+    ///
+    /// This is a more general version of [`Quoted::ascii_only`]. Characters
+    /// that are escaped anyway, like control codes, stay escaped even if
+    /// they're allowed. `None` turns it off again, which is the default.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let quoted = Quoted::unix("café").allowed(Some(|ch| ch.is_ascii_graphic()));
+    /// assert_eq!(quoted.to_string(), r"$'caf\xC3\xA9'");
+    /// # }
+    /// ```
+    pub fn allowed(mut self, allowed: Option<fn(char) -> bool>) -> Self {
+        self.opts.allowed = allowed;
+        self
+    }
+
+    /// Toggle escaping of invisible characters, like U+200D ZERO WIDTH
+    /// JOINER and U+FE0F VARIATION SELECTOR-16.
+    ///
+    /// These don't show up by themselves, so they can make two different
+    /// names look the same. The characters that get escaped are the ones
+    /// that Unicode calls `Default_Ignorable_Code_Point`, except for the
+    /// bidirectional control codes, which are governed by [`Quoted::bidi`].
+    ///
+    /// Emoji that are built from several characters are taken apart.
+    ///
+    /// Defaults to `false`.
+    pub fn escape_invisible(mut self, escape_invisible: bool) -> Self {
+        self.opts.escape_invisible = escape_invisible;
+        self
+    }
+
+    /// Choose the syntax for escaping control codes in the Unix style.
+    ///
+    /// Defaults to [`EscapeDialect::AnsiC`]. This only affects text that
+    /// needs escaping, other text is quoted as usual.
+    pub fn escape_dialect(mut self, dialect: EscapeDialect) -> Self {
+        self.opts.escape_dialect = dialect;
+        self
+    }
+
+    /// Always use the `$'...'` syntax when quoting in the Unix style, even
+    /// if plain single quotes would do.
+    ///
+    /// This way there's only one syntax to parse. Text that doesn't need
+    /// quotes is still left bare unless [`Quoted::force`] is on. With
+    /// [`EscapeDialect::DoubleQuoteBackslash`] that dialect is used instead.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::unix("foo").always_ansi_c(true).to_string(), "$'foo'");
+    /// assert_eq!(Quoted::unix("it's").always_ansi_c(true).to_string(), r"$'it\'s'");
+    /// # }
+    /// ```
+    pub fn always_ansi_c(mut self, always: bool) -> Self {
+        self.opts.always_ansi_c = always;
+        self
+    }
+
+    /// Use a PowerShell here-string for text with line breaks in the
+    /// Windows style, instead of escaping every line break.
+    ///
+    /// A here-string spans several lines, so the quoted text does too. It's
+    /// only used if the text fits: it can't contain any other control codes,
+    /// and no line can look like the end of the here-string. Otherwise the
+    /// text is escaped as usual.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "windows")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::windows("a\nb").to_string(), r#""a`nb""#);
+    /// assert_eq!(Quoted::windows("a\nb").here_string(true).to_string(), "@'\na\nb\n'@");
+    /// # }
+    /// ```
+    pub fn here_string(mut self, here_string: bool) -> Self {
+        self.opts.here_string = here_string;
+        self
+    }
+
+    /// Use a here-document for text with line breaks in the Unix style,
+    /// instead of escaping every line break.
+    ///
+    /// This turns the text into a redirection, like `<<'EOF'`, followed by
+    /// the lines of the text and the delimiter on a line of its own. So it's
+    /// fed to a command's standard input rather than passed as an argument,
+    /// and nothing else can follow it on the last line. A here-document
+    /// always ends with a line break, so one is added if the text doesn't
+    /// have it.
+    ///
+    /// The delimiter is `EOF`, unless that's a line of the text, in which
+    /// case a number is added. A here-document is only used if the text
+    /// doesn't need any escapes other than line breaks. Otherwise the text is
+    /// quoted as usual.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::unix("a\nb").to_string(), r"$'a\nb'");
+    /// assert_eq!(Quoted::unix("a\nb").heredoc(true).to_string(), "<<'EOF'\na\nb\nEOF");
+    /// assert_eq!(
+    ///     Quoted::unix("EOF\n").heredoc(true).to_string(),
+    ///     "<<'EOF1'\nEOF\nEOF1"
+    /// );
+    /// # }
+    /// ```
+    pub fn heredoc(mut self, heredoc: bool) -> Self {
+        self.opts.heredoc = heredoc;
+        self
+    }
+
+    /// Choose how to show ASCII control codes in the Unix style, and
+    /// whether to leave them out in the PowerShell style.
+    ///
+    /// Defaults to [`ControlStyle::Escape`]. Other characters that need
+    /// escaping, like unicode control codes, are always escaped.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::{ControlStyle, Quoted};
+    ///
+    /// let quoted = Quoted::unix("foo\tbar").control_style(ControlStyle::Caret);
+    /// assert_eq!(quoted.to_string(), "'foo^Ibar'");
+    /// # }
+    /// ```
+    pub fn control_style(mut self, style: ControlStyle) -> Self {
+        self.opts.control_style = style;
+        self
+    }
+
+    /// Choose what to do with line breaks (`\r\n`, `\r` and `\n`) in the
+    /// Unix and PowerShell styles, before [`Quoted::control_style`] decides
+    /// how to show them.
+    ///
+    /// Defaults to [`NewlineStyle::Control`], which treats them like any
+    /// other control code.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::{NewlineStyle, Quoted};
+    ///
+    /// let quoted = Quoted::unix("a\r\nb");
+    /// assert_eq!(quoted.to_string(), r"$'a\r\nb'");
+    /// let joined = quoted.newline_style(NewlineStyle::JoinCrlf);
+    /// assert_eq!(joined.to_string(), r"$'a\nb'");
+    /// let stripped = quoted.newline_style(NewlineStyle::Strip);
+    /// assert_eq!(stripped.to_string(), "'ab'");
+    /// # }
+    /// ```
+    pub fn newline_style(mut self, style: NewlineStyle) -> Self {
+        self.opts.newline_style = style;
+        self
+    }
+
+    /// Choose what to do with invalid unicode in [`Quoted::unix_raw`],
+    /// [`Quoted::windows_raw`] and `Quoted::native_raw`.
+    ///
+    /// Defaults to [`InvalidPolicy::Escape`]. The other policies lose
+    /// information, so they're only suitable for text that's meant for
+    /// humans. The rest of the text is quoted as if the invalid parts had
+    /// been replaced or left out from the start.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::{InvalidPolicy, Quoted};
+    ///
+    /// let quoted = Quoted::unix_raw(b"a\xFFb").force(false);
+    /// assert_eq!(quoted.to_string(), "$'a\\xFF'$'b'");
+    /// assert_eq!(quoted.invalid(InvalidPolicy::Replace).to_string(), "a\u{FFFD}b");
+    /// assert_eq!(quoted.invalid(InvalidPolicy::Strip).to_string(), "ab");
+    /// # }
+    /// ```
+    pub fn invalid(mut self, policy: InvalidPolicy) -> Self {
+        self.opts.invalid = policy;
+        self
+    }
+
+    /// Choose the character that [`InvalidPolicy::Replace`] puts in place
+    /// of invalid unicode, for terminals that can't show U+FFFD.
+    ///
+    /// This also goes for [`Quoted::windows_raw_utf8`] and for bytes that
+    /// aren't valid in [`Quoted::wtf8`], which always replace them.
+    ///
+    /// Defaults to U+FFFD REPLACEMENT CHARACTER (`�`). The replacement is
+    /// quoted like any other character, so something like `?` may cause
+    /// quoting as a glob character.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::{InvalidPolicy, Quoted};
+    ///
+    /// let quoted = Quoted::unix_raw(b"a\xFFb").invalid(InvalidPolicy::Replace);
+    /// assert_eq!(quoted.replacement_char('_').to_string(), "'a_b'");
+    /// # }
+    /// ```
+    pub fn replacement_char(mut self, ch: char) -> Self {
+        self.opts.replacement = Utf8Char::new(ch);
+        self
+    }
+
+    /// Leave out a byte order mark (U+FEFF) at the start of the text.
+    ///
+    /// Editors on Windows sometimes put one at the start of a file, and it
+    /// can end up in text read from that file. It's invisible, but it stops
+    /// shells from recognizing whatever comes after it.
+    ///
+    /// By default it's kept. In the Unix and Windows styles this always
+    /// causes quotes, since it's a zero-width character at the start.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let quoted = Quoted::unix("\u{FEFF}foo").force(false);
+    /// assert_eq!(quoted.to_string(), "'\u{FEFF}foo'");
+    /// assert_eq!(quoted.strip_bom(true).to_string(), "foo");
+    /// # }
+    /// ```
+    pub fn strip_bom(mut self, strip_bom: bool) -> Self {
+        self.opts.strip_bom = strip_bom;
+        self
+    }
+
+    /// Normalize the text to NFC before quoting it.
+    ///
+    /// Some characters can be written in more than one way, like `é`, which
+    /// is either U+00E9 or an `e` followed by U+0301 COMBINING ACUTE ACCENT.
+    /// These look the same, but some filesystems keep the decomposed form.
+    /// Normalizing makes them come out the same. Invalid text is left alone.
+    ///
+    /// Note that the result no longer matches the original text exactly,
+    /// so it might not find the same file if it's pasted into a shell.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let quoted = Quoted::unix("cafe\u{301}").normalize_nfc(true);
+    /// assert_eq!(quoted.to_string(), "'caf\u{E9}'");
+    /// # }
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the optional `nfc` feature.
+    #[cfg(feature = "nfc")]
+    pub fn normalize_nfc(mut self, normalize: bool) -> Self {
+        self.opts.normalize_nfc = normalize;
+        self
+    }
+
+    /// Set the character that separates fields in the CSV style. Fields
+    /// that contain it are quoted.
+    ///
+    /// Defaults to `,`.
+    ///
+    /// # Panics
+    /// If the delimiter is `"`, `\r` or `\n`, since those can't separate
+    /// fields.
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        assert!(
+            !"\"\r\n".contains(delimiter),
+            "invalid CSV delimiter {:?}",
+            delimiter
+        );
+        self.opts.delimiter = delimiter;
+        self
+    }
+
+    /// Toggle quoting of glob characters (`*?[]{}`) in the Unix style.
+    ///
+    /// If `false`, these don't cause the text to be quoted. That's only
+    /// appropriate if the output is meant for humans and won't be pasted
+    /// into a shell.
+    ///
+    /// Defaults to `true`. This has no effect if quoting is forced.
+    pub fn glob_safe(mut self, glob_safe: bool) -> Self {
+        self.opts.glob_safe = glob_safe;
+        self
+    }
+
+    /// Toggle quoting of text that starts with a dash (`-`) in the Unix
+    /// style, so that it's harder to mistake for an option.
+    ///
+    /// The PowerShell style always does this. Defaults to `false`.
+    pub fn protect_dashes(mut self, protect_dashes: bool) -> Self {
+        self.opts.protect_dashes = protect_dashes;
+        self
+    }
+
+    /// Toggle quoting of text that's exactly a shell keyword, like `if` or
+    /// `done`, so that it can't be read as one at the start of a command.
+    ///
+    /// The keywords depend on the style: the Unix style uses bash's reserved
+    /// words and the Windows style uses PowerShell's (in any case). Builtins
+    /// like `test` aren't included, since quoting doesn't stop the shell
+    /// from running them.
+    ///
+    /// Defaults to `false`. This has no effect if quoting is forced.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::unix("if").force(false).to_string(), "if");
+    /// assert_eq!(
+    ///     Quoted::unix("if").force(false).quote_keywords(true).to_string(),
+    ///     "'if'"
+    /// );
+    /// # }
+    /// ```
+    pub fn quote_keywords(mut self, quote_keywords: bool) -> Self {
+        self.opts.quote_keywords = quote_keywords;
+        self
+    }
+
+    /// Toggle quoting of reserved device names in the Windows style, like
+    /// `NUL` or `con.txt`.
+    ///
+    /// Windows treats these names as devices in any directory, in any case
+    /// and with any extension, so a file by that name can't be opened
+    /// normally. Quoting doesn't change that, but it makes them stand out.
+    ///
+    /// Defaults to `false`. This has no effect if quoting is forced.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "windows")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::windows("NUL").force(false).to_string(), "NUL");
+    /// assert_eq!(
+    ///     Quoted::windows("NUL").force(false).protect_devices(true).to_string(),
+    ///     "'NUL'"
+    /// );
+    /// # }
+    /// ```
+    pub fn protect_devices(mut self, protect_devices: bool) -> Self {
+        self.opts.protect_devices = protect_devices;
+        self
+    }
+
+    /// Toggle quoting of text that starts like a number in the PowerShell
+    /// style, like `2023-report.txt` or `.5d`.
+    ///
+    /// PowerShell may parse such text as a number when it's passed to a
+    /// cmdlet, so by default it's quoted. If the output is only meant to be
+    /// read, that's not necessary. Text that starts with a dash is still
+    /// quoted.
+    ///
+    /// Defaults to `true`. This has no effect if quoting is forced.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "windows")] {
+    /// use os_display::Quoted;
+    ///
+    /// let quoted = Quoted::windows("2023-report.txt").force(false);
+    /// assert_eq!(quoted.to_string(), "'2023-report.txt'");
+    /// assert_eq!(quoted.quote_numbers(false).to_string(), "2023-report.txt");
+    /// # }
+    /// ```
+    pub fn quote_numbers(mut self, quote_numbers: bool) -> Self {
+        self.opts.quote_numbers = quote_numbers;
+        self
+    }
+
+    /// Choose where text in the Windows style is going to be used. See
+    /// [`PowerShellContext`].
+    ///
+    /// Defaults to [`PowerShellContext::External`], which quotes more.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "windows")] {
+    /// use os_display::{PowerShellContext, Quoted};
+    ///
+    /// let quoted = Quoted::windows("~/foo").force(false);
+    /// assert_eq!(quoted.to_string(), "'~/foo'");
+    /// assert_eq!(
+    ///     quoted.powershell_context(PowerShellContext::Cmdlet).to_string(),
+    ///     "~/foo"
+    /// );
+    /// # }
+    /// ```
+    pub fn powershell_context(mut self, context: PowerShellContext) -> Self {
+        self.opts.powershell_context = context;
+        self
+    }
+
+    /// Treat extra characters as special in the Unix style, so that text
+    /// that contains them is quoted. This is on top of the characters that
+    /// are always special, like `$` and `;`.
+    ///
+    /// This is useful for wrappers and other tools that give meaning to
+    /// characters that the shell doesn't care about, like `:` or `@`.
+    ///
+    /// Calling this again replaces the earlier characters. This has no
+    /// effect if quoting is forced.
+    ///
+    /// # Panics
+    /// Panics if `chars` contains non-ASCII characters.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::unix("a:b").force(false).to_string(), "a:b");
+    /// assert_eq!(
+    ///     Quoted::unix("a:b").force(false).extra_specials(":").to_string(),
+    ///     "'a:b'"
+    /// );
+    /// # }
+    /// ```
+    pub fn extra_specials(mut self, chars: &str) -> Self {
+        let mut set = ByteSet(0);
+        for byte in chars.bytes() {
+            assert!(byte.is_ascii(), "extra special characters must be ASCII");
+            set = set.union(byte_set![byte]);
+        }
+        self.opts.extra_specials = set;
+        self
+    }
+
+    /// Choose between short symbolic escapes for control codes (like `\n` or
+    /// `` `n ``) and numeric escapes (like `\x0A` or `` `u{0A} ``).
+    ///
+    /// By default the Unix style only uses `\n`, `\t` and `\r`, because
+    /// the others are rarely seen or not supported by every shell. `true`
+    /// uses every symbolic escape the style knows, `false` only uses numeric
+    /// escapes.
+    pub fn symbolic_escapes(mut self, symbolic: bool) -> Self {
+        self.opts.symbolic_escapes = Some(symbolic);
+        self
+    }
+
+    /// Toggle lowercase hexadecimal digits in escape codes, e.g. `\xff`
+    /// instead of `\xFF`.
+    ///
+    /// Defaults to `false`, except in the Python style.
+    pub fn lowercase_hex(mut self, lowercase: bool) -> Self {
+        self.opts.lowercase_hex = Some(lowercase);
+        self
+    }
+
+    /// Toggle quoting for interactive shells in the Unix style.
+    ///
+    /// Interactive bash uses `!` for history expansion, even between double
+    /// quotes. If `true`, text that contains `!` is always put between single
+    /// quotes.
+    ///
+    /// Defaults to `false`, because scripts don't do history expansion.
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.opts.interactive = interactive;
+        self
+    }
+
+    /// Turn on everything needed for text that will be pasted into an
+    /// interactive bash prompt, in the Unix style.
+    ///
+    /// That is:
+    /// - [`Quoted::interactive`], so that `!` doesn't expand the history.
+    /// - When this is the command name in a [`CommandLine`], it's always
+    ///   quoted, so that it can't be replaced by an alias.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::{CommandLine, Quoted};
+    ///
+    /// let args = ["ls", "hi!"];
+    /// let command = CommandLine::new(args.iter().map(|arg| Quoted::unix(arg)));
+    /// assert_eq!(command.to_string(), "ls hi!");
+    /// let command = CommandLine::new(args.iter().map(|arg| Quoted::unix(arg).interactive_bash()));
+    /// assert_eq!(command.to_string(), "'ls' 'hi!'");
+    /// # }
+    /// ```
+    pub fn interactive_bash(mut self) -> Self {
+        self.opts.interactive = true;
+        self.opts.avoid_aliases = true;
+        self
+    }
+
+    /// Show the quotes and escapes in a different color than the text
+    /// itself, using ANSI escape codes.
+    ///
+    /// This makes it easier to tell which characters are literal, but the
+    /// output can't be pasted into a shell anymore. To only use color when
+    /// printing to a terminal, pass the result of
+    /// `std::io::IsTerminal::is_terminal` (or a similar check).
+    ///
+    /// Defaults to `false`.
+    pub fn color(mut self, color: bool) -> Self {
+        self.opts.color = color;
+        self
+    }
+
+    /// Cut the text short so that it takes up at most `max_width` columns,
+    /// and end it with `…` to show that something is missing.
+    ///
+    /// The quotes and escapes count towards the width, but they're never
+    /// cut in half. Instead, only as much of the text is quoted as fits.
+    ///
+    /// The result isn't meant to be pasted into a shell, since it's not the
+    /// original text.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let quoted = Quoted::unix("a very long name.txt").max_width(10);
+    /// assert_eq!(quoted.to_string(), "'a very '…");
+    /// # }
+    /// ```
+    pub fn max_width(mut self, max_width: usize) -> Self {
+        self.opts.max_width = Some(max_width);
+        self
+    }
+
+    /// Cut the text short so that the output is at most `max_len` bytes
+    /// long, and end it with `…` to show that something is missing.
+    ///
+    /// Escaping can make text several times longer, e.g. a null byte turns
+    /// into `\x00`. This puts a bound on that for untrusted input. Like with
+    /// [`Quoted::max_width`], escapes are never cut in half and open quotes
+    /// are closed. Formatting stops early once the output gets too long,
+    /// so huge text doesn't take much work.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let quoted = Quoted::unix("\0\0\0\0\0\0\0\0").max_output_len(16);
+    /// assert_eq!(quoted.to_string(), r"$'\x00\x00'…");
+    /// # }
+    /// ```
+    pub fn max_output_len(mut self, max_len: usize) -> Self {
+        self.opts.max_output_len = Some(max_len);
+        self
+    }
+
+    /// Count East Asian ambiguous characters (like `①` and `○`) as two
+    /// columns wide instead of one, as terminals in CJK locales do.
+    ///
+    /// This affects [`Quoted::display_width`], [`Quoted::max_width`] and
+    /// [`Quoted::pad_to`]. It doesn't change what gets quoted or escaped.
+    ///
+    /// Defaults to `false`.
+    pub fn east_asian(mut self, east_asian: bool) -> Self {
+        self.opts.east_asian = east_asian;
+        self
+    }
+
+    /// Use the text as the value in an assignment to an environment
+    /// variable, like `FOO='bar baz'`.
+    ///
+    /// Returns `None` if `key` isn't a valid variable name, i.e. if it
+    /// contains anything other than ASCII letters, digits and underscores,
+    /// or if it starts with a digit.
+    ///
+    /// See [`EnvAssignment`].
+    pub fn env_assignment(self, key: &'a str) -> Option<EnvAssignment<'a>> {
+        EnvAssignment::new(key, self)
+    }
+
+    /// Check whether the text would be displayed as it is, without quotes
+    /// or escapes, if quoting weren't forced.
+    ///
+    /// This ignores [`Quoted::force`] but does take the other options into
+    /// account. It returns `true` exactly when `.force(false)` (or
+    /// `maybe_quote()`) leaves the text unchanged.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert!(Quoted::unix("foo.txt").is_safe_bare());
+    /// assert!(!Quoted::unix("foo bar.txt").is_safe_bare());
+    /// # }
+    /// ```
+    pub fn is_safe_bare(&self) -> bool {
+        match self.source {
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => match text.to_str() {
+                Some(text) => displays_as(&self.force(false), text.chars()),
+                None => false,
+            },
+
+            #[cfg(feature = "native")]
+            Kind::NativeChar(ch) => displays_as(&self.force(false), core::iter::once(ch)),
+
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => displays_as(&self.force(false), text.chars()),
+
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) => match core::str::from_utf8(bytes) {
+                Ok(text) => displays_as(&self.force(false), text.chars()),
+                Err(_) => false,
+            },
+
+            #[cfg(feature = "unix")]
+            Kind::Printf(bytes) => match core::str::from_utf8(bytes) {
+                Ok(text) => displays_as(&self.force(false), text.chars()),
+                Err(_) => false,
+            },
+
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => displays_as(&self.force(false), text.chars()),
+
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(units) => {
+                use crate::windows::Text;
+
+                if decode_utf16(units.iter().cloned()).any(|res| res.is_err()) {
+                    false
+                } else {
+                    let text = windows::Utf16(units, core::char::REPLACEMENT_CHARACTER);
+                    displays_as(&self.force(false), text.chars())
+                }
+            }
+
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) | Kind::Wtf8(bytes) => match core::str::from_utf8(bytes) {
+                Ok(text) => displays_as(&self.force(false), text.chars()),
+                Err(_) => false,
+            },
+
+            // These are always quoted.
+            #[cfg(feature = "c")]
+            Kind::C(_) => false,
+
+            #[cfg(feature = "json")]
+            Kind::Json(_) => false,
+
+            #[cfg(feature = "python")]
+            Kind::Python(_) => false,
+
+            #[cfg(feature = "xonsh")]
+            Kind::Xonsh(text) => displays_as(&self.force(false), text.chars()),
+
+            #[cfg(feature = "toml")]
+            Kind::Toml(_) => false,
+
+            #[cfg(feature = "yaml")]
+            Kind::Yaml(text) => displays_as(&self.force(false), text.chars()),
+
+            #[cfg(feature = "csv")]
+            Kind::Csv(text) => displays_as(&self.force(false), text.chars()),
+
+            #[cfg(feature = "makefile")]
+            Kind::Makefile(text) => displays_as(&self.force(false), text.chars()),
+
+            #[cfg(feature = "makefile")]
+            Kind::MakefileShell(text) => displays_as(&self.force(false), text.chars()),
+
+            #[cfg(feature = "verbatim")]
+            Kind::Verbatim(_) => false,
+        }
+    }
+
+    /// Check whether the original text contains bidirectional control codes,
+    /// like U+202E RIGHT-TO-LEFT OVERRIDE.
+    ///
+    /// These are escaped when they're used in a suspicious way, see
+    /// [`Quoted::bidi`]. But a security-conscious tool may want to flag
+    /// them in any case. See also [`contains_bidi`].
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert!(!Quoted::unix("foo").contains_bidi());
+    /// assert!(Quoted::unix("foo\u{202E}").contains_bidi());
+    /// # }
+    /// ```
+    pub fn contains_bidi(&self) -> bool {
+        self.source.any_char(is_bidi)
+    }
+
+    /// Check whether the original text mixes Latin letters with Cyrillic or
+    /// Greek letters inside a word, like `p\u{430}ypal` (with a Cyrillic
+    /// `а`).
+    ///
+    /// This is a common way to make a name look like another one. It's only
+    /// advisory and doesn't affect quoting. See also [`contains_confusables`].
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert!(!Quoted::unix("paypal").contains_confusables());
+    /// assert!(Quoted::unix("p\u{430}ypal").contains_confusables());
+    /// # }
+    /// ```
+    pub fn contains_confusables(&self) -> bool {
+        let mut word = None;
+        self.source.any_char(|ch| mixes_scripts(&mut word, ch))
+    }
+
+    /// Find out whether the text has to be quoted or escaped, and why.
+    ///
+    /// This makes the same decisions as formatting the text, but reports
+    /// them instead of writing anything. That lets a linter say exactly
+    /// what's wrong with a filename. [`Quoted::force`] is ignored, since
+    /// it's not a property of the text.
+    ///
+    /// The reasons are most detailed for the Unix and Windows styles. The
+    /// other styles only report control codes and bidi control codes.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::{Classification, Quoted};
+    ///
+    /// assert_eq!(Quoted::unix("foo").analyze(), Classification::Safe);
+    /// assert_eq!(
+    ///     Quoted::unix("foo\u{202E}").analyze(),
+    ///     Classification::NeedsEscape {
+    ///         control: false,
+    ///         invalid_unicode: false,
+    ///         bidi: true,
+    ///     },
+    /// );
+    /// # }
+    /// ```
+    pub fn analyze(&self) -> Classification {
+        let opts = Options {
+            force_quote: false,
+            ..self.opts
+        };
+        match self.source {
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => {
+                #[cfg(unix)]
+                use std::os::unix::ffi::OsStrExt;
+                #[cfg(target_os = "wasi")]
+                use std::os::wasi::ffi::OsStrExt;
+                #[cfg(windows)]
+                use std::os::windows::ffi::OsStrExt;
+
+                #[cfg(windows)]
+                return match text.to_str() {
+                    Some(text) => windows::classify(text, &opts, false),
+                    None => {
+                        let units: std::vec::Vec<u16> = text.encode_wide().collect();
+                        windows::classify_raw(&units, &opts)
+                    }
+                };
+                #[cfg(any(unix, target_os = "wasi"))]
+                return unix::classify(text.as_bytes(), &opts);
+                #[cfg(not(any(windows, unix, target_os = "wasi")))]
+                return match text.to_str() {
+                    Some(text) => unix::classify(text.as_bytes(), &opts),
+                    None => Classification::NeedsEscape {
+                        control: false,
+                        invalid_unicode: true,
+                        bidi: false,
+                    },
+                };
+            }
+
+            #[cfg(feature = "native")]
+            Kind::NativeChar(ch) => {
+                let mut buf = [0; 4];
+                let text: &str = ch.encode_utf8(&mut buf);
+                #[cfg(windows)]
+                return windows::classify(text, &opts, false);
+                #[cfg(not(windows))]
+                return unix::classify(text.as_bytes(), &opts);
+            }
+
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => unix::classify(text.as_bytes(), &opts),
+
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) => unix::classify(bytes, &opts),
+
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => windows::classify(text, &opts, false),
+
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(units) => windows::classify_raw(units, &opts),
+
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) => {
+                let text = windows::Utf8Lossy(bytes, opts.replacement.as_char());
+                windows::classify(text, &opts, false)
+            }
+
+            #[cfg(feature = "windows")]
+            Kind::Wtf8(bytes) => windows::classify_wtf8(bytes, &opts),
+
+            // The escaping for Make comes on top of the shell quoting.
+            #[cfg(feature = "makefile")]
+            Kind::MakefileShell(text) => unix::classify(text.as_bytes(), &opts),
+
+            #[allow(unreachable_patterns)]
+            _ => {
+                let control = self.source.any_char(|ch| opts.requires_escape(ch));
+                let bidi = opts.bidi != BidiPolicy::Allow && self.contains_bidi();
+                if control || bidi {
+                    Classification::NeedsEscape {
+                        control,
+                        invalid_unicode: false,
+                        bidi,
+                    }
+                } else if self.is_safe_bare() {
+                    Classification::Safe
+                } else {
+                    Classification::NeedsQuote {
+                        whitespace: false,
+                        special: false,
+                        leading: false,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Find out how the text will be written without writing it, for
+    /// example to place a cursor inside the quotes.
+    ///
+    /// Unlike [`Quoted::analyze`] this does take [`Quoted::force`] into
+    /// account. It describes formatting with `{}`, not with `{:#}`.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::{Quoted, Rendering};
+    ///
+    /// assert_eq!(
+    ///     Quoted::unix("a\nb").rendering(),
+    ///     Rendering {
+    ///         delimiter: Some('\''),
+    ///         escaped: true,
+    ///     },
+    /// );
+    /// # }
+    /// ```
+    pub fn rendering(&self) -> Rendering {
+        let escaped = match self.analyze() {
+            Classification::NeedsEscape { .. } => true,
+            _ => false,
+        };
+        let mut probe = *self;
+        probe.opts.find_delimiter = true;
+        probe.opts.color = false;
+        Rendering {
+            delimiter: width::opening_quote(&probe),
+            escaped,
+        }
+    }
+
+    /// The number of columns that the quoted text takes up in a terminal.
+    ///
+    /// Wide characters (like most CJK) count as two columns and zero-width
+    /// characters don't count, as decided by the `unicode-width` crate.
+    /// Color codes don't count either.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::unix("foo").display_width(), 5);
+    /// assert_eq!(Quoted::unix("テキスト").force(false).display_width(), 8);
+    /// # }
+    /// ```
+    pub fn display_width(&self) -> usize {
+        width::width(self, self.opts.east_asian)
+    }
+
+    /// Pad the quoted text with spaces until it takes up `width` columns,
+    /// to line it up with other text.
+    ///
+    /// If the text is already wider than that, it's left as it is. You can
+    /// combine this with [`Quoted::max_width`] to get a fixed width.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// assert_eq!(Quoted::unix("foo").pad_to(8).to_string(), "'foo'   ");
+    /// assert_eq!(Quoted::unix("foo").pad_to(2).to_string(), "'foo'");
+    /// # }
+    /// ```
+    pub fn pad_to(&self, width: usize) -> impl Display + 'a {
+        width::Padded {
+            quoted: *self,
+            width,
+        }
+    }
+
+    /// An upper bound for the length of the quoted text in bytes, for use
+    /// with `String::reserve` and [`Quoted::quote_into`].
+    ///
+    /// This only looks at the length of the original text, so it's cheap,
+    /// but it makes room for every single byte to be escaped. It's usually
+    /// far too high.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let quoted = Quoted::unix("foo\tbar");
+    /// assert!(quoted.estimated_len() >= quoted.to_string().len());
+    /// # }
+    /// ```
+    pub fn estimated_len(&self) -> usize {
+        // The worst cases look like $'\xFF'$'0' in the Unix style, where a
+        // single byte turns into seven. Coloring the syntax adds ten bytes
+        // to each piece of it. A placeholder for empty text is written
+        // as it is, without any color.
+        let (mut per_unit, mut fixed) = if self.opts.color { (30, 40) } else { (10, 16) };
+        fixed += self.opts.empty_as.map_or(0, str::len);
+        // A replacement character takes the place of a single invalid unit,
+        // and each of its bytes can be escaped as badly as any other byte.
+        if self.opts.invalid == InvalidPolicy::Replace {
+            per_unit *= usize::from(self.opts.replacement.len);
+        }
+        // Normalization can make text up to three times as long.
+        #[cfg(feature = "nfc")]
+        {
+            if self.opts.normalize_nfc {
+                per_unit *= 3;
+            }
+        }
+        let len = match self.source {
+            #[cfg(feature = "native")]
+            Kind::NativeChar(ch) => ch.len_utf8(),
+            _ => self.source.len(),
+        };
+        len.saturating_mul(per_unit).saturating_add(fixed)
+    }
+
+    /// Copy the text into a [`QuotedBuf`] so it can outlive the original.
+    ///
+    /// # Optional
+    /// This requires the (default) `alloc` feature.
+    #[cfg(feature = "alloc")]
+    pub fn into_owned(self) -> QuotedBuf {
+        QuotedBuf::from(self)
+    }
+
+    /// Copy the text into a [`QuotedBuf`] without giving up this `Quoted`.
+    ///
+    /// Raw text stays raw: the bytes of [`Quoted::unix_raw`] and the code
+    /// units of [`Quoted::windows_raw`] are copied as they are.
+    ///
+    /// Since `Quoted` is `Copy` this is the same as [`Quoted::into_owned`],
+    /// but it takes precedence over `ToOwned::to_owned`, which would
+    /// return another `Quoted`.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let owned = {
+    ///     let text = String::from("my file");
+    ///     Quoted::unix(&text).to_owned()
+    /// };
+    /// assert_eq!(owned.to_string(), "'my file'");
+    /// # }
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the (default) `alloc` feature.
+    #[cfg(feature = "alloc")]
+    pub fn to_owned(&self) -> QuotedBuf {
+        QuotedBuf::from(*self)
+    }
+
+    /// Append the quoted text to a `String`.
+    ///
+    /// This gives the same result as `buf.push_str(&quoted.to_string())`,
+    /// but it doesn't allocate a new `String` every time. That helps when
+    /// building a large buffer.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let mut buf = String::from("rm");
+    /// for name in &["foo", "bar baz"] {
+    ///     buf.push(' ');
+    ///     Quoted::unix(name).force(false).quote_into(&mut buf);
+    /// }
+    /// assert_eq!(buf, "rm foo 'bar baz'");
+    /// # }
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the (default) `alloc` feature.
+    #[cfg(feature = "alloc")]
+    pub fn quote_into(&self, buf: &mut alloc::string::String) {
+        use core::fmt::Write;
+
+        write!(buf, "{}", self).expect("formatting Quoted failed");
+    }
+
+    /// Get the quoted text as bytes.
+    ///
+    /// If quoting leaves the text unchanged then the original is borrowed,
+    /// without a copy or a round trip through `String`. That suits tools
+    /// that write raw bytes, e.g. to stdout. Otherwise the quoted form is
+    /// returned, which can be written the same way.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let quoted = Quoted::unix_raw(b"foo").force(false).quote_bytes();
+    /// assert_eq!(&*quoted, b"foo");
+    /// let quoted = Quoted::unix_raw(b"foo\xFF").force(false).quote_bytes();
+    /// assert_eq!(&*quoted, b"$'foo\\xFF'");
+    /// # }
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the (default) `alloc` feature.
+    #[cfg(feature = "alloc")]
+    pub fn quote_bytes(&self) -> alloc::borrow::Cow<'a, [u8]> {
+        use alloc::borrow::Cow;
+        use alloc::string::ToString;
+
+        if !self.opts.force_quote && self.is_safe_bare() {
+            if let Some(bytes) = self.source.as_bytes() {
+                return Cow::Borrowed(bytes);
+            }
+        }
+        Cow::Owned(self.to_string().into_bytes())
+    }
+
+    /// Write the text in its escaped form, without first checking whether
+    /// that's necessary.
+    ///
+    /// In the Unix style this is the `$'...'` syntax (or the one chosen with
+    /// [`Quoted::escape_dialect`]), in the Windows style it's double quotes
+    /// with backtick escapes. It's meant for custom formatting, when you
+    /// already know that the text has to be escaped. The other settings
+    /// still apply, except for [`Quoted::max_width`].
+    ///
+    /// The other styles have only one form, so for them this is the same as
+    /// formatting the text normally.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use std::fmt;
+    ///
+    /// use os_display::Quoted;
+    ///
+    /// struct Label<'a>(&'a str);
+    ///
+    /// impl fmt::Display for Label<'_> {
+    ///     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    ///         f.write_str("label=")?;
+    ///         Quoted::unix(self.0).write_escaped(f)
+    ///     }
+    /// }
+    ///
+    /// assert_eq!(Label("foo").to_string(), "label=$'foo'");
+    /// assert_eq!(Label("a\tb").to_string(), r"label=$'a\tb'");
+    /// # }
+    /// ```
+    pub fn write_escaped(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.opts.strip_bom {
+            if let Some(source) = self.source.strip_bom() {
+                let mut opts = self.opts;
+                opts.strip_bom = false;
+                return Quoted { source, opts }.write_escaped(f);
+            }
+        }
+
+        match self.source {
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => {
+                #[cfg(unix)]
+                use std::os::unix::ffi::OsStrExt;
+                #[cfg(target_os = "wasi")]
+                use std::os::wasi::ffi::OsStrExt;
+                #[cfg(windows)]
+                use std::os::windows::ffi::OsStrExt;
+
+                // Kind::strip_bom() can't help with invalid text.
+                #[cfg(windows)]
+                return {
+                    let mut units = text.encode_wide().peekable();
+                    if self.opts.strip_bom && units.peek() == Some(&0xFEFF) {
+                        units.next();
+                    }
+                    windows::write_escaped(f, decode_utf16(units), &self.opts)
+                };
+                #[cfg(any(unix, target_os = "wasi"))]
+                return unix::write_escaped(f, text.as_bytes(), &self.opts);
+                #[cfg(not(any(windows, unix, target_os = "wasi")))]
+                return self.fmt(f);
+            }
+
+            #[cfg(feature = "native")]
+            Kind::NativeChar(ch) => {
+                let mut buf = [0; 4];
+                let text: &str = ch.encode_utf8(&mut buf);
+                #[cfg(windows)]
+                return windows::write_escaped(f, text.chars().map(Ok), &self.opts);
+                #[cfg(not(windows))]
+                return unix::write_escaped(f, text.as_bytes(), &self.opts);
+            }
+
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => unix::write_escaped(f, text.as_bytes(), &self.opts),
+
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) => unix::write_escaped(f, bytes, &self.opts),
+
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => windows::write_escaped(f, text.chars().map(Ok), &self.opts),
+
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(units) => {
+                windows::write_escaped(f, decode_utf16(units.iter().cloned()), &self.opts)
+            }
+
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) => {
+                use crate::windows::Text;
+
+                let text = windows::Utf8Lossy(bytes, self.opts.replacement.as_char());
+                let text = text.chars().map(Ok);
+                windows::write_escaped(f, text, &self.opts)
+            }
+
+            #[cfg(feature = "windows")]
+            Kind::Wtf8(bytes) => {
+                let text = windows::decode_wtf8(bytes, self.opts.replacement.as_char());
+                windows::write_escaped(f, text, &self.opts)
+            }
+
+            #[allow(unreachable_patterns)]
+            _ => self.fmt(f),
+        }
+    }
+
+    /// Escape the text for use inside double quotes, without adding any
+    /// quotes around it.
+    ///
+    /// This is for splicing text into a larger double-quoted string that
+    /// you write yourself. In the Unix style `$`, `` ` ``, `\` and `"` are
+    /// escaped with a backslash. Control codes can't be written inside double
+    /// quotes, so for those the quotes are closed for a moment, as in
+    /// `"$'\t'"`. In the Windows style backtick escapes are used, which work
+    /// inside double quotes.
+    ///
+    /// The other styles have only one form, so for them this is the same as
+    /// formatting the text normally, with quotes.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(feature = "unix")] {
+    /// use os_display::Quoted;
+    ///
+    /// let name = Quoted::unix("$USER's \"files\"").escape_only();
+    /// assert_eq!(format!("\"/home/{}\"", name), r#""/home/\$USER's \"files\"""#);
+    /// # }
+    /// ```
+    pub fn escape_only(&self) -> impl Display + 'a {
+        struct EscapeOnly<'a>(Quoted<'a>);
+
+        impl<'a> Display for EscapeOnly<'a> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                self.0.write_escaped(f)
+            }
+        }
+
+        let mut quoted = *self;
+        quoted.opts.escape_only = true;
+        EscapeOnly(quoted)
+    }
+}
+
+impl<'a> Display for Quoted<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.opts.strip_bom {
+            if let Some(source) = self.source.strip_bom() {
+                let mut opts = self.opts;
+                opts.strip_bom = false;
+                return Quoted { source, opts }.fmt(f);
+            }
+        }
+        #[cfg(feature = "nfc")]
+        {
+            if self.opts.normalize_nfc {
+                if let Some((text, kind)) = self.source.nfc() {
+                    let mut opts = self.opts;
+                    opts.normalize_nfc = false;
+                    return Quoted {
+                        source: kind(&text),
+                        opts,
+                    }
+                    .fmt(f);
+                }
+            }
+        }
+        if let Some(placeholder) = self.opts.empty_as {
+            if self.source.len() == 0 {
+                return f.write_str(placeholder);
+            }
+        }
+        if f.alternate() && self.opts.force_quote {
+            return self.force(false).fmt(f);
+        }
+        if let Some(max_len) = self.opts.max_output_len {
+            return truncate::write_len(f, self, max_len);
+        }
+        if let Some(max_width) = self.opts.max_width {
+            return truncate::write(f, self, max_width);
+        }
+
+        match self.source {
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => {
+                #[cfg(unix)]
+                use std::os::unix::ffi::OsStrExt;
+                #[cfg(target_os = "wasi")]
+                use std::os::wasi::ffi::OsStrExt;
                 #[cfg(windows)]
                 use std::os::windows::ffi::OsStrExt;
 
-                #[cfg(windows)]
-                match text.to_str() {
-                    Some(text) => windows::write(f, text, self.force_quote),
-                    None => windows::write_escaped(f, decode_utf16(text.encode_wide())),
+                #[cfg(windows)]
+                match text.to_str() {
+                    Some(text) => windows::write(f, text, &self.opts),
+                    None if self.opts.invalid == InvalidPolicy::Escape && !self.opts.strip_bom => {
+                        windows::write_escaped(f, decode_utf16(text.encode_wide()), &self.opts)
+                    }
+                    None => {
+                        // We need to go over the text more than once.
+                        let units: std::vec::Vec<u16> = text.encode_wide().collect();
+                        windows::write_raw(f, &units, &self.opts)
+                    }
+                }
+                #[cfg(any(unix, target_os = "wasi"))]
+                match text.to_str() {
+                    Some(text) => unix::write(f, text, &self.opts),
+                    None => unix::write_bytes(f, text.as_bytes(), &self.opts),
+                }
+                #[cfg(not(any(windows, unix, target_os = "wasi")))]
+                match text.to_str() {
+                    Some(text) => unix::write(f, text, &self.opts),
+                    // Debug is our best shot for not losing information.
+                    // But you probably can't paste it into a shell.
+                    None => write!(f, "{:?}", text),
+                }
+            }
+
+            #[cfg(feature = "native")]
+            Kind::NativeChar(ch) => {
+                let mut buf = [0; 4];
+                let text: &str = ch.encode_utf8(&mut buf);
+                #[cfg(windows)]
+                return windows::write(f, text, &self.opts);
+                #[cfg(not(windows))]
+                return unix::write(f, text, &self.opts);
+            }
+
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => unix::write(f, text, &self.opts),
+
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) => match core::str::from_utf8(bytes) {
+                Ok(text) => unix::write(f, text, &self.opts),
+                Err(_) => unix::write_bytes(f, bytes, &self.opts),
+            },
+
+            #[cfg(feature = "unix")]
+            Kind::Printf(bytes) => unix::write_printf(f, bytes, &self.opts),
+
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => windows::write(f, text, &self.opts),
+
+            #[cfg(feature = "windows")]
+            // We go over the text once to check that it's valid, and then
+            // decode it again for every pass that windows::write() makes.
+            // That's still cheaper than allocating a String.
+            Kind::WindowsRaw(units) => windows::write_raw(f, units, &self.opts),
+
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) => match core::str::from_utf8(bytes) {
+                Ok(text) => windows::write(f, text, &self.opts),
+                Err(_) => {
+                    let text = windows::Utf8Lossy(bytes, self.opts.replacement.as_char());
+                    windows::write(f, text, &self.opts)
+                }
+            },
+
+            #[cfg(feature = "windows")]
+            Kind::Wtf8(bytes) => windows::write_wtf8(f, bytes, &self.opts),
+
+            #[cfg(feature = "c")]
+            Kind::C(bytes) => c::write(f, bytes, &self.opts),
+
+            #[cfg(feature = "json")]
+            Kind::Json(bytes) => json::write(f, bytes, &self.opts),
+
+            #[cfg(feature = "python")]
+            Kind::Python(text) => python::write(f, text, &self.opts),
+            #[cfg(feature = "xonsh")]
+            Kind::Xonsh(text) => xonsh::write(f, text, &self.opts),
+
+            #[cfg(feature = "toml")]
+            Kind::Toml(text) => toml::write(f, text, &self.opts),
+
+            #[cfg(feature = "yaml")]
+            Kind::Yaml(text) => yaml::write(f, text, &self.opts),
+
+            #[cfg(feature = "csv")]
+            Kind::Csv(text) => csv::write(f, text, &self.opts),
+
+            #[cfg(feature = "makefile")]
+            Kind::Makefile(text) => makefile::write(f, text, &self.opts),
+
+            #[cfg(feature = "makefile")]
+            Kind::MakefileShell(text) => makefile::write_shell(f, text, &self.opts),
+
+            #[cfg(feature = "verbatim")]
+            Kind::Verbatim(text) => verbatim::write(f, text, &self.opts),
+        }
+    }
+}
+
+/// Check whether something is displayed as exactly this text.
+#[cfg(any(
+    feature = "unix",
+    feature = "windows",
+    feature = "native",
+    feature = "makefile",
+    feature = "yaml",
+    feature = "csv",
+    feature = "xonsh"
+))]
+fn displays_as(value: &impl Display, text: impl Iterator<Item = char>) -> bool {
+    struct Compare<I> {
+        text: I,
+    }
+
+    impl<I: Iterator<Item = char>> fmt::Write for Compare<I> {
+        fn write_str(&mut self, out: &str) -> fmt::Result {
+            for ch in out.chars() {
+                if self.text.next() != Some(ch) {
+                    // No need to look at the rest.
+                    return Err(fmt::Error);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut compare = Compare { text };
+    fmt::write(&mut compare, format_args!("{}", value)).is_ok() && compare.text.next().is_none()
+}
+
+#[cfg(any(feature = "windows", all(feature = "native", feature = "std", windows)))]
+fn decode_utf16(units: impl IntoIterator<Item = u16>) -> impl Iterator<Item = Result<char, u16>> {
+    core::char::decode_utf16(units).map(|res| res.map_err(|err| err.unpaired_surrogate()))
+}
+
+#[cfg(any(
+    feature = "unix",
+    feature = "c",
+    feature = "json",
+    feature = "makefile",
+    all(feature = "native", not(windows))
+))]
+fn from_utf8_iter(bytes: &[u8]) -> impl Iterator<Item = Result<&str, u8>> {
+    struct Iter<'a> {
+        bytes: &'a [u8],
+    }
+
+    impl<'a> Iterator for Iter<'a> {
+        type Item = Result<&'a str, u8>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.bytes.is_empty() {
+                return None;
+            }
+            match core::str::from_utf8(self.bytes) {
+                Ok(text) => {
+                    self.bytes = &[];
+                    Some(Ok(text))
+                }
+                Err(err) if err.valid_up_to() == 0 => {
+                    let res = self.bytes[0];
+                    self.bytes = &self.bytes[1..];
+                    Some(Err(res))
+                }
+                Err(err) => {
+                    let (valid, rest) = self.bytes.split_at(err.valid_up_to());
+                    self.bytes = rest;
+                    Some(Ok(core::str::from_utf8(valid).unwrap()))
+                }
+            }
+        }
+    }
+
+    Iter { bytes }
+}
+
+/// Characters that may not be safe to print in a terminal.
+///
+/// This includes all the ASCII control characters.
+fn requires_escape(ch: char) -> bool {
+    ch.is_control() || is_separator(ch)
+}
+
+/// U+2028 LINE SEPARATOR and U+2029 PARAGRAPH SEPARATOR are currently the only
+/// in their categories. The terminals I tried don't treat them very specially,
+/// but gedit does.
+fn is_separator(ch: char) -> bool {
+    ch == '\u{2028}' || ch == '\u{2029}'
+}
+
+/// Check whether text contains bidirectional control codes, like U+202E
+/// RIGHT-TO-LEFT OVERRIDE.
+///
+/// These can be used to make text look different from what it is, see
+/// <https://trojansource.codes/>.
+///
+/// # Examples
+/// ```
+/// use os_display::contains_bidi;
+///
+/// assert!(!contains_bidi("foo"));
+/// assert!(contains_bidi("foo\u{202E}"));
+/// ```
+pub fn contains_bidi(text: &str) -> bool {
+    text.chars().any(is_bidi)
+}
+
+/// Check whether text mixes Latin letters with Cyrillic or Greek letters
+/// inside a word, like `p\u{430}ypal` (with a Cyrillic `а`).
+///
+/// Many letters in these scripts look the same, so this is a common way to
+/// make a name look like another one. Words in a single script are fine, and
+/// so are other scripts. This is a rough check, not a full implementation of
+/// [Unicode's confusables](https://www.unicode.org/reports/tr39/).
+///
+/// # Examples
+/// ```
+/// use os_display::contains_confusables;
+///
+/// assert!(!contains_confusables("paypal"));
+/// assert!(!contains_confusables("Москва (Moscow)"));
+/// assert!(contains_confusables("p\u{430}ypal"));
+/// ```
+pub fn contains_confusables(text: &str) -> bool {
+    let mut word = None;
+    text.chars().any(|ch| mixes_scripts(&mut word, ch))
+}
+
+/// The scripts that are easily mistaken for each other.
+#[derive(Clone, Copy, PartialEq)]
+enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+}
+
+/// Feed a character to the check for mixed scripts, with the script of the
+/// current word so far. Returns true once a word turns out to be mixed.
+fn mixes_scripts(word: &mut Option<Script>, ch: char) -> bool {
+    if !ch.is_alphanumeric() {
+        *word = None;
+        return false;
+    }
+    match (*word, script(ch)) {
+        (Some(current), Some(script)) => current != script,
+        (None, script) => {
+            *word = script;
+            false
+        }
+        (_, None) => false,
+    }
+}
+
+/// The script of a letter, if it's one we care about. These are the main
+/// blocks from Blocks.txt, which is close enough.
+fn script(ch: char) -> Option<Script> {
+    match ch {
+        'A'..='Z'
+        | 'a'..='z'
+        | '\u{C0}'..='\u{D6}'
+        | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{24F}'
+        | '\u{1E00}'..='\u{1EFF}' => Some(Script::Latin),
+        '\u{370}'..='\u{3FF}' | '\u{1F00}'..='\u{1FFF}' => Some(Script::Greek),
+        '\u{400}'..='\u{52F}'
+        | '\u{1C80}'..='\u{1C8F}'
+        | '\u{2DE0}'..='\u{2DFF}'
+        | '\u{A640}'..='\u{A69F}' => Some(Script::Cyrillic),
+        _ => None,
+    }
+}
+
+/// Check whether text uses bidirectional control codes in a way that could
+/// make it look different from what it is, as in a
+/// [Trojan Source](https://trojansource.codes/) attack.
+///
+/// This is the check that decides whether these control codes are escaped
+/// by default (see [`BidiPolicy::Escape`]). The text is suspicious if an
+/// override, embedding or isolate isn't closed before the end of the line,
+/// if one is closed that wasn't opened, or if they're nested too deeply.
+///
+/// # Examples
+/// ```
+/// use os_display::is_suspicious;
+///
+/// assert!(!is_suspicious("foo"));
+/// assert!(!is_suspicious("\u{2067}foo\u{2069}"));
+/// assert!(is_suspicious("\u{202E}foo"));
+/// ```
+pub fn is_suspicious(text: &str) -> bool {
+    // Each paragraph (in practice, each line) starts over.
+    text.split(is_paragraph_separator)
+        .any(|paragraph| is_suspicious_bidi(paragraph.chars()))
+}
+
+/// Paragraph separators, bidi class B in UnicodeData.txt.
+fn is_paragraph_separator(ch: char) -> bool {
+    match ch {
+        '\n' | '\r' | '\x1C'..='\x1E' | '\u{85}' | '\u{2029}' => true,
+        _ => false,
+    }
+}
+
+/// Default_Ignorable_Code_Point in DerivedCoreProperties.txt, minus the
+/// bidi control codes from is_bidi().
+///
+/// This includes format characters like zero-width spaces, variation
+/// selectors, fillers, tags and unassigned codepoints that are reserved
+/// for more of the same.
+fn is_invisible(ch: char) -> bool {
+    match ch {
+        '\u{AD}'
+        | '\u{34F}'
+        | '\u{61C}'
+        | '\u{115F}'..='\u{1160}'
+        | '\u{17B4}'..='\u{17B5}'
+        | '\u{180B}'..='\u{180F}'
+        | '\u{200B}'..='\u{200F}'
+        | '\u{2060}'..='\u{2065}'
+        | '\u{206A}'..='\u{206F}'
+        | '\u{3164}'
+        | '\u{FE00}'..='\u{FE0F}'
+        | '\u{FEFF}'
+        | '\u{FFA0}'
+        | '\u{FFF0}'..='\u{FFF8}'
+        | '\u{1BCA0}'..='\u{1BCA3}'
+        | '\u{1D173}'..='\u{1D17A}'
+        | '\u{E0000}'..='\u{E0FFF}' => true,
+        _ => false,
+    }
+}
+
+/// These two ranges in PropList.txt:
+/// LEFT-TO-RIGHT EMBEDDING..RIGHT-TO-LEFT OVERRIDE
+/// LEFT-TO-RIGHT ISOLATE..POP DIRECTIONAL ISOLATE
+fn is_bidi(ch: char) -> bool {
+    match ch {
+        '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' => true,
+        _ => false,
+    }
+}
+
+/// Check whether text uses bidi in a potentially problematic way.
+///
+/// See https://trojansource.codes/ and
+/// https://www.unicode.org/reports/tr9/tr9-42.html.
+///
+/// If text fails this check then it's handled by write_escaped(), which
+/// escapes these bidi control characters no matter what, or it's wrapped in
+/// isolate marks by Options::write_isolated().
+///
+/// We can safely assume that there are no newlines (or unicode separators)
+/// in the text because those would get it sent to write_escaped() earlier.
+/// In unicode terms, this is all a single paragraph.
+#[inline(never)]
+fn is_suspicious_bidi(text: impl IntoIterator<Item = char>) -> bool {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Kind {
+        Formatting,
+        Isolate,
+    }
+    const STACK_SIZE: usize = 16;
+    // Can't use a Vec because of no_std
+    let mut stack: [Option<Kind>; STACK_SIZE] = [None; STACK_SIZE];
+    let mut pos = 0;
+    for ch in text {
+        match ch {
+            '\u{202A}' | '\u{202B}' | '\u{202D}' | '\u{202E}' => {
+                if pos >= STACK_SIZE {
+                    // Suspicious amount of nesting.
+                    return true;
+                }
+                stack[pos] = Some(Kind::Formatting);
+                pos += 1;
+            }
+            '\u{202C}' => {
+                if pos == 0 {
+                    // Unpaired terminator.
+                    // Not necessarily dangerous, but suspicious and
+                    // could disrupt preceding text.
+                    return true;
+                }
+                pos -= 1;
+                if stack[pos] != Some(Kind::Formatting) {
+                    // Terminator doesn't match.
+                    // UAX #9 says to pop the stack until we find a match.
+                    // But we'll keep things simple and cautious.
+                    return true;
+                }
+            }
+            '\u{2066}' | '\u{2067}' | '\u{2068}' => {
+                if pos >= STACK_SIZE {
+                    return true;
+                }
+                stack[pos] = Some(Kind::Isolate);
+                pos += 1;
+            }
+            '\u{2069}' => {
+                if pos == 0 {
+                    return true;
+                }
+                pos -= 1;
+                if stack[pos] != Some(Kind::Isolate) {
+                    return true;
+                }
+            }
+            _ => (),
+        }
+    }
+    pos != 0
+}
+
+#[cfg(feature = "native")]
+mod native {
+    use super::*;
+
+    /// An extension trait to apply quoting to strings.
+    ///
+    /// This is implemented on [`str`], [`char`], [`OsStr`] and [`Path`].
+    ///
+    /// With the `windows` feature it's also implemented on `[u16]`, for
+    /// possibly invalid UTF-16. Wide strings are always quoted with PowerShell
+    /// syntax, even on other platforms.
+    ///
+    /// Likewise, with the `unix` feature it's implemented on `[u8]` and
+    /// `CStr`, for possibly invalid UTF-8. Byte strings are always quoted with
+    /// bash/ksh syntax. The terminating NUL of a `CStr` is left out.
+    ///
+    /// For finer control, see the constructors on [`Quoted`].
+    pub trait Quotable {
+        /// Returns an object that implements [`Display`] for printing strings with
+        /// proper quoting and escaping for the platform.
+        ///
+        /// On Unix this corresponds to bash/ksh syntax, on Windows PowerShell syntax
+        /// is used.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use std::path::Path;
+        /// use os_display::Quotable;
+        ///
+        /// let path = Path::new("foo/bar.baz");
+        ///
+        /// println!("Found file {}", path.quote()); // Prints "Found file 'foo/bar.baz'"
+        /// ```
+        fn quote(&self) -> Quoted<'_>;
+
+        /// Like `quote()`, but don't actually add quotes unless necessary because of
+        /// whitespace or special characters.
+        ///
+        /// # Examples
+        ///
+        /// ```
+        /// use std::path::Path;
+        /// use os_display::Quotable;
+        ///
+        /// let foo = Path::new("foo/bar.baz");
+        /// let bar = "foo bar";
+        ///
+        /// println!("{}: Not found", foo.maybe_quote()); // Prints "foo/bar.baz: Not found"
+        /// println!("{}: Not found", bar.maybe_quote()); // Prints "'foo bar': Not found"
+        /// ```
+        fn maybe_quote(&self) -> Quoted<'_> {
+            let mut quoted = self.quote();
+            quoted.opts.force_quote = false;
+            quoted
+        }
+    }
+
+    impl Quotable for str {
+        fn quote(&self) -> Quoted<'_> {
+            Quoted::native(self)
+        }
+    }
+
+    impl Quotable for char {
+        fn quote(&self) -> Quoted<'_> {
+            Quoted::new(Kind::NativeChar(*self))
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl Quotable for OsStr {
+        fn quote(&self) -> Quoted<'_> {
+            Quoted::native_raw(self)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl Quotable for Path {
+        fn quote(&self) -> Quoted<'_> {
+            Quoted::native_raw(self.as_ref())
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    impl Quotable for [u16] {
+        fn quote(&self) -> Quoted<'_> {
+            Quoted::windows_raw(self)
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    impl Quotable for [u8] {
+        fn quote(&self) -> Quoted<'_> {
+            Quoted::unix_raw(self)
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    #[cfg(feature = "std")]
+    impl Quotable for std::ffi::CStr {
+        fn quote(&self) -> Quoted<'_> {
+            Quoted::unix_raw(self.to_bytes())
+        }
+    }
+
+    impl<'a, T: Quotable + ?Sized> From<&'a T> for Quoted<'a> {
+        fn from(val: &'a T) -> Self {
+            val.quote()
+        }
+    }
+
+    /// A slice that's displayed as a list of its items, like `foo 'my file'`.
+    ///
+    /// The items can be anything that can be seen as an `OsStr`, like
+    /// `&str`, `String`, `OsString`, `&Path` or `PathBuf`. Each item is
+    /// quoted only if necessary, as with
+    /// [`maybe_quote`][Quotable::maybe_quote], and they're separated by
+    /// spaces, or by another separator chosen with
+    /// [`QuotedSlice::separator`]. Nothing is allocated. For a command line,
+    /// where the first item gets special treatment, see [`CommandLine`].
+    ///
+    /// # Examples
+    /// ```
+    /// use os_display::QuotedSlice;
+    ///
+    /// let args = ["foo", "my file"];
+    /// let list = QuotedSlice::new(&args);
+    /// assert_eq!(format!("args: {}", list), "args: foo 'my file'");
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the (default) `std` feature.
+    #[cfg(feature = "std")]
+    #[derive(Debug)]
+    pub struct QuotedSlice<'a, T> {
+        items: &'a [T],
+        separator: &'a str,
+    }
+
+    // Derived impls would require T: Copy.
+    #[cfg(feature = "std")]
+    impl<'a, T> Clone for QuotedSlice<'a, T> {
+        fn clone(&self) -> Self {
+            *self
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl<'a, T> Copy for QuotedSlice<'a, T> {}
+
+    #[cfg(feature = "std")]
+    impl<'a, T: AsRef<OsStr>> QuotedSlice<'a, T> {
+        /// Display the items of a slice, separated by spaces.
+        pub fn new(items: &'a [T]) -> Self {
+            QuotedSlice {
+                items,
+                separator: " ",
+            }
+        }
+
+        /// Put something other than a space between the items, like `"\n"`
+        /// for one item per line.
+        ///
+        /// The separator is written as it is, without quoting.
+        pub fn separator(mut self, separator: &'a str) -> Self {
+            self.separator = separator;
+            self
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl<'a, T: AsRef<OsStr>> Display for QuotedSlice<'a, T> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            for (i, item) in self.items.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(self.separator)?;
+                }
+                item.as_ref().maybe_quote().fmt(f)?;
+            }
+            Ok(())
+        }
+    }
+
+    /// Quote each component of a path separately, with
+    /// [`maybe_quote`][Quotable::maybe_quote], so that the structure of the
+    /// path stays visible.
+    ///
+    /// The separators are kept as they are, including a root, repeated
+    /// separators and a trailing separator. On Windows both `\` and `/` are
+    /// separators, and a prefix like `C:` or `\\server\share` is quoted as a
+    /// single component.
+    ///
+    /// This is meant for display. The result works in a Unix shell, but
+    /// PowerShell sees a mix of quoted and bare text as several arguments.
+    ///
+    /// # Examples
+    /// ```
+    /// # #[cfg(unix)] {
+    /// use std::path::Path;
+    /// use os_display::quote_components;
+    ///
+    /// let path = Path::new("/home/user/my file");
+    /// assert_eq!(quote_components(path), "/home/user/'my file'");
+    /// # }
+    /// ```
+    ///
+    /// # Optional
+    /// This requires the (default) `std` feature.
+    #[cfg(feature = "std")]
+    pub fn quote_components(path: &Path) -> std::string::String {
+        use std::string::String;
+
+        fn push_quoted(out: &mut String, part: &OsStr) {
+            // Quoting an empty part would add a component.
+            if !part.is_empty() {
+                part.maybe_quote().quote_into(out);
+            }
+        }
+
+        let mut out = String::new();
+
+        #[cfg(windows)]
+        {
+            use std::ffi::OsString;
+            use std::os::windows::ffi::{OsStrExt, OsStringExt};
+            use std::path::Component;
+
+            let mut units: std::vec::Vec<u16> = path.as_os_str().encode_wide().collect();
+            if let Some(Component::Prefix(prefix)) = path.components().next() {
+                let prefix = prefix.as_os_str();
+                push_quoted(&mut out, prefix);
+                units.drain(..prefix.encode_wide().count());
+            }
+            let mut start = 0;
+            for (i, &unit) in units.iter().enumerate() {
+                if unit == u16::from(b'\\') || unit == u16::from(b'/') {
+                    push_quoted(&mut out, &OsString::from_wide(&units[start..i]));
+                    out.push(char::from(unit as u8));
+                    start = i + 1;
+                }
+            }
+            push_quoted(&mut out, &OsString::from_wide(&units[start..]));
+        }
+
+        #[cfg(any(unix, target_os = "wasi"))]
+        {
+            #[cfg(unix)]
+            use std::os::unix::ffi::OsStrExt;
+            #[cfg(target_os = "wasi")]
+            use std::os::wasi::ffi::OsStrExt;
+
+            let bytes = path.as_os_str().as_bytes();
+            let mut start = 0;
+            for (i, &byte) in bytes.iter().enumerate() {
+                if byte == b'/' {
+                    push_quoted(&mut out, OsStr::from_bytes(&bytes[start..i]));
+                    out.push('/');
+                    start = i + 1;
+                }
+            }
+            push_quoted(&mut out, OsStr::from_bytes(&bytes[start..]));
+        }
+
+        #[cfg(not(any(windows, unix, target_os = "wasi")))]
+        path.as_os_str().maybe_quote().quote_into(&mut out);
+
+        out
+    }
+}
+
+#[cfg(feature = "native")]
+#[cfg(feature = "std")]
+pub use crate::native::quote_components;
+#[cfg(feature = "native")]
+pub use crate::native::Quotable;
+#[cfg(feature = "native")]
+#[cfg(feature = "std")]
+pub use crate::native::QuotedSlice;
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    #![allow(unused)]
+
+    use super::*;
+
+    use std::format;
+    use std::string::{String, ToString};
+    use std::vec;
+    use std::vec::Vec;
+
+    const BOTH_ALWAYS: &[(&str, &str)] = &[
+        ("foo", "'foo'"),
+        ("", "''"),
+        ("foo/bar.baz", "'foo/bar.baz'"),
+        ("can't", r#""can't""#),
+    ];
+    const BOTH_MAYBE: &[(&str, &str)] = &[
+        ("foo", "foo"),
+        ("", "''"),
+        ("foo bar", "'foo bar'"),
+        ("$foo", "'$foo'"),
+        ("-", "-"),
+        ("a#b", "a#b"),
+        ("#ab", "'#ab'"),
+        ("a~b", "a~b"),
+        ("~backup", "'~backup'"),
+        ("~", "'~'"),
+        ("!", "'!'"),
+        ("}", ("'}'")),
+        ("\u{200B}", "'\u{200B}'"),
+        ("\u{200B}a", "'\u{200B}a'"),
+        ("a\u{200B}", "a\u{200B}"),
+        ("\u{2000}", "'\u{2000}'"),
+        ("a\u{00A0}b", "'a\u{00A0}b'"),
+        ("a\u{2003}b", "'a\u{2003}b'"),
+        // Odd but safe bidi
+        (
+            "\u{2067}\u{2066}abc\u{2069}\u{2066}def\u{2069}\u{2069}",
+            "'\u{2067}\u{2066}abc\u{2069}\u{2066}def\u{2069}\u{2069}'",
+        ),
+    ];
+
+    const UNIX_ALWAYS: &[(&str, &str)] = &[
+        (r#"can'"t"#, r#"'can'\''"t'"#),
+        (r#"can'$t"#, r#"'can'\''$t'"#),
+        ("foo\nb\ta\r\\\0`r", r#"$'foo\nb\ta\r\\\x00`r'"#),
+        ("foo\x02", r#"$'foo\x02'"#),
+        ("foo\x7F", r#"$'foo\x7F'"#),
+        (r#"'$''"#, r#"\''$'\'\'"#),
+    ];
+    const UNIX_MAYBE: &[(&str, &str)] = &[
+        ("-x", "-x"),
+        ("a,b", "a,b"),
+        ("a\\b", "'a\\b'"),
+        ("\x02AB", "$'\\x02'$'AB'"),
+        ("\x02GH", "$'\\x02GH'"),
+        ("\t", r#"$'\t'"#),
+        ("\r", r#"$'\r'"#),
+        ("\u{85}", r#"$'\xC2\x85'"#),
+        ("\u{85}a", r#"$'\xC2\x85'$'a'"#),
+        ("\u{2028}", r#"$'\xE2\x80\xA8'"#),
+        // Dangerous bidi
+        (
+            "user\u{202E} \u{2066}// Check if admin\u{2069} \u{2066}",
+            r#"$'user\xE2\x80\xAE \xE2\x81\xA6// Check if admin\xE2\x81\xA9 \xE2\x81\xA6'"#,
+        ),
+    ];
+    const UNIX_RAW: &[(&[u8], &str)] = &[
+        (b"foo\xFF", r#"$'foo\xFF'"#),
+        (b"foo\xFFbar", r#"$'foo\xFF'$'bar'"#),
+    ];
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix() {
+        for &(orig, expected) in UNIX_ALWAYS.iter().chain(BOTH_ALWAYS) {
+            assert_eq!(Quoted::unix(orig).to_string(), expected);
+        }
+        for &(orig, expected) in UNIX_MAYBE.iter().chain(BOTH_MAYBE) {
+            assert_eq!(Quoted::unix(orig).force(false).to_string(), expected);
+        }
+        for &(orig, expected) in UNIX_RAW {
+            assert_eq!(Quoted::unix_raw(orig).to_string(), expected);
+        }
+        let bidi_ok = nest_bidi(16);
+        assert_eq!(
+            Quoted::unix(&bidi_ok).to_string(),
+            "'".to_string() + &bidi_ok + "'"
+        );
+        let bidi_too_deep = nest_bidi(17);
+        assert!(Quoted::unix(&bidi_too_deep).to_string().starts_with('$'));
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_safe_bare() {
+        for &(orig, expected) in UNIX_MAYBE.iter().chain(BOTH_MAYBE) {
+            let quoted = Quoted::unix(orig);
+            assert_eq!(quoted.is_safe_bare(), orig == expected);
+            // Forced quoting doesn't matter.
+            assert_eq!(quoted.force(false).is_safe_bare(), orig == expected);
+        }
+        assert!(Quoted::unix("foo").is_safe_bare());
+        assert_eq!(Quoted::unix("foo").to_string(), "'foo'");
+        assert!(!Quoted::unix("").is_safe_bare());
+        assert!(!Quoted::unix("*").is_safe_bare());
+        assert!(Quoted::unix("*").glob_safe(false).is_safe_bare());
+        assert!(!Quoted::unix("-x").protect_dashes(true).is_safe_bare());
+        assert!(!Quoted::unix(&nest_bidi(17)).is_safe_bare());
+        assert!(Quoted::unix_raw(b"foo").is_safe_bare());
+        assert!(!Quoted::unix_raw(b"foo\xFF").is_safe_bare());
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_color() {
+        const CASES: &[(&str, &str)] = &[
+            ("foo", "foo"),
+            ("a b", "\x1B[36m'\x1B[39ma b\x1B[36m'\x1B[39m"),
+            (
+                "a\tb",
+                "\x1B[36m$'\x1B[39ma\x1B[36m\\t\x1B[39mb\x1B[36m'\x1B[39m",
+            ),
+            ("it's", "\x1B[36m\"\x1B[39mit's\x1B[36m\"\x1B[39m"),
+            (
+                "'$",
+                "\x1B[36m\\'\x1B[39m\x1B[36m'\x1B[39m$\x1B[36m'\x1B[39m",
+            ),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(
+                Quoted::unix(orig).force(false).color(true).to_string(),
+                expected
+            );
+        }
+        assert_eq!(
+            Quoted::unix_raw(b"\xFF").color(true).to_string(),
+            "\x1B[36m$'\x1B[39m\x1B[36m\\xFF\x1B[39m\x1B[36m'\x1B[39m"
+        );
+        assert_eq!(
+            Quoted::unix("a\tb").color(false).to_string(),
+            Quoted::unix("a\tb").to_string()
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_max_width() {
+        const CASES: &[(&str, usize, &str)] = &[
+            ("abcdefghijklmnopqrstuvwxyz", 10, "'abcdefg'…"),
+            (
+                "abcdefghijklmnopqrstuvwxyz",
+                28,
+                "'abcdefghijklmnopqrstuvwxyz'",
+            ),
+            (
+                "abcdefghijklmnopqrstuvwxyz",
+                27,
+                "'abcdefghijklmnopqrstuvwx'…",
+            ),
+            ("テキストファイル", 10, "'テキス'…"),
+            ("テキストファイル", 9, "'テキス'…"),
+            ("テキストファイル", 8, "'テキ'…"),
+            ("ab\tcdefgh", 10, "$'ab\\tcd'…"),
+            ("ab\tcdefgh", 8, "$'ab\\t'…"),
+            ("ab\tcdefgh", 7, "'ab'…"),
+            ("abc", 4, "'a'…"),
+            ("abc", 3, "…"),
+            ("abc", 1, "…"),
+            ("abc", 0, ""),
+        ];
+        for &(orig, max_width, expected) in CASES {
+            assert_eq!(
+                Quoted::unix(orig).max_width(max_width).to_string(),
+                expected
+            );
+        }
+        assert_eq!(
+            Quoted::unix("abcdefghijklmnopqrstuvwxyz")
+                .force(false)
+                .max_width(10)
+                .to_string(),
+            "abcdefghi…"
+        );
+
+        // Escapes aren't split, and neither is valid UTF-8.
+        const RAW: &[(&[u8], usize, &str)] = &[
+            (b"ab\xFF\xFFcd", 12, r"$'ab\xFF'…"),
+            (b"ab\xFF\xFFcd", 16, r"$'ab\xFF\xFF'…"),
+            (b"ab\xFF\xFFcd", 19, r"$'ab\xFF\xFF'$'cd'"),
+            (b"ab\xFF\xFFcd", 8, r"'ab'…"),
+            (b"ab\xC3\xA9\xC3", 7, "'ab\u{E9}'…"),
+        ];
+        for &(orig, max_width, expected) in RAW {
+            assert_eq!(
+                Quoted::unix_raw(orig).max_width(max_width).to_string(),
+                expected
+            );
+        }
+
+        // Color codes don't take up space.
+        assert_eq!(
+            Quoted::unix("abcdef").color(true).max_width(5).to_string(),
+            "\x1B[36m'\x1B[39mab\x1B[36m'\x1B[39m…"
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_max_output_len() {
+        let nuls = "\0".repeat(100_000);
+        const CASES: &[(usize, &str)] = &[
+            (20, r"$'\x00\x00\x00'…"),
+            (17, r"$'\x00\x00'…"),
+            (10, r"$'\x00'…"),
+            (9, "…"),
+            (3, "…"),
+            (2, ""),
+        ];
+        for &(max_len, expected) in CASES {
+            let quoted = Quoted::unix(&nuls).max_output_len(max_len).to_string();
+            assert_eq!(quoted, expected);
+            assert!(quoted.len() <= max_len);
+        }
+        assert_eq!(Quoted::unix("abc").max_output_len(5).to_string(), "'abc'");
+        assert_eq!(
+            Quoted::unix("abcdef").max_output_len(7).to_string(),
+            "'ab'…"
+        );
+        #[cfg(feature = "windows")]
+        assert_eq!(
+            Quoted::windows("\0\0\0\0").max_output_len(9).to_string(),
+            "\"`0`0\"…"
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_pad_to() {
+        const CASES: &[(&str, usize, &str)] = &[
+            ("abc", 8, "'abc'   "),
+            ("テキスト", 12, "'テキスト'  "),
+            ("a\tb", 10, "$'a\\tb'   "),
+            ("e\u{301}", 4, "'e\u{301}' "),
+            ("abcdef", 3, "'abcdef'"),
+            ("abc", 5, "'abc'"),
+        ];
+        for &(orig, width, expected) in CASES {
+            let quoted = Quoted::unix(orig);
+            assert_eq!(quoted.pad_to(width).to_string(), expected);
+            assert_eq!(
+                width::width(&quoted.pad_to(width), false),
+                core::cmp::max(width, quoted.display_width())
+            );
+        }
+        assert_eq!(Quoted::unix("テキスト").display_width(), 10);
+        assert_eq!(Quoted::unix("a\tb").display_width(), 7);
+        assert_eq!(
+            Quoted::unix("abc").force(false).pad_to(5).to_string(),
+            "abc  "
+        );
+        assert_eq!(format!("{:#}", Quoted::unix("abc").pad_to(5)), "abc  ");
+        assert_eq!(
+            Quoted::unix("abcdef").max_width(5).pad_to(6).to_string(),
+            "'ab'… "
+        );
+        assert_eq!(
+            Quoted::unix("a b").color(true).pad_to(6).to_string(),
+            "\x1B[36m'\x1B[39ma b\x1B[36m'\x1B[39m "
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_east_asian() {
+        const CASES: &[(&str, usize, usize)] = &[
+            ("\u{2460}", 1, 2),
+            ("\u{25CB}", 1, 2),
+            ("abc", 3, 3),
+            ("テキスト", 8, 8),
+        ];
+        for &(orig, narrow, wide) in CASES {
+            let quoted = Quoted::unix(orig).force(false);
+            assert_eq!(quoted.display_width(), narrow);
+            assert_eq!(quoted.east_asian(false).display_width(), narrow);
+            assert_eq!(quoted.east_asian(true).display_width(), wide);
+        }
+        let quoted = Quoted::unix("\u{2460}\u{2460}\u{2460}");
+        assert_eq!(
+            quoted.max_width(5).to_string(),
+            "'\u{2460}\u{2460}\u{2460}'"
+        );
+        assert_eq!(
+            quoted.east_asian(true).max_width(5).to_string(),
+            "'\u{2460}'…"
+        );
+        assert_eq!(
+            quoted.force(false).pad_to(4).to_string(),
+            "\u{2460}\u{2460}\u{2460} "
+        );
+        assert_eq!(
+            quoted.force(false).east_asian(true).pad_to(8).to_string(),
+            "\u{2460}\u{2460}\u{2460}  "
+        );
+        // Escaping doesn't change.
+        assert_eq!(
+            quoted.east_asian(true).to_string(),
+            quoted.east_asian(false).to_string()
+        );
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_pad_to() {
+        assert_eq!(Quoted::windows("abc").pad_to(7).to_string(), "'abc'  ");
+        assert_eq!(
+            Quoted::windows("テキスト").pad_to(12).to_string(),
+            "'テキスト'  "
+        );
+        assert_eq!(Quoted::windows("a\tb").pad_to(8).to_string(), "\"a`tb\"  ");
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_alternate() {
+        for &(orig, expected) in UNIX_MAYBE.iter().chain(BOTH_MAYBE) {
+            assert_eq!(format!("{:#}", Quoted::unix(orig)), expected);
+        }
+        assert_eq!(format!("{:#}", Quoted::unix("foo")), "foo");
+        assert_eq!(format!("{:#}", Quoted::unix("foo").max_width(3)), "foo");
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_unquote() {
+        let roundtrip = |text: &str| {
+            let quoted = [
+                Quoted::unix(text),
+                Quoted::unix(text).force(false),
+                Quoted::unix(text).prefer(QuoteChar::Double),
+                Quoted::unix(text).symbolic_escapes(true),
+                Quoted::unix(text).symbolic_escapes(false),
+                Quoted::unix(text).ascii_only(true),
+                Quoted::unix(text).interactive(true),
+            ];
+            for quoted in quoted.iter() {
+                assert_eq!(unquote_unix(&quoted.to_string()).unwrap(), text);
+            }
+        };
+        for &(orig, _) in UNIX_ALWAYS
+            .iter()
+            .chain(UNIX_MAYBE)
+            .chain(BOTH_ALWAYS)
+            .chain(BOTH_MAYBE)
+        {
+            roundtrip(orig);
+        }
+        roundtrip(&nest_bidi(17));
+        roundtrip("\u{1F600}\x00\u{85}");
+
+        const CASES: &[(&str, &str)] = &[
+            ("foo\\ bar", "foo bar"),
+            ("foo\\\nbar", "foobar"),
+            (r#""a\$b\c""#, r#"a$b\c"#),
+            (r"$'\x41\x4'", "A\x04"),
+            (r"$'\101\0'", "A\0"),
+            (r"$'\u00e9\U0001F600'", "\u{E9}\u{1F600}"),
+            (r"$'\x\u\q'", r"\x\u\q"),
+            (r"$'\cA\e\E\?'", "\x01\x1B\x1B?"),
+            (r#"$'\'\"'"#, r#"'""#),
+            ("''\"\"$''", ""),
+        ];
+        for &(quoted, expected) in CASES {
+            assert_eq!(unquote_unix(quoted).unwrap(), expected);
+        }
+
+        const ERRORS: &[(&str, ParseError)] = &[
+            ("'foo", ParseError::UnterminatedQuote),
+            ("\"foo", ParseError::UnterminatedQuote),
+            ("\"foo\\\"", ParseError::UnterminatedQuote),
+            ("$'foo\\'", ParseError::UnterminatedQuote),
+            ("foo\\", ParseError::UnterminatedEscape),
+            (r"$'\uD800'", ParseError::InvalidEscape),
+            (r"$'\xFF'", ParseError::InvalidUtf8),
+        ];
+        for &(quoted, err) in ERRORS {
+            assert_eq!(unquote_unix(quoted), Err(err));
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_prefer() {
+        const DOUBLE: &[(&str, &str)] = &[
+            ("can't", r#""can't""#),
+            ("foo", r#""foo""#),
+            ("a\"$b`\\", r#""a\"\$b\`\\""#),
+            ("foo\n", r#"$'foo\n'"#),
+            ("dir/foo.txt", r#""dir/foo.txt""#),
+        ];
+        const SINGLE: &[(&str, &str)] = &[
+            ("can't", r#"'can'\''t'"#),
+            ("foo", "'foo'"),
+            ("a\"$b", r#"'a"$b'"#),
+            ("foo\n", r#"$'foo\n'"#),
+        ];
+        for &(orig, expected) in DOUBLE {
+            assert_eq!(
+                Quoted::unix(orig).prefer(QuoteChar::Double).to_string(),
+                expected
+            );
+        }
+        for &(orig, expected) in SINGLE {
+            assert_eq!(
+                Quoted::unix(orig).prefer(QuoteChar::Single).to_string(),
+                expected
+            );
+        }
+        assert_eq!(
+            Quoted::unix("foo")
+                .prefer(QuoteChar::Double)
+                .force(false)
+                .to_string(),
+            "foo"
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_bidi() {
+        let text = "\u{202E}xyz";
+        assert_eq!(Quoted::unix(text).to_string(), r#"$'\xE2\x80\xAExyz'"#);
+        assert_eq!(
+            Quoted::unix(text).bidi(BidiPolicy::Allow).to_string(),
+            "'\u{202E}xyz'"
+        );
+        assert_eq!(
+            Quoted::unix("\u{202E}\n")
+                .bidi(BidiPolicy::Allow)
+                .to_string(),
+            "$'\u{202E}\\n'"
+        );
+
+        let isolated = |text| Quoted::unix(text).bidi(BidiPolicy::Isolate).to_string();
+        assert_eq!(isolated(text), "\u{2066}'\u{202E}xyz'\u{2069}");
+        assert_eq!(
+            isolated("\u{202B}\u{5E9}\u{5DC}\u{5D5}\u{5DD}"),
+            "\u{2066}'\u{202B}\u{5E9}\u{5DC}\u{5D5}\u{5DD}'\u{2069}"
+        );
+        assert_eq!(isolated("\u{2067}x\u{2069}"), "'\u{2067}x\u{2069}'");
+        // If we're escaping anyway the control codes are escaped too.
+        assert_eq!(isolated("\u{202E}\n"), r#"$'\xE2\x80\xAE\n'"#);
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_ascii_only() {
+        assert_eq!(Quoted::unix("café").to_string(), "'café'");
+        assert_eq!(
+            Quoted::unix("café").ascii_only(true).to_string(),
+            r#"$'caf\xC3\xA9'"#
+        );
+        assert_eq!(
+            Quoted::unix("\u{1F600} it's").ascii_only(true).to_string(),
+            r#"$'\xF0\x9F\x98\x80 it\'s'"#
+        );
+        assert_eq!(
+            Quoted::unix("foo")
+                .ascii_only(true)
+                .force(false)
+                .to_string(),
+            "foo"
+        );
+    }
+
+    #[cfg(any(feature = "unix", feature = "windows"))]
+    #[test]
+    fn allowed() {
+        fn printable(ch: char) -> bool {
+            ('\x20'..='\x7E').contains(&ch)
+        }
+
+        #[cfg(feature = "unix")]
+        {
+            let quoted = |text| Quoted::unix(text).allowed(Some(printable));
+            assert_eq!(quoted("café\nx").to_string(), r#"$'caf\xC3\xA9\nx'"#);
+            assert_eq!(quoted("a b").to_string(), "'a b'");
+            assert_eq!(quoted("foo").force(false).to_string(), "foo");
+            assert_eq!(quoted("café").allowed(None).to_string(), "'café'");
+            // ASCII can be ruled out too.
+            let no_x = Quoted::unix("xyz").allowed(Some(|ch| ch != 'x'));
+            assert_eq!(no_x.force(false).to_string(), r#"$'\x78yz'"#);
+            assert!(!no_x.is_safe_bare());
+        }
+        #[cfg(feature = "windows")]
+        {
+            let quoted = |text| Quoted::windows(text).allowed(Some(printable));
+            assert_eq!(quoted("café\nx").to_string(), r#""caf`u{E9}`nx""#);
+            assert_eq!(quoted("a b").to_string(), "'a b'");
+            assert_eq!(quoted("foo").force(false).to_string(), "foo");
+            let no_x = Quoted::windows("xyz").allowed(Some(|ch| ch != 'x'));
+            assert_eq!(no_x.force(false).to_string(), r#""`u{78}yz""#);
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_escape_invisible() {
+        const CASES: &[(&str, &str)] = &[
+            ("x\u{200D}y", r#"$'x\xE2\x80\x8Dy'"#),
+            ("\u{2764}\u{FE0F}", "$'\u{2764}\\xEF\\xB8\\x8F'"),
+            ("soft\u{AD}hyphen", r#"$'soft\xC2\xADhyphen'"#),
+            ("tag\u{E0041}", r#"$'tag\xF3\xA0\x81\x81'"#),
+            ("plain", "'plain'"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(
+                Quoted::unix(orig).escape_invisible(true).to_string(),
+                expected
+            );
+            assert!(!Quoted::unix(orig).to_string().starts_with('$'));
+        }
+        // Bidi control codes follow their own policy.
+        assert_eq!(
+            Quoted::unix("\u{2067}x\u{2069}")
+                .escape_invisible(true)
+                .to_string(),
+            "'\u{2067}x\u{2069}'"
+        );
+        assert_eq!(
+            Quoted::unix("\u{202E}")
+                .escape_invisible(true)
+                .bidi(BidiPolicy::Allow)
+                .to_string(),
+            "'\u{202E}'"
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_escape_dialect() {
+        const CASES: &[(&str, &str, &str)] = &[
+            ("a\tb", r#"$'a\tb'"#, r#""a\tb""#),
+            ("a'b\\c\n", r#"$'a\'b\\c\n'"#, r#""a'b\\c\n""#),
+            ("\"$x`\n", r#"$'"$x`\n'"#, r#""\"\$x\`\n""#),
+            ("\x02AB", r#"$'\x02'$'AB'"#, r#""\x02""AB""#),
+        ];
+        for &(orig, ansi_c, double) in CASES {
+            assert_eq!(
+                Quoted::unix(orig)
+                    .escape_dialect(EscapeDialect::AnsiC)
+                    .to_string(),
+                ansi_c
+            );
+            assert_eq!(
+                Quoted::unix(orig)
+                    .escape_dialect(EscapeDialect::DoubleQuoteBackslash)
+                    .to_string(),
+                double
+            );
+        }
+        assert_eq!(
+            Quoted::unix_raw(b"a\xFFb")
+                .escape_dialect(EscapeDialect::DoubleQuoteBackslash)
+                .to_string(),
+            r#""a\xFF""b""#
+        );
+        assert_eq!(
+            Quoted::unix("a b")
+                .escape_dialect(EscapeDialect::DoubleQuoteBackslash)
+                .to_string(),
+            "'a b'"
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_control_style() {
+        const CASES: &[(&str, &str, &str)] = &[
+            ("a\tb", "a^Ib", "'a^Ib'"),
+            ("\0", "^@", "'^@'"),
+            ("\x1B[0m", "'^[[0m'", "'^[[0m'"),
+            ("\x7F", "^?", "'^?'"),
+            ("a b\n", "'a b^J'", "'a b^J'"),
+            ("it's\r", "\"it's^M\"", "\"it's^M\""),
+            ("'$\x01", r#"\''$^A'"#, r#"\''$^A'"#),
+            // Unicode control codes are still escaped.
+            ("\t\u{85}", r#"$'\t\xC2\x85'"#, r#"$'\t\xC2\x85'"#),
+        ];
+        for &(orig, maybe, always) in CASES {
+            let quoted = Quoted::unix(orig).control_style(ControlStyle::Caret);
+            assert_eq!(quoted.force(false).to_string(), maybe);
+            assert_eq!(quoted.to_string(), always);
+            assert_eq!(
+                Quoted::unix(orig)
+                    .control_style(ControlStyle::Escape)
+                    .to_string(),
+                Quoted::unix(orig).to_string()
+            );
+        }
+        assert_eq!(
+            Quoted::unix("a\tb")
+                .control_style(ControlStyle::Caret)
+                .color(true)
+                .to_string(),
+            "\x1B[36m'\x1B[39ma\x1B[36m^I\x1B[39mb\x1B[36m'\x1B[39m"
+        );
+        assert!(!Quoted::unix("a\tb")
+            .control_style(ControlStyle::Caret)
+            .is_safe_bare());
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_control_style_strip() {
+        const CASES: &[(&str, &str, &str)] = &[
+            ("a\tb\nc", "abc", "'a?b?c'"),
+            ("\t\n", "''", "'?'"),
+            ("\x01~x", "'~x'", "'?~x'"),
+            ("a\x01\x02 b", "'a b'", "'a? b'"),
+            ("it's\r", "\"it's\"", "\"it's?\""),
+            // Unicode control codes are still escaped.
+            ("\t\u{85}", r#"$'\xC2\x85'"#, r#"$'?\xC2\x85'"#),
+        ];
+        for &(orig, strip, replace) in CASES {
+            let quoted = Quoted::unix(orig).force(false);
+            assert_eq!(quoted.control_style(ControlStyle::Strip).to_string(), strip);
+            assert_eq!(
+                quoted.control_style(ControlStyle::Replace).to_string(),
+                replace
+            );
+        }
+        assert_eq!(
+            Quoted::unix_raw(b"a\x01\xFF")
+                .control_style(ControlStyle::Strip)
+                .to_string(),
+            r#"$'a\xFF'"#
+        );
+        assert!(!Quoted::unix("a\tb")
+            .control_style(ControlStyle::Strip)
+            .is_safe_bare());
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_control_style_strip() {
+        const CASES: &[(&str, &str, &str)] = &[
+            ("a\tb\nc", "abc", "'a?b?c'"),
+            ("\t\n", "''", "'?'"),
+            ("a\x01\x02 b", "'a b'", "'a? b'"),
+            ("it's\r", "\"it's\"", "\"it's?\""),
+        ];
+        for &(orig, strip, replace) in CASES {
+            let quoted = Quoted::windows(orig).force(false);
+            assert_eq!(quoted.control_style(ControlStyle::Strip).to_string(), strip);
+            assert_eq!(
+                quoted.control_style(ControlStyle::Replace).to_string(),
+                replace
+            );
+        }
+        let units = [0x61, 0x09, 0x0A, 0xD800];
+        let quoted = Quoted::windows_raw(&units);
+        assert_eq!(
+            quoted.control_style(ControlStyle::Strip).to_string(),
+            r#""a`u{D800}""#
+        );
+        assert_eq!(
+            quoted.control_style(ControlStyle::Replace).to_string(),
+            r#""a?`u{D800}""#
+        );
+    }
+
+    #[cfg(all(feature = "unix", feature = "windows"))]
+    #[test]
+    fn newline_style() {
+        const TEXT: &str = "a\r\nb\nc\rd";
+        // (style, unix, unix with carets, windows, windows with a surrogate)
+        const CASES: &[(NewlineStyle, &str, &str, &str, &str)] = &[
+            (
+                NewlineStyle::Control,
+                r"$'a\r\nb\nc\rd'",
+                "'a^M^Jb^Jc^Md'",
+                r#""a`r`nb`nc`rd""#,
+                r#""a`r`nb`nc`rd`u{D800}""#,
+            ),
+            (
+                NewlineStyle::JoinCrlf,
+                r"$'a\nb\nc\rd'",
+                "'a^Jb^Jc^Md'",
+                r#""a`nb`nc`rd""#,
+                r#""a`nb`nc`rd`u{D800}""#,
+            ),
+            (
+                NewlineStyle::Normalize,
+                r"$'a\nb\nc\nd'",
+                "'a^Jb^Jc^Jd'",
+                r#""a`nb`nc`nd""#,
+                r#""a`nb`nc`nd`u{D800}""#,
+            ),
+            (
+                NewlineStyle::Strip,
+                "'abcd'",
+                "'abcd'",
+                "'abcd'",
+                r#""abcd`u{D800}""#,
+            ),
+        ];
+        for &(style, unix, caret, windows, windows_raw) in CASES {
+            let quoted = Quoted::unix(TEXT).newline_style(style);
+            assert_eq!(quoted.to_string(), unix);
+            let caret_quoted = quoted.control_style(ControlStyle::Caret);
+            assert_eq!(caret_quoted.to_string(), caret);
+            let raw = Quoted::unix_raw(TEXT.as_bytes()).newline_style(style);
+            assert_eq!(raw.to_string(), unix);
+
+            let quoted = Quoted::windows(TEXT).newline_style(style);
+            assert_eq!(quoted.to_string(), windows);
+            // Invalid text goes straight to escaping.
+            let mut units: Vec<u16> = TEXT.encode_utf16().collect();
+            units.push(0xD800);
+            let raw = Quoted::windows_raw(&units).newline_style(style);
+            assert_eq!(raw.to_string(), windows_raw);
+        }
+
+        // Other control codes stay escaped.
+        assert_eq!(
+            Quoted::unix("a\n\tb")
+                .newline_style(NewlineStyle::Strip)
+                .to_string(),
+            r"$'a\tb'"
+        );
+        assert_eq!(
+            Quoted::windows("\r\n")
+                .newline_style(NewlineStyle::Strip)
+                .force(false)
+                .to_string(),
+            "''"
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_control_style_meta() {
+        const BYTES: &[(u8, &str)] = &[
+            (0x80, "M-^@"),
+            (0x89, "M-^I"),
+            (0x9B, "M-^["),
+            (0x9F, "M-^_"),
+            (0xA0, "M- "),
+            (0xC1, "M-A"),
+            (0xE9, "M-i"),
+            (0xFE, "M-~"),
+            (0xFF, "M-^?"),
+        ];
+        for &(byte, expected) in BYTES {
+            let quoted = Quoted::unix_raw(core::slice::from_ref(&byte))
+                .control_style(ControlStyle::Caret)
+                .force(false);
+            assert_eq!(quoted.to_string(), expected);
+        }
+        for byte in 0x80..=0xFF {
+            let low = byte & 0x7F;
+            let expected = if low < 0x20 || low == 0x7F {
+                format!("M-^{}", (low ^ 0x40) as char)
+            } else {
+                format!("M-{}", low as char)
+            };
+            let quoted = Quoted::unix_raw(&[byte])
+                .control_style(ControlStyle::Caret)
+                .force(false)
+                .to_string();
+            assert_eq!(quoted, expected);
+        }
+
+        const CASES: &[(&[u8], &str, &str)] = &[
+            (b"a\tb\xFF", "a^IbM-^?", "'a^IbM-^?'"),
+            (b"a b\xFF", "'a bM-^?'", "'a bM-^?'"),
+            (b"it's\x80", r#""it'sM-^@""#, r#""it'sM-^@""#),
+            (b"\xFF'$", r#"'M-^?'\''$'"#, r#"'M-^?'\''$'"#),
+            // Valid UTF-8 is left alone.
+            (b"\xC3\xA9\xFF", "\u{E9}M-^?", "'\u{E9}M-^?'"),
+            // Unicode control codes are still escaped.
+            (b"\xFF\xC2\x85", r#"$'\xFF\xC2\x85'"#, r#"$'\xFF\xC2\x85'"#),
+        ];
+        for &(orig, maybe, always) in CASES {
+            let quoted = Quoted::unix_raw(orig).control_style(ControlStyle::Caret);
+            assert_eq!(quoted.force(false).to_string(), maybe);
+            assert_eq!(quoted.to_string(), always);
+        }
+        assert_eq!(Quoted::unix_raw(b"\xFF").to_string(), r#"$'\xFF'"#);
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_always_ansi_c() {
+        const CASES: &[(&str, &str, &str)] = &[
+            ("foo", "foo", "$'foo'"),
+            ("", "''", "$''"),
+            ("foo bar", "'foo bar'", "$'foo bar'"),
+            ("it's", r#""it's""#, r"$'it\'s'"),
+            (r#"'"$`\"#, r#"\''"$`\'"#, r#"$'\'"$`\\'"#),
+            ("~x", "'~x'", "$'~x'"),
+            ("foo\n", r"$'foo\n'", r"$'foo\n'"),
+        ];
+        for &(orig, default, ansi_c) in CASES {
+            assert_eq!(Quoted::unix(orig).force(false).to_string(), default);
+            assert_eq!(Quoted::unix(orig).always_ansi_c(true).to_string(), ansi_c);
+        }
+        // Bare text stays bare.
+        assert_eq!(
+            Quoted::unix("foo")
+                .force(false)
+                .always_ansi_c(true)
+                .to_string(),
+            "foo"
+        );
+        assert_eq!(
+            Quoted::unix("it's")
+                .always_ansi_c(true)
+                .escape_dialect(EscapeDialect::DoubleQuoteBackslash)
+                .to_string(),
+            r#""it's""#
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_quote_preference() {
+        use crate::QuotePreference::{AnsiC, Double, Single};
+
+        const CASES: &[(&str, &[QuotePreference], &str)] = &[
+            (r#"it's "x""#, &[], r#"'it'\''s "x"'"#),
+            (r#"it's "x""#, &[Single, Double], r#"'it'\''s "x"'"#),
+            (r#"it's "x""#, &[Double, Single], r#""it's \"x\"""#),
+            (r#"it's "x""#, &[Double, Double, Single], r#""it's \"x\"""#),
+            (r#"it's "x""#, &[Single, Double, AnsiC], r#"$'it\'s "x"'"#),
+            (r#"it's "x""#, &[AnsiC, Single], r#"$'it\'s "x"'"#),
+            ("it's", &[Single, Double], r#""it's""#),
+            ("it's", &[Single, AnsiC], r"$'it\'s'"),
+            ("$x", &[Double, Single], "'$x'"),
+            ("$x", &[Double], r#""\$x""#),
+            ("foo", &[Double], r#""foo""#),
+            ("foo", &[AnsiC], "$'foo'"),
+            ("foo\n", &[Single], r"$'foo\n'"),
+        ];
+        for &(orig, order, expected) in CASES {
+            assert_eq!(
+                Quoted::unix(orig).quote_preference(order).to_string(),
+                expected
+            );
+            // It wins over prefer().
+            assert_eq!(
+                Quoted::unix(orig)
+                    .prefer(QuoteChar::Double)
+                    .quote_preference(order)
+                    .to_string(),
+                if order.is_empty() {
+                    Quoted::unix(orig).prefer(QuoteChar::Double).to_string()
+                } else {
+                    expected.to_string()
+                }
+            );
+        }
+
+        assert_eq!(
+            Quoted::unix("foo")
+                .force(false)
+                .quote_preference(&[AnsiC])
+                .to_string(),
+            "foo"
+        );
+        // $'' isn't safe for !.
+        assert_eq!(
+            Quoted::unix(r#"it's "x"!"#)
+                .interactive(true)
+                .quote_preference(&[AnsiC, Double])
+                .to_string(),
+            r#"'it'\''s "x"!'"#
+        );
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_quote_preference() {
+        use crate::QuotePreference::{AnsiC, Double, Single};
+
+        const CASES: &[(&str, &[QuotePreference], &str)] = &[
+            (r#"it's "x""#, &[Single, Double], r#"'it''s "x"'"#),
+            (r#"it's "x""#, &[Double, Single], r#""it's `"x`"""#),
+            (r#"it's "x""#, &[AnsiC], r#""it's `"x`"""#),
+            ("it's", &[Single, Double], r#""it's""#),
+            ("it's", &[Single, AnsiC], r#""it's""#),
+            ("foo", &[Double], r#""foo""#),
+            (r"foo\", &[Double], r"'foo\'"),
+            (r#"it's "x"\"#, &[Double, AnsiC], r#"'it''s "x"\'"#),
+        ];
+        for &(orig, order, expected) in CASES {
+            assert_eq!(
+                Quoted::windows(orig).quote_preference(order).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_minimize() {
+        // (orig, default, minimized)
+        const CASES: &[(&str, &str, &str)] = &[
+            (r#"it's "x""#, r#"'it'\''s "x"'"#, r#""it's \"x\"""#),
+            (r#"'"'"#, r#"\''"'\'"#, r#""'\"'""#),
+            (r#""""'"#, r#"'"""'\'"#, r#"'"""'\'"#),
+            (r#"x'y'z$$$""#, r#"'x'\''y'\''z$$$"'"#, r#"$'x\'y\'z$$$"'"#),
+            // Simple quotes can't be beaten.
+            ("it's", r#""it's""#, r#""it's""#),
+            ("$x", "'$x'", "'$x'"),
+            // Control codes leave no choice.
+            ("'\"\n", r#"$'\'"\n'"#, r#"$'\'"\n'"#),
+        ];
+        for &(orig, default, minimized) in CASES {
+            let quoted = Quoted::unix(orig);
+            assert_eq!(quoted.to_string(), default);
+            assert_eq!(quoted.minimize(true).to_string(), minimized);
+        }
+
+        // Ties go to the preferred quote.
+        assert_eq!(
+            Quoted::unix(r#"'a"b"#).minimize(true).to_string(),
+            r#"\''a"b'"#
+        );
+        assert_eq!(
+            Quoted::unix(r#"'a"b"#)
+                .minimize(true)
+                .prefer(QuoteChar::Double)
+                .to_string(),
+            r#""'a\"b""#
+        );
+
+        // Color doesn't count.
+        assert_eq!(
+            Quoted::unix(r#"'"'"#)
+                .minimize(true)
+                .color(true)
+                .to_string(),
+            "\x1B[36m\"\x1B[39m'\x1B[36m\\\"\x1B[39m'\x1B[36m\"\x1B[39m"
+        );
+
+        // ! can't be put in double quotes in interactive mode.
+        assert_eq!(
+            Quoted::unix(r#"'"'!"#)
+                .minimize(true)
+                .interactive(true)
+                .to_string(),
+            r#"\''"'\''!'"#
+        );
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_minimize() {
+        // (orig, default, minimized)
+        const CASES: &[(&str, &str, &str)] = &[
+            ("''\"", r#"'''''"'"#, r#""''`"""#),
+            ("'\"\"", r#"'''""'"#, r#"'''""'"#),
+            ("it's $x", "'it''s $x'", "'it''s $x'"),
+            // A backslash at the end rules out double quotes.
+            ("''\"\\", r#"'''''"\'"#, r#"'''''"\'"#),
+        ];
+        for &(orig, default, minimized) in CASES {
+            let quoted = Quoted::windows(orig);
+            assert_eq!(quoted.to_string(), default);
+            assert_eq!(quoted.minimize(true).to_string(), minimized);
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_here_string() {
+        const CASES: &[(&str, &str)] = &[
+            ("foo\nbar", "@'\nfoo\nbar\n'@"),
+            ("it's\n$x", "@'\nit's\n$x\n'@"),
+            ("a\n", "@'\na\n\n'@"),
+            ("\n", "@'\n\n\n'@"),
+            ("a\n'@", "@\"\na\n'@\n\"@"),
+            ("a\n  \u{2018}@", "@\"\na\n  \u{2018}@\n\"@"),
+            ("a'@\nb", "@'\na'@\nb\n'@"),
+            // Neither kind fits.
+            ("a\n'@$x", "\"a`n'@`$x\""),
+            ("a\r\nb", "\"a`r`nb\""),
+            ("a\tb\n", "\"a`tb`n\""),
+            // No line breaks, no here-string.
+            ("a b", "'a b'"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(
+                Quoted::windows(orig).here_string(true).to_string(),
+                expected
+            );
+        }
+        assert_eq!(
+            Quoted::windows("a\nb")
+                .here_string(true)
+                .prefer(QuoteChar::Double)
+                .to_string(),
+            "@\"\na\nb\n\"@"
+        );
+        assert_eq!(
+            Quoted::windows("a\n$b")
+                .here_string(true)
+                .prefer(QuoteChar::Double)
+                .to_string(),
+            "@'\na\n$b\n'@"
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_heredoc() {
+        const CASES: &[(&[u8], &str)] = &[
+            (b"foo\nbar", "<<'EOF'\nfoo\nbar\nEOF"),
+            (b"foo\nbar\n", "<<'EOF'\nfoo\nbar\nEOF"),
+            (b"\n", "<<'EOF'\n\nEOF"),
+            (b"$x 'y' \"z\"\n`w`\\", "<<'EOF'\n$x 'y' \"z\"\n`w`\\\nEOF"),
+            // Delimiters.
+            (b"EOF\n", "<<'EOF1'\nEOF\nEOF1"),
+            (b"a\nEOF", "<<'EOF1'\na\nEOF\nEOF1"),
+            (b"EOF\nEOF1\nEOF3", "<<'EOF2'\nEOF\nEOF1\nEOF3\nEOF2"),
+            (b"EOF1\nEOF01\n", "<<'EOF'\nEOF1\nEOF01\nEOF"),
+            (b" EOF\nEOF \nEOFF", "<<'EOF'\n EOF\nEOF \nEOFF\nEOF"),
+            (b"EOF\nEOF+1\n", "<<'EOF1'\nEOF\nEOF+1\nEOF1"),
+            // Quoted as usual.
+            (b"a\tb\n", r"$'a\tb\n'"),
+            (b"a\r\nb", r"$'a\r\nb'"),
+            (b"a\xFF\nb", r"$'a\xFF\nb'"),
+            (b"a b", "'a b'"),
+            (b"foo", "'foo'"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::unix_raw(orig).heredoc(true).to_string(), expected);
+        }
+        assert_eq!(
+            Quoted::unix("!\n")
+                .heredoc(true)
+                .interactive(true)
+                .to_string(),
+            Quoted::unix("!\n").interactive(true).to_string()
+        );
+    }
+
+    #[cfg(any(feature = "unix", feature = "windows"))]
+    #[test]
+    fn write_escaped() {
+        struct Escaped<'a>(Quoted<'a>);
+
+        impl<'a> Display for Escaped<'a> {
+            fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+                self.0.write_escaped(f)
+            }
+        }
+
+        #[cfg(feature = "unix")]
+        {
+            let escaped = |quoted| Escaped(quoted).to_string();
+            assert_eq!(escaped(Quoted::unix("foo")), "$'foo'");
+            assert_eq!(escaped(Quoted::unix("it's\n")), r"$'it\'s\n'");
+            assert_eq!(escaped(Quoted::unix_raw(b"a\xFF")), r"$'a\xFF'");
+            assert_eq!(
+                escaped(Quoted::unix("a\tb").escape_dialect(EscapeDialect::DoubleQuoteBackslash)),
+                r#""a\tb""#
+            );
+            assert_eq!(escaped(Quoted::unix("\u{FEFF}x").strip_bom(true)), "$'x'");
+        }
+        #[cfg(feature = "windows")]
+        {
+            let escaped = |quoted| Escaped(quoted).to_string();
+            assert_eq!(escaped(Quoted::windows("foo")), r#""foo""#);
+            assert_eq!(escaped(Quoted::windows("$x\n")), r#""`$x`n""#);
+            assert_eq!(
+                escaped(Quoted::windows_raw(&[0xD800, u16::from(b'x')])),
+                r#""`u{D800}x""#
+            );
+        }
+    }
+
+    #[cfg(feature = "nfc")]
+    #[test]
+    fn normalize_nfc() {
+        let decomposed = "cafe\u{301} n\u{303}";
+        let composed = "caf\u{E9} \u{F1}";
+
+        #[cfg(feature = "unix")]
+        {
+            let quote = |text| Quoted::unix(text).normalize_nfc(true).to_string();
+            assert_eq!(quote(decomposed), quote(composed));
+            assert_eq!(quote(composed), "'caf\u{E9} \u{F1}'");
+            assert_ne!(Quoted::unix(decomposed).to_string(), quote(composed));
+            assert_eq!(
+                Quoted::unix_raw(decomposed.as_bytes())
+                    .normalize_nfc(true)
+                    .to_string(),
+                quote(composed)
+            );
+            // Invalid text is left alone.
+            assert_eq!(
+                Quoted::unix_raw(b"e\xCC\x81\xFF")
+                    .normalize_nfc(true)
+                    .to_string(),
+                "$'e\u{301}\\xFF'"
+            );
+        }
+        #[cfg(feature = "windows")]
+        {
+            let quote = |text| Quoted::windows(text).normalize_nfc(true).to_string();
+            assert_eq!(quote(decomposed), quote(composed));
+            let units: Vec<u16> = decomposed.encode_utf16().collect();
+            assert_eq!(
+                Quoted::windows_raw(&units).normalize_nfc(true).to_string(),
+                quote(composed)
+            );
+            assert_eq!(
+                Quoted::windows_raw(&[u16::from(b'e'), 0x301, 0xD800])
+                    .normalize_nfc(true)
+                    .to_string(),
+                "\"e\u{301}`u{D800}\""
+            );
+        }
+        #[cfg(feature = "native")]
+        {
+            assert_eq!(
+                decomposed.quote().normalize_nfc(true).to_string(),
+                composed.quote().to_string()
+            );
+            // U+212B ANGSTROM SIGN normalizes to U+00C5.
+            assert_eq!(
+                '\u{212B}'.quote().normalize_nfc(true).to_string(),
+                '\u{C5}'.quote().to_string()
+            );
+        }
+        #[cfg(all(feature = "native", feature = "std"))]
+        assert_eq!(
+            OsStr::new(decomposed)
+                .quote()
+                .normalize_nfc(true)
+                .to_string(),
+            composed.quote().to_string()
+        );
+        #[cfg(feature = "json")]
+        assert_eq!(
+            Quoted::json(decomposed).normalize_nfc(true).to_string(),
+            Quoted::json(composed).to_string()
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn quote_bytes() {
+        use std::borrow::Cow;
+
+        match Quoted::unix_raw(b"foo").force(false).quote_bytes() {
+            Cow::Borrowed(bytes) => assert_eq!(bytes, b"foo"),
+            Cow::Owned(_) => panic!("safe text was copied"),
+        }
+        match Quoted::unix_raw(b"foo\xFF").force(false).quote_bytes() {
+            Cow::Owned(bytes) => assert_eq!(bytes, b"$'foo\\xFF'"),
+            Cow::Borrowed(_) => panic!("unsafe text was borrowed"),
+        }
+        assert_eq!(&*Quoted::unix_raw(b"foo").quote_bytes(), b"'foo'");
+        assert_eq!(&*Quoted::unix("a b").force(false).quote_bytes(), b"'a b'");
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_escape_only() {
+        const CASES: &[(&[u8], &str)] = &[
+            (b"foo", "foo"),
+            (b"a b", "a b"),
+            (b"it's", "it's"),
+            (b"$x `y` \\ \"z\"", r#"\$x \`y\` \\ \"z\""#),
+            (b"a\tb", r#"a"$'\t'"b"#),
+            (b"a\t\x01", r#"a"$'\t\x01'""#),
+            (b"\x01f", r#""$'\x01'"f"#),
+            (b"\xFF!", r#""$'\xFF'"!"#),
+            (b"", ""),
+        ];
+        for &(orig, expected) in CASES {
+            let escaped = Quoted::unix_raw(orig).escape_only().to_string();
+            assert_eq!(escaped, expected);
+            if let Ok(orig) = core::str::from_utf8(orig) {
+                let parsed = unquote_unix(&format!("\"{}\"", escaped)).unwrap();
+                assert_eq!(parsed, orig);
+            }
+        }
+        assert_eq!(
+            Quoted::unix("a!b")
+                .interactive(true)
+                .escape_only()
+                .to_string(),
+            r#"a"'!'"b"#
+        );
+        assert_eq!(
+            Quoted::unix("a\tb\"")
+                .escape_dialect(EscapeDialect::DoubleQuoteBackslash)
+                .escape_only()
+                .to_string(),
+            r#"a\tb\""#
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn printf() {
+        const CASES: &[(&[u8], &str)] = &[
+            (b"foo", "'foo'"),
+            (b"a\tb", r"'a\0011b'"),
+            (b"foo\xFF", r"'foo\0377'"),
+            (b"\xC3\xA9", "'\u{E9}'"),
+            (b"\xE2\x80\xA8", r"'\0342\0200\0250'"),
+            (b"a\\b", r"'a\\b'"),
+            (b"it's", r"'it'\''s'"),
+            (b"", "''"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::printf_raw(orig).to_string(), expected);
+        }
+        assert_eq!(Quoted::printf("a\tb").to_string(), r"'a\0011b'");
+        assert_eq!(Quoted::printf("foo").force(false).to_string(), "foo");
+        assert_eq!(Quoted::printf("a b").force(false).to_string(), "'a b'");
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_escape_only() {
+        const CASES: &[(&str, &str)] = &[
+            ("foo", "foo"),
+            ("it's", "it's"),
+            ("$x `y` \"z\"", r#"`$x ``y`` `"z`""#),
+            ("a\tb", "a`tb"),
+            ("a\u{201C}b", "a`\u{201C}b"),
+        ];
+        for &(orig, expected) in CASES {
+            let escaped = Quoted::windows(orig).escape_only().to_string();
+            assert_eq!(escaped, expected);
+            let parsed = unquote_windows(&format!("\"{}\"", escaped)).unwrap();
+            assert_eq!(parsed, orig);
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_invalid_policy() {
+        const CASES: &[(&[u8], &str, &str, &str)] = &[
+            (b"a\xFFb", r#"$'a\xFF'$'b'"#, "a\u{FFFD}b", "ab"),
+            (b"\xFF", r#"$'\xFF'"#, "\u{FFFD}", "''"),
+            (b"\xFF~", r#"$'\xFF~'"#, "\u{FFFD}~", "'~'"),
+            (b"a b\xFF", r#"$'a b\xFF'"#, "'a b\u{FFFD}'", "'a b'"),
+            (
+                b"a\tb\xFF",
+                r#"$'a\tb\xFF'"#,
+                "$'a\\tb\u{FFFD}'",
+                r#"$'a\tb'"#,
+            ),
+        ];
+        for &(orig, escape, replace, strip) in CASES {
+            let quoted = Quoted::unix_raw(orig).force(false);
+            assert_eq!(quoted.to_string(), escape);
+            assert_eq!(quoted.invalid(InvalidPolicy::Escape).to_string(), escape);
+            assert_eq!(quoted.invalid(InvalidPolicy::Replace).to_string(), replace);
+            assert_eq!(quoted.invalid(InvalidPolicy::Strip).to_string(), strip);
+        }
+        assert_eq!(
+            Quoted::unix_raw(b"a\xFFb")
+                .invalid(InvalidPolicy::Strip)
+                .to_string(),
+            "'ab'"
+        );
+        // Valid text isn't affected.
+        assert_eq!(
+            Quoted::unix_raw(b"\xEF\xBF\xBD")
+                .invalid(InvalidPolicy::Strip)
+                .to_string(),
+            "'\u{FFFD}'"
+        );
+        // The policy wins over meta notation.
+        assert_eq!(
+            Quoted::unix_raw(b"\xFF")
+                .force(false)
+                .control_style(ControlStyle::Caret)
+                .invalid(InvalidPolicy::Replace)
+                .to_string(),
+            "\u{FFFD}"
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_glob_safe() {
+        for &text in &["a*b", "a?b", "[ab]", "{a,b}", "}"] {
+            assert_eq!(
+                Quoted::unix(text).force(false).to_string(),
+                "'".to_string() + text + "'"
+            );
+            assert_eq!(
+                Quoted::unix(text).force(false).glob_safe(false).to_string(),
+                text
+            );
+        }
+        assert_eq!(
+            Quoted::unix("a*b c")
+                .force(false)
+                .glob_safe(false)
+                .to_string(),
+            "'a*b c'"
+        );
+        assert_eq!(Quoted::unix("a*b").glob_safe(false).to_string(), "'a*b'");
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_protect_dashes() {
+        const CASES: &[(&str, &str)] = &[
+            ("-x", "'-x'"),
+            ("--force", "'--force'"),
+            ("-", "-"),
+            ("a-b", "a-b"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::unix(orig).force(false).to_string(), orig);
+            assert_eq!(
+                Quoted::unix(orig)
+                    .force(false)
+                    .protect_dashes(true)
+                    .to_string(),
+                expected
+            );
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_quote_keywords() {
+        const CASES: &[(&str, &str)] = &[
+            ("if", "'if'"),
+            ("for", "'for'"),
+            ("done", "'done'"),
+            ("If", "If"),
+            ("iff", "iff"),
+            ("test", "test"),
+            ("foreach", "foreach"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::unix(orig).force(false).to_string(), orig);
+            assert_eq!(
+                Quoted::unix(orig)
+                    .force(false)
+                    .quote_keywords(true)
+                    .to_string(),
+                expected
+            );
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_quote_keywords() {
+        const CASES: &[(&str, &str)] = &[
+            ("if", "'if'"),
+            ("ForEach", "'ForEach'"),
+            ("function", "'function'"),
+            ("fi", "fi"),
+            ("done", "done"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::windows(orig).force(false).to_string(), orig);
+            assert_eq!(
+                Quoted::windows(orig)
+                    .force(false)
+                    .quote_keywords(true)
+                    .to_string(),
+                expected
+            );
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_protect_devices() {
+        const CASES: &[(&str, &str)] = &[
+            ("NUL", "'NUL'"),
+            ("con.txt", "'con.txt'"),
+            ("Com1", "'Com1'"),
+            ("lpt9.tar.gz", "'lpt9.tar.gz'"),
+            ("console", "console"),
+            ("com10", "com10"),
+            ("nul_", "nul_"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::windows(orig).force(false).to_string(), orig);
+            assert_eq!(
+                Quoted::windows(orig)
+                    .force(false)
+                    .protect_devices(true)
+                    .to_string(),
+                expected
+            );
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_leading_equals() {
+        const CASES: &[(&str, &str)] = &[
+            ("=foo", "'=foo'"),
+            ("=1", "'=1'"),
+            ("=", "'='"),
+            ("=-1", "'=-1'"),
+            ("-=1", "'-=1'"),
+            ("1=", "'1='"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::windows(orig).force(false).to_string(), expected);
+            assert_eq!(
+                Quoted::windows(orig)
+                    .force(false)
+                    .quote_numbers(false)
+                    .to_string(),
+                expected
+            );
+        }
+        assert_eq!(
+            Quoted::windows("=foo").analyze(),
+            Classification::NeedsQuote {
+                whitespace: false,
+                special: true,
+                leading: true,
+            }
+        );
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_subexpressions() {
+        const CASES: &[(&str, &str)] = &[
+            ("a@(b)", "'a@(b)'"),
+            ("a$(b)", "'a$(b)'"),
+            ("a@(", "'a@('"),
+            ("@(b)", "'@(b)'"),
+            ("a@b", "a@b"),
+            ("user@example.com", "user@example.com"),
+            ("@b", "'@b'"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::windows(orig).force(false).to_string(), expected);
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_stop_parsing() {
+        assert_eq!(Quoted::windows("--%").force(false).to_string(), "'--%'");
+        let args: Vec<&str> = vec!["echo", "--%", "a b"];
+        assert_eq!(
+            CommandLine::new(args.iter().map(|arg| Quoted::windows(arg))).to_string(),
+            "echo '--%' 'a b'"
+        );
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_quote_numbers() {
+        // (orig, default, without quote_numbers)
+        const CASES: &[(&str, &str, &str)] = &[
+            ("2023", "'2023'", "2023"),
+            (".5d", "'.5d'", ".5d"),
+            ("2023-report.txt", "'2023-report.txt'", "2023-report.txt"),
+            ("1d", "'1d'", "1d"),
+            ("-5", "'-5'", "'-5'"),
+            ("1 2", "'1 2'", "'1 2'"),
+            (".git", ".git", ".git"),
+        ];
+        for &(orig, default, without) in CASES {
+            let quoted = Quoted::windows(orig).force(false);
+            assert_eq!(quoted.to_string(), default);
+            assert_eq!(quoted.quote_numbers(false).to_string(), without);
+            assert_eq!(
+                quoted.quote_numbers(false).force(true).to_string(),
+                quoted.force(true).to_string()
+            );
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_powershell_context() {
+        // (orig, external, cmdlet)
+        const CASES: &[(&str, &str, &str)] = &[
+            ("~", "'~'", "~"),
+            ("~/foo", "'~/foo'", "~/foo"),
+            ("a~b", "a~b", "a~b"),
+            ("~ x", "'~ x'", "'~ x'"),
+            ("#x", "'#x'", "'#x'"),
+            ("@x", "'@x'", "'@x'"),
+        ];
+        for &(orig, external, cmdlet) in CASES {
+            let quoted = Quoted::windows(orig).force(false);
+            assert_eq!(quoted.to_string(), external);
+            assert_eq!(
+                quoted
+                    .powershell_context(PowerShellContext::External)
+                    .to_string(),
+                external
+            );
+            assert_eq!(
+                quoted
+                    .powershell_context(PowerShellContext::Cmdlet)
+                    .to_string(),
+                cmdlet
+            );
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_quote_nested() {
+        const CASES: &[(&str, usize, &str)] = &[
+            ("my file", 0, "my file"),
+            ("my file", 1, "'my file'"),
+            ("my file", 2, r#""'my file'""#),
+            ("my file", 3, r#"'"'\''my file'\''"'"#),
+            ("it's", 2, r#"'"it'\''s"'"#),
+            ("foo", 2, "foo"),
+            ("", 2, r#""''""#),
+            ("a\tb", 2, r"'$'\''a\tb'\'"),
+        ];
+        for &(orig, levels, expected) in CASES {
+            let quoted = quote_nested(orig, levels, QuoteStyle::Unix);
+            assert_eq!(quoted, expected);
+            let mut text = quoted;
+            for _ in 0..levels {
+                text = unquote_unix(&text).unwrap();
+            }
+            assert_eq!(text, orig);
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_quote_nested() {
+        for &text in &["my file", "it's", "$x `y`", "a\nb", ""] {
+            for levels in 0..4 {
+                let mut quoted = quote_nested(text, levels, QuoteStyle::Windows);
+                for _ in 0..levels {
+                    quoted = unquote_windows(&quoted).unwrap();
+                }
+                assert_eq!(quoted, text);
+            }
+        }
+        assert_eq!(quote_nested("my file", 1, QuoteStyle::Windows), "'my file'");
+        assert_eq!(
+            quote_nested("my file", 2, QuoteStyle::Windows),
+            r#""'my file'""#
+        );
+    }
+
+    #[cfg(all(feature = "roundtrip", feature = "unix", feature = "windows"))]
+    #[test]
+    fn verify_roundtrip_random() {
+        // Pieces that are special somewhere, so that random strings of
+        // them hit interesting cases more often than random bytes would.
+        const PIECES: &[&[u8]] = &[
+            b"a",
+            b"0",
+            b" ",
+            b"'",
+            b"\"",
+            b"$",
+            b"`",
+            b"\\",
+            b"!",
+            b"-",
+            b"~",
+            b"#",
+            b"*",
+            b"{",
+            b"@",
+            b"\n",
+            b"\r",
+            b"\t",
+            b"\x1B",
+            b"\x7F",
+            b"\xFF",
+            b"\xC3",
+            "\u{E9}".as_bytes(),
+            "\u{2018}".as_bytes(),
+            "\u{201C}".as_bytes(),
+            "\u{202E}".as_bytes(),
+            "\u{FEFF}".as_bytes(),
+            "\u{1F600}".as_bytes(),
+        ];
+
+        // xorshift64, so that failures can be reproduced.
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..2000 {
+            let mut input = Vec::new();
+            for _ in 0..next() % 12 {
+                input.extend_from_slice(PIECES[next() as usize % PIECES.len()]);
+            }
+            assert!(verify_roundtrip(&input, QuoteStyle::Unix), "{:?}", input);
+            assert!(verify_roundtrip(&input, QuoteStyle::Windows), "{:?}", input);
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn quote_all_lazy() {
+        let names = ["foo", "my file", "it's"].iter().cloned();
+        let quoted: Vec<String> = quote_all(names, QuoteStyle::Unix)
+            .map(|quoted| quoted.to_string())
+            .collect();
+        assert_eq!(quoted, ["foo", "'my file'", r#""it's""#]);
+
+        // Only the items that are used get quoted.
+        let quoted: Vec<String> = quote_all(core::iter::repeat("a b"), QuoteStyle::Unix)
+            .take(2)
+            .map(|quoted| quoted.to_string())
+            .collect();
+        assert_eq!(quoted, ["'a b'", "'a b'"]);
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_extra_specials() {
+        const CASES: &[(&str, &str)] = &[
+            ("a:b", "'a:b'"),
+            ("user@host", "'user@host'"),
+            ("ab", "ab"),
+            ("dir:", "'dir:'"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::unix(orig).force(false).to_string(), orig);
+            assert_eq!(
+                Quoted::unix(orig)
+                    .force(false)
+                    .extra_specials("@:")
+                    .to_string(),
+                expected
+            );
+        }
+        assert!(!Quoted::unix("a:b").extra_specials(":").is_safe_bare());
+        assert_eq!(
+            Quoted::unix_raw(b"a:b\xFF")
+                .force(false)
+                .extra_specials(":")
+                .to_string(),
+            r#"$'a:b\xFF'"#
+        );
+        // Only the last call counts.
+        assert_eq!(
+            Quoted::unix("a:b")
+                .force(false)
+                .extra_specials(":")
+                .extra_specials("@")
+                .to_string(),
+            "a:b"
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    #[should_panic]
+    fn unix_extra_specials_ascii() {
+        let _ = Quoted::unix("a").extra_specials("\u{E9}");
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_symbolic_escapes() {
+        const CASES: &[(&str, &str, &str, &str)] = &[
+            ("a\nb", r#"$'a\nb'"#, r#"$'a\nb'"#, r#"$'a\x0A'$'b'"#),
+            ("\t\r", r#"$'\t\r'"#, r#"$'\t\r'"#, r#"$'\x09\x0D'"#),
+            ("\x07\x0C", r#"$'\x07\x0C'"#, r#"$'\a\f'"#, r#"$'\x07\x0C'"#),
+            (
+                "\x1B[31mred",
+                r#"$'\x1B[31mred'"#,
+                r#"$'\e[31mred'"#,
+                r#"$'\x1B[31mred'"#,
+            ),
+            ("\x1BA", r#"$'\x1B'$'A'"#, r#"$'\eA'"#, r#"$'\x1B'$'A'"#),
+        ];
+        for &(orig, default, symbolic, numeric) in CASES {
+            assert_eq!(Quoted::unix(orig).to_string(), default);
+            assert_eq!(
+                Quoted::unix(orig).symbolic_escapes(true).to_string(),
+                symbolic
+            );
+            assert_eq!(
+                Quoted::unix(orig).symbolic_escapes(false).to_string(),
+                numeric
+            );
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_lowercase_hex() {
+        assert_eq!(Quoted::unix_raw(b"foo\xFF").to_string(), r#"$'foo\xFF'"#);
+        assert_eq!(
+            Quoted::unix_raw(b"foo\xFF").lowercase_hex(true).to_string(),
+            r#"$'foo\xff'"#
+        );
+        assert_eq!(
+            Quoted::unix("\u{2028}").lowercase_hex(true).to_string(),
+            r#"$'\xe2\x80\xa8'"#
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_interactive() {
+        const CASES: &[(&str, &str, &str)] = &[
+            ("foo!", "foo!", "'foo!'"),
+            ("!bar", "'!bar'", "'!bar'"),
+            ("foo", "foo", "foo"),
+            ("it's!", r#""it's!""#, r#"'it'\''s!'"#),
+        ];
+        for &(orig, default, interactive) in CASES {
+            assert_eq!(Quoted::unix(orig).force(false).to_string(), default);
+            assert_eq!(
+                Quoted::unix(orig)
+                    .force(false)
+                    .interactive(true)
+                    .to_string(),
+                interactive
+            );
+        }
+        assert_eq!(
+            Quoted::unix("hi!")
+                .prefer(QuoteChar::Double)
+                .interactive(true)
+                .to_string(),
+            "'hi!'"
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_interactive_bash() {
+        assert_eq!(Quoted::unix("ls!").force(false).to_string(), "ls!");
+        assert_eq!(
+            Quoted::unix("ls!")
+                .force(false)
+                .interactive_bash()
+                .to_string(),
+            "'ls!'"
+        );
+
+        let command = |args: &[&'static str], interactive| {
+            CommandLine::new(args.iter().map(move |arg| {
+                let quoted = Quoted::unix(arg);
+                if interactive {
+                    quoted.interactive_bash()
+                } else {
+                    quoted
                 }
-                #[cfg(any(unix, target_os = "wasi"))]
-                match text.to_str() {
-                    Some(text) => unix::write(f, text, self.force_quote),
-                    None => unix::write_escaped(f, text.as_bytes()),
+            }))
+            .to_string()
+        };
+        assert_eq!(command(&["ls!", "-l"], false), "ls! -l");
+        assert_eq!(command(&["ls!", "-l"], true), "'ls!' -l");
+        assert_eq!(command(&["ls", "-l", "ls"], true), "'ls' -l ls");
+        assert_eq!(command(&["ls", "it's!"], true), r#"'ls' 'it'\''s!'"#);
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_interactive_bash() {
+        // PowerShell would treat a quoted command name as a string.
+        let args = ["Get-ChildItem", "hi!"];
+        assert_eq!(
+            CommandLine::new(
+                args.iter()
+                    .map(|arg| Quoted::windows(arg).interactive_bash())
+            )
+            .to_string(),
+            "Get-ChildItem hi!"
+        );
+    }
+
+    const WINDOWS_ALWAYS: &[(&str, &str)] = &[
+        (r#"foo\bar"#, r#"'foo\bar'"#),
+        (r#"can'"t"#, r#"'can''"t'"#),
+        (r#"can'$t"#, r#"'can''$t'"#),
+        ("foo\nb\ta\r\\\0`r", r#""foo`nb`ta`r\`0``r""#),
+        ("foo\x02", r#""foo`u{02}""#),
+        ("foo\x7F", r#""foo`u{7F}""#),
+        (r#"'$''"#, r#"'''$'''''"#),
+        (r#"x\"#, r#"'x\'"#),
+        (r#"a"b\"#, r#"'a"b\'"#),
+        (r#"it's\"#, r#"'it''s\'"#),
+        (r#"it's\x"#, r#""it's\x""#),
+    ];
+    const WINDOWS_MAYBE: &[(&str, &str)] = &[
+        ("-x", "'-x'"),
+        ("—x", "'—x'"),
+        ("a,b", "'a,b'"),
+        ("a\\b", "a\\b"),
+        ("‘", r#""‘""#),
+        ("‘\"", r#"''‘"'"#),
+        ("„\0", r#""`„`0""#),
+        ("\t", r#""`t""#),
+        ("\r", r#""`r""#),
+        ("\u{85}", r#""`u{85}""#),
+        ("\u{2028}", r#""`u{2028}""#),
+        (
+            "user\u{202E} \u{2066}// Check if admin\u{2069} \u{2066}",
+            r#""user`u{202E} `u{2066}// Check if admin`u{2069} `u{2066}""#,
+        ),
+    ];
+    const WINDOWS_RAW: &[(&[u16], &str)] = &[(&[b'x' as u16, 0xD800], r#""x`u{D800}""#)];
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows() {
+        for &(orig, expected) in WINDOWS_ALWAYS.iter().chain(BOTH_ALWAYS) {
+            assert_eq!(Quoted::windows(orig).to_string(), expected);
+        }
+        for &(orig, expected) in WINDOWS_MAYBE.iter().chain(BOTH_MAYBE) {
+            assert_eq!(Quoted::windows(orig).force(false).to_string(), expected);
+        }
+        for &(orig, expected) in WINDOWS_RAW {
+            assert_eq!(Quoted::windows_raw(orig).to_string(), expected);
+        }
+        let bidi_ok = nest_bidi(16);
+        assert_eq!(
+            Quoted::windows(&bidi_ok).to_string(),
+            "'".to_string() + &bidi_ok + "'"
+        );
+        let bidi_too_deep = nest_bidi(17);
+        assert!(Quoted::windows(&bidi_too_deep).to_string().contains('`'));
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_bidi() {
+        let text = "\u{202E}abc";
+        assert_eq!(Quoted::windows(text).to_string(), r#""`u{202E}abc""#);
+        assert_eq!(
+            Quoted::windows(text).bidi(BidiPolicy::Allow).to_string(),
+            "'\u{202E}abc'"
+        );
+        assert_eq!(
+            Quoted::windows("\u{202E}\n")
+                .bidi(BidiPolicy::Allow)
+                .to_string(),
+            "\"\u{202E}`n\""
+        );
+
+        let isolated = |text| Quoted::windows(text).bidi(BidiPolicy::Isolate).to_string();
+        assert_eq!(isolated(text), "\u{2066}'\u{202E}abc'\u{2069}");
+        assert_eq!(isolated("\u{2067}x\u{2069}"), "'\u{2067}x\u{2069}'");
+        assert_eq!(isolated("\u{202E}\n"), r#""`u{202E}`n""#);
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_escape_invisible() {
+        assert_eq!(
+            Quoted::windows("a\u{200D}b")
+                .escape_invisible(true)
+                .to_string(),
+            r#""a`u{200D}b""#
+        );
+        assert_eq!(
+            Quoted::windows("\u{2764}\u{FE0F}")
+                .escape_invisible(true)
+                .to_string(),
+            "\"\u{2764}`u{FE0F}\""
+        );
+        assert_eq!(Quoted::windows("a\u{200D}b").to_string(), "'a\u{200D}b'");
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_invalid_policy() {
+        const CASES: &[(&[u16], &str, &str, &str)] = &[
+            (
+                &[b'a' as u16, 0xD800, b'b' as u16],
+                r#""a`u{D800}b""#,
+                "a\u{FFFD}b",
+                "ab",
+            ),
+            (&[0xDC00], r#""`u{DC00}""#, "\u{FFFD}", "''"),
+            (
+                &[0xD800, b'-' as u16, b'x' as u16],
+                r#""`u{D800}-x""#,
+                "\u{FFFD}-x",
+                "'-x'",
+            ),
+        ];
+        for &(orig, escape, replace, strip) in CASES {
+            let quoted = Quoted::windows_raw(orig).force(false);
+            assert_eq!(quoted.to_string(), escape);
+            assert_eq!(quoted.invalid(InvalidPolicy::Replace).to_string(), replace);
+            assert_eq!(quoted.invalid(InvalidPolicy::Strip).to_string(), strip);
+        }
+        // A trailing backslash is still avoided between double quotes.
+        assert_eq!(
+            Quoted::windows_raw(&[b'$' as u16, b'\\' as u16, 0xD800])
+                .invalid(InvalidPolicy::Strip)
+                .prefer(QuoteChar::Double)
+                .to_string(),
+            r#"'$\'"#
+        );
+    }
+
+    #[cfg(any(feature = "unix", feature = "windows"))]
+    #[test]
+    fn replacement_char() {
+        #[cfg(feature = "unix")]
+        {
+            let quoted = Quoted::unix_raw(b"a\xFFb")
+                .invalid(InvalidPolicy::Replace)
+                .force(false);
+            assert_eq!(quoted.to_string(), "a\u{FFFD}b");
+            assert_eq!(quoted.replacement_char('?').to_string(), "'a?b'");
+            assert_eq!(quoted.replacement_char('_').to_string(), "a_b");
+            // It only applies to the replace policy.
+            assert_eq!(
+                quoted
+                    .invalid(InvalidPolicy::Escape)
+                    .replacement_char('?')
+                    .to_string(),
+                r#"$'a\xFF'$'b'"#
+            );
+        }
+        #[cfg(feature = "windows")]
+        {
+            let units = [b'a' as u16, 0xD800, b'b' as u16];
+            let quoted = Quoted::windows_raw(&units)
+                .invalid(InvalidPolicy::Replace)
+                .force(false);
+            assert_eq!(quoted.replacement_char('?').to_string(), "'a?b'");
+            assert_eq!(
+                Quoted::windows_raw_utf8(b"a\xFFb")
+                    .replacement_char('_')
+                    .force(false)
+                    .to_string(),
+                "a_b"
+            );
+            assert_eq!(
+                Quoted::wtf8(b"a\xED\xA0\x80\xFFb")
+                    .invalid(InvalidPolicy::Replace)
+                    .replacement_char('_')
+                    .force(false)
+                    .to_string(),
+                "a__b"
+            );
+        }
+    }
+
+    #[cfg(any(feature = "unix", feature = "windows"))]
+    #[test]
+    fn empty_as() {
+        #[cfg(feature = "unix")]
+        {
+            assert_eq!(Quoted::unix("").to_string(), "''");
+            assert_eq!(Quoted::unix("").empty_as(None).to_string(), "''");
+            assert_eq!(Quoted::unix("").empty_as(Some("")).to_string(), "");
+            assert_eq!(
+                Quoted::unix("").empty_as(Some("(empty)")).to_string(),
+                "(empty)"
+            );
+            assert_eq!(
+                Quoted::unix_raw(b"").empty_as(Some("(empty)")).to_string(),
+                "(empty)"
+            );
+            // It only applies to empty text.
+            assert_eq!(
+                Quoted::unix("a b").empty_as(Some("(empty)")).to_string(),
+                "'a b'"
+            );
+        }
+        #[cfg(feature = "windows")]
+        {
+            assert_eq!(Quoted::windows("").to_string(), "''");
+            assert_eq!(
+                Quoted::windows_raw(&[])
+                    .empty_as(Some("(empty)"))
+                    .to_string(),
+                "(empty)"
+            );
+        }
+    }
+
+    #[cfg(any(feature = "unix", feature = "windows"))]
+    #[test]
+    fn rendering() {
+        fn rendering(delimiter: Option<char>, escaped: bool) -> Rendering {
+            Rendering { delimiter, escaped }
+        }
+
+        #[cfg(feature = "unix")]
+        {
+            let plain = Quoted::unix("foo").force(false);
+            assert_eq!(plain.rendering(), rendering(None, false));
+            assert_eq!(plain.force(true).rendering(), rendering(Some('\''), false));
+            let spaced = Quoted::unix("a b");
+            assert_eq!(spaced.rendering(), rendering(Some('\''), false));
+            assert_eq!(spaced.color(true).rendering(), spaced.rendering());
+            assert_eq!(Quoted::unix("a'b").rendering(), rendering(Some('"'), false));
+            assert_eq!(
+                Quoted::unix("a\nb").rendering(),
+                rendering(Some('\''), true)
+            );
+            assert_eq!(
+                Quoted::unix("\u{202E}")
+                    .bidi(BidiPolicy::Isolate)
+                    .rendering(),
+                rendering(Some('\''), false)
+            );
+        }
+        #[cfg(feature = "windows")]
+        {
+            assert_eq!(
+                Quoted::windows("foo").force(false).rendering(),
+                rendering(None, false)
+            );
+            assert_eq!(
+                Quoted::windows("a b").rendering(),
+                rendering(Some('\''), false)
+            );
+            assert_eq!(
+                Quoted::windows("a\nb").rendering(),
+                rendering(Some('"'), true)
+            );
+            assert_eq!(
+                Quoted::windows("a\nb").here_string(true).rendering(),
+                rendering(Some('\''), true)
+            );
+        }
+        #[cfg(feature = "unix")]
+        {
+            // Prefixes are skipped, but an escape before the quote isn't.
+            let tab = Quoted::unix("a\tb");
+            assert_eq!(tab.rendering().delimiter, Some('\''));
+            assert_eq!(Quoted::unix("'$").to_string(), r"\''$'");
+            assert_eq!(Quoted::unix("'$").rendering().delimiter, None);
+            let lines = Quoted::unix("a\nb").heredoc(true);
+            assert_eq!(lines.rendering().delimiter, Some('\''));
+            // What's written is described, even if it's cut short.
+            assert_eq!(tab.max_width(1).rendering().delimiter, None);
+            assert_eq!(tab.max_width(5).rendering().delimiter, Some('\''));
+            assert_eq!(
+                Quoted::unix("").empty_as(Some("'")).rendering().delimiter,
+                None
+            );
+        }
+        #[cfg(feature = "csv")]
+        {
+            // The text can look like our own color codes.
+            let colored = Quoted::csv("\x1B[36m'x");
+            assert_eq!(colored.to_string(), "\x1B[36m'x");
+            assert_eq!(colored.rendering().delimiter, None);
+            assert_eq!(colored.color(true).rendering().delimiter, None);
+            assert_eq!(Quoted::csv("'x").rendering().delimiter, None);
+            assert_eq!(Quoted::csv("a,b").rendering().delimiter, Some('"'));
+        }
+        #[cfg(feature = "verbatim")]
+        assert_eq!(
+            Quoted::verbatim("\x1B[39m").rendering().delimiter,
+            Some('«')
+        );
+
+        #[cfg(all(feature = "unix", feature = "windows"))]
+        {
+            let tables = [BOTH_ALWAYS, BOTH_MAYBE, UNIX_ALWAYS, WINDOWS_ALWAYS];
+            for &(orig, _) in tables.iter().flat_map(|table| table.iter()) {
+                for &quoted in &[Quoted::unix(orig), Quoted::windows(orig)] {
+                    for &quoted in &[quoted, quoted.force(false), quoted.color(true)] {
+                        let out = quoted.color(false).to_string();
+                        let out = out.trim_start_matches('\u{2066}');
+                        let out = out.trim_start_matches(|ch| ch == '$' || ch == '@');
+                        if let Some(quote) = quoted.rendering().delimiter {
+                            assert!(out.starts_with(quote), "{:?}", quoted);
+                        }
+                    }
                 }
-                #[cfg(not(any(windows, unix, target_os = "wasi")))]
-                match text.to_str() {
-                    Some(text) => unix::write(f, text, self.force_quote),
-                    // Debug is our best shot for not losing information.
-                    // But you probably can't paste it into a shell.
-                    None => write!(f, "{:?}", text),
+            }
+        }
+    }
+
+    #[cfg(all(feature = "unix", feature = "windows"))]
+    #[test]
+    fn unicode_whitespace() {
+        const CASES: &[char] = &[
+            '\u{00A0}', '\u{1680}', '\u{2000}', '\u{2007}', '\u{200A}', '\u{202F}', '\u{205F}',
+            '\u{3000}',
+        ];
+        for &ch in CASES {
+            let text = format!("a{}b", ch);
+            let expected = format!("'{}'", text);
+            assert_eq!(Quoted::unix(&text).force(false).to_string(), expected);
+            assert_eq!(Quoted::windows(&text).force(false).to_string(), expected);
+        }
+        // Blank, but not whitespace.
+        for &text in &["a\u{2800}b", "a\u{200B}b"] {
+            assert_eq!(Quoted::unix(text).force(false).to_string(), text);
+            assert_eq!(Quoted::windows(text).force(false).to_string(), text);
+        }
+    }
+
+    #[cfg(all(feature = "unix", feature = "windows"))]
+    #[test]
+    fn quote_into() {
+        let mut buf = String::new();
+        let mut expected = String::new();
+        for &(orig, _) in UNIX_MAYBE.iter().chain(WINDOWS_MAYBE).chain(BOTH_MAYBE) {
+            for &quoted in &[Quoted::unix(orig), Quoted::windows(orig).force(false)] {
+                quoted.quote_into(&mut buf);
+                expected.push_str(&quoted.to_string());
+            }
+        }
+        assert_eq!(buf, expected);
+
+        // Appending keeps what was there.
+        let mut buf = String::from("x=");
+        Quoted::unix("foo bar").quote_into(&mut buf);
+        assert_eq!(buf, "x='foo bar'");
+    }
+
+    #[cfg(all(
+        feature = "unix",
+        feature = "windows",
+        feature = "c",
+        feature = "json",
+        feature = "python",
+        feature = "toml",
+        feature = "yaml",
+        feature = "csv",
+        feature = "makefile"
+    ))]
+    #[test]
+    fn only_line_breaks() {
+        // Nothing but control codes, so there's no first character that
+        // decides things and no plain text in between.
+        // (orig, unix, windows, c/json/toml/yaml, python, makefile_shell, printf)
+        const CASES: &[(&str, &str, &str, &str, &str, &str, &str)] = &[
+            (
+                "\n", r"$'\n'", r#""`n""#, r#""\n""#, r"'\n'", r"$$'\n'", r"'\0012'",
+            ),
+            (
+                "\r\n",
+                r"$'\r\n'",
+                r#""`r`n""#,
+                r#""\r\n""#,
+                r"'\r\n'",
+                r"$$'\r\n'",
+                r"'\0015\0012'",
+            ),
+            (
+                "\n\n",
+                r"$'\n\n'",
+                r#""`n`n""#,
+                r#""\n\n""#,
+                r"'\n\n'",
+                r"$$'\n\n'",
+                r"'\0012\0012'",
+            ),
+        ];
+        for &(orig, unix, windows, c, python, makefile_shell, printf) in CASES {
+            for &force in &[true, false] {
+                assert_eq!(Quoted::unix(orig).force(force).to_string(), unix);
+                let raw = Quoted::unix_raw(orig.as_bytes()).force(force);
+                assert_eq!(raw.to_string(), unix);
+                assert_eq!(Quoted::windows(orig).force(force).to_string(), windows);
+                let units: Vec<u16> = orig.encode_utf16().collect();
+                let raw = Quoted::windows_raw(&units).force(force);
+                assert_eq!(raw.to_string(), windows);
+                assert_eq!(Quoted::c(orig).force(force).to_string(), c);
+                assert_eq!(Quoted::json(orig).force(force).to_string(), c);
+                assert_eq!(Quoted::toml(orig).force(force).to_string(), c);
+                assert_eq!(Quoted::yaml(orig).force(force).to_string(), c);
+                assert_eq!(Quoted::python(orig).force(force).to_string(), python);
+                assert_eq!(
+                    Quoted::makefile_shell(orig).force(force).to_string(),
+                    makefile_shell
+                );
+                assert_eq!(Quoted::printf(orig).force(force).to_string(), printf);
+                // CSV fields can hold line breaks as they are.
+                let csv = format!("\"{}\"", orig);
+                assert_eq!(Quoted::csv(orig).force(force).to_string(), csv);
+            }
+            assert_eq!(unquote_unix(unix).unwrap(), orig);
+            assert_eq!(unquote_windows(windows).unwrap(), orig);
+        }
+    }
+
+    #[cfg(all(
+        feature = "unix",
+        feature = "windows",
+        feature = "c",
+        feature = "json",
+        feature = "python",
+        feature = "toml",
+        feature = "yaml",
+        feature = "csv",
+        feature = "makefile"
+    ))]
+    #[test]
+    fn estimated_len() {
+        fn check(quoted: Quoted<'_>) {
+            for &color in &[false, true] {
+                for &style in &[ControlStyle::Escape, ControlStyle::Caret] {
+                    for &dialect in &[EscapeDialect::AnsiC, EscapeDialect::DoubleQuoteBackslash] {
+                        let quoted = quoted
+                            .color(color)
+                            .control_style(style)
+                            .escape_dialect(dialect)
+                            .ascii_only(true)
+                            .escape_invisible(true)
+                            .protect_dashes(true);
+                        for &quoted in &[quoted, quoted.force(false), quoted.max_width(5)] {
+                            let len = quoted.to_string().len();
+                            assert!(quoted.estimated_len() >= len, "{:?}", quoted);
+                        }
+                    }
                 }
             }
+        }
 
-            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
-            Kind::Unix(text) => unix::write(f, text, self.force_quote),
+        let tables = [
+            BOTH_ALWAYS,
+            BOTH_MAYBE,
+            UNIX_ALWAYS,
+            UNIX_MAYBE,
+            WINDOWS_ALWAYS,
+            WINDOWS_MAYBE,
+        ];
+        for &(orig, _) in tables.iter().flat_map(|table| table.iter()) {
+            check(Quoted::unix(orig));
+            check(Quoted::windows(orig));
+            check(Quoted::c(orig));
+            check(Quoted::json(orig));
+            check(Quoted::python(orig));
+            check(Quoted::toml(orig));
+            check(Quoted::yaml(orig));
+            check(Quoted::csv(orig));
+            check(Quoted::makefile(orig));
+            check(Quoted::makefile_shell(orig));
+        }
 
-            #[cfg(feature = "unix")]
-            Kind::UnixRaw(bytes) => match core::str::from_utf8(bytes) {
-                Ok(text) => unix::write(f, text, self.force_quote),
-                Err(_) => unix::write_escaped(f, bytes),
-            },
+        // Every pair of bytes, with something that could interact with
+        // an escape after it.
+        for first in 0..=255u8 {
+            for &second in &[first, b'0', b'a', b'\'', b'"', b'$', b'?', 0xFF] {
+                let bytes = [first, second, b'f'];
+                check(Quoted::unix_raw(&bytes));
+                check(Quoted::c_raw(&bytes));
+                check(Quoted::json_raw(&bytes));
+                let units = [u16::from(first) << 8, u16::from(second), b'f' as u16];
+                check(Quoted::windows_raw(&units));
+            }
+        }
+        for &ch in &['\u{85}', '\u{202E}', '\u{FEFF}', '\u{10FFFF}', '\u{E0001}'] {
+            let mut buf = [0; 4];
+            let text: &str = ch.encode_utf8(&mut buf);
+            check(Quoted::unix(text));
+            check(Quoted::windows(text));
+            check(Quoted::c(text));
+            check(Quoted::json(text));
+            check(Quoted::python(text));
+            check(Quoted::toml(text));
+            check(Quoted::yaml(text));
+            check(Quoted::csv(text));
+            check(Quoted::makefile_shell(text));
+        }
 
-            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
-            Kind::Windows(text) => windows::write(f, text, self.force_quote),
+        // Invalid units can turn into a replacement character that's
+        // escaped one byte at a time.
+        check(Quoted::unix_raw(&[0xFF; 8]).invalid(InvalidPolicy::Replace));
+        check(Quoted::printf_raw(b"\xFF\xFF\xFF").invalid(InvalidPolicy::Replace));
+        check(Quoted::c_raw(&[0xFF; 8]).invalid(InvalidPolicy::Replace));
+        check(Quoted::windows_raw(&[0xD800; 8]).invalid(InvalidPolicy::Replace));
+        for &replacement in &['?', '\u{E9}', '\u{1F600}'] {
+            for &quoted in &[
+                Quoted::unix_raw(&[0xFF; 4]),
+                Quoted::printf_raw(&[0xFF; 4]),
+                Quoted::windows_raw(&[0xD800; 4]),
+            ] {
+                check(
+                    quoted
+                        .invalid(InvalidPolicy::Replace)
+                        .replacement_char(replacement),
+                );
+            }
+        }
+        #[cfg(feature = "nfc")]
+        check(Quoted::unix("\u{1D160}\u{FB2C}\u{344}").normalize_nfc(true));
 
-            #[cfg(feature = "windows")]
-            #[cfg(feature = "alloc")]
-            // Avoiding this allocation is possible in theory, but it'd require either
-            // complicating or slowing down the common case.
-            // Perhaps we could offer a non-allocating API for known-invalid UTF-16 strings
-            // that we pass straight to write_escaped(), but it seems a bit awkward.
-            // Please open an issue if you have a need for this.
-            Kind::WindowsRaw(units) => match alloc::string::String::from_utf16(units) {
-                Ok(text) => windows::write(f, &text, self.force_quote),
-                Err(_) => windows::write_escaped(f, decode_utf16(units.iter().cloned())),
-            },
+        let placeholder = "(this text was left empty on purpose)";
+        check(Quoted::unix("").empty_as(Some(placeholder)));
+        check(Quoted::windows("").empty_as(Some(placeholder)));
+        check(Quoted::csv("").empty_as(Some(placeholder)));
+    }
+
+    #[cfg(all(feature = "unix", feature = "windows"))]
+    #[test]
+    fn bom() {
+        // (orig, kept, stripped)
+        const CASES: &[(&str, &str, &str)] = &[
+            ("\u{FEFF}foo", "'\u{FEFF}foo'", "foo"),
+            ("\u{FEFF}", "'\u{FEFF}'", "''"),
+            ("\u{FEFF}\u{FEFF}", "'\u{FEFF}\u{FEFF}'", "'\u{FEFF}'"),
+            ("\u{FEFF}foo bar", "'\u{FEFF}foo bar'", "'foo bar'"),
+            ("foo\u{FEFF}", "foo\u{FEFF}", "foo\u{FEFF}"),
+        ];
+        for &(orig, kept, stripped) in CASES {
+            for &quoted in &[Quoted::unix(orig), Quoted::windows(orig)] {
+                let quoted = quoted.force(false);
+                assert_eq!(quoted.to_string(), kept);
+                assert_eq!(quoted.strip_bom(true).to_string(), stripped);
+            }
         }
+        assert_eq!(
+            Quoted::unix_raw(b"\xEF\xBB\xBF\xFF")
+                .strip_bom(true)
+                .to_string(),
+            r#"$'\xFF'"#
+        );
+        assert_eq!(
+            Quoted::windows_raw(&[0xFEFF, 0xD800])
+                .strip_bom(true)
+                .to_string(),
+            r#""`u{D800}""#
+        );
+        assert_eq!(
+            Quoted::unix("\u{FEFF}foo")
+                .strip_bom(true)
+                .max_width(4)
+                .to_string(),
+            "'f'…"
+        );
     }
-}
 
-#[cfg(any(feature = "windows", all(feature = "native", feature = "std", windows)))]
-#[cfg(feature = "alloc")]
-fn decode_utf16(units: impl IntoIterator<Item = u16>) -> impl Iterator<Item = Result<char, u16>> {
-    core::char::decode_utf16(units).map(|res| res.map_err(|err| err.unpaired_surrogate()))
-}
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_ascii_only() {
+        assert_eq!(Quoted::windows("café").to_string(), "'café'");
+        assert_eq!(
+            Quoted::windows("café").ascii_only(true).to_string(),
+            r#""caf`u{E9}""#
+        );
+        assert_eq!(
+            Quoted::windows("\u{1F600} $x").ascii_only(true).to_string(),
+            r#""`u{1F600} `$x""#
+        );
+        assert_eq!(
+            Quoted::windows("foo")
+                .ascii_only(true)
+                .force(false)
+                .to_string(),
+            "foo"
+        );
+    }
 
-/// Characters that may not be safe to print in a terminal.
-///
-/// This includes all the ASCII control characters.
-fn requires_escape(ch: char) -> bool {
-    ch.is_control() || is_separator(ch)
-}
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_symbolic_escapes() {
+        const CASES: &[(&str, &str, &str)] = &[
+            ("a\nb", r#""a`nb""#, r#""a`u{0A}b""#),
+            ("\0\x07", r#""`0`a""#, r#""`u{00}`u{07}""#),
+            ("\x1B[31mred", r#""`u{1B}[31mred""#, r#""`u{1B}[31mred""#),
+            ("$`\t", r#""`$```t""#, r#""`$```u{09}""#),
+        ];
+        for &(orig, symbolic, numeric) in CASES {
+            assert_eq!(Quoted::windows(orig).to_string(), symbolic);
+            assert_eq!(
+                Quoted::windows(orig).symbolic_escapes(true).to_string(),
+                symbolic
+            );
+            assert_eq!(
+                Quoted::windows(orig).symbolic_escapes(false).to_string(),
+                numeric
+            );
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_lowercase_hex() {
+        const UNITS: &[u16] = &[b'x' as u16, 0xDBFF];
+        assert_eq!(Quoted::windows_raw(UNITS).to_string(), r#""x`u{DBFF}""#);
+        assert_eq!(
+            Quoted::windows_raw(UNITS).lowercase_hex(true).to_string(),
+            r#""x`u{dbff}""#
+        );
+        assert_eq!(
+            Quoted::windows("\x1B").lowercase_hex(true).to_string(),
+            r#""`u{1b}""#
+        );
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_safe_bare() {
+        for &(orig, expected) in WINDOWS_MAYBE.iter().chain(BOTH_MAYBE) {
+            assert_eq!(Quoted::windows(orig).is_safe_bare(), orig == expected);
+        }
+        assert!(Quoted::windows("foo").is_safe_bare());
+        assert!(!Quoted::windows("1d").is_safe_bare());
+        let units: Vec<u16> = "foo".encode_utf16().collect();
+        assert!(Quoted::windows_raw(&units).is_safe_bare());
+        for &(orig, _) in WINDOWS_RAW {
+            assert!(!Quoted::windows_raw(orig).is_safe_bare());
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_color() {
+        const CASES: &[(&str, &str)] = &[
+            ("foo", "foo"),
+            (
+                "a\tb",
+                "\x1B[36m\"\x1B[39ma\x1B[36m`t\x1B[39mb\x1B[36m\"\x1B[39m",
+            ),
+            (
+                "it's $x",
+                "\x1B[36m'\x1B[39mit\x1B[36m''\x1B[39ms $x\x1B[36m'\x1B[39m",
+            ),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(
+                Quoted::windows(orig).force(false).color(true).to_string(),
+                expected
+            );
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_max_width() {
+        assert_eq!(
+            Quoted::windows("abcdefghijklmnopqrstuvwxyz")
+                .max_width(10)
+                .to_string(),
+            "'abcdefg'…"
+        );
+        assert_eq!(
+            Quoted::windows("テキストファイル").max_width(9).to_string(),
+            "'テキス'…"
+        );
+        // A surrogate pair isn't split.
+        let units: Vec<u16> = "ab\u{1F600}".encode_utf16().collect();
+        assert_eq!(
+            Quoted::windows_raw(&units).max_width(5).to_string(),
+            "'ab'…"
+        );
+        assert_eq!(
+            Quoted::windows_raw(&units).max_width(6).to_string(),
+            "'ab\u{1F600}'"
+        );
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_alternate() {
+        for &(orig, expected) in WINDOWS_MAYBE.iter().chain(BOTH_MAYBE) {
+            assert_eq!(format!("{:#}", Quoted::windows(orig)), expected);
+        }
+        assert_eq!(format!("{:#}", Quoted::windows("foo")), "foo");
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_unquote() {
+        let roundtrip = |text: &str| {
+            let quoted = [
+                Quoted::windows(text),
+                Quoted::windows(text).force(false),
+                Quoted::windows(text).prefer(QuoteChar::Double),
+                Quoted::windows(text).symbolic_escapes(false),
+                Quoted::windows(text).ascii_only(true),
+            ];
+            for quoted in quoted.iter() {
+                assert_eq!(unquote_windows(&quoted.to_string()).unwrap(), text);
+            }
+        };
+        for &(orig, _) in WINDOWS_ALWAYS
+            .iter()
+            .chain(WINDOWS_MAYBE)
+            .chain(BOTH_ALWAYS)
+            .chain(BOTH_MAYBE)
+        {
+            roundtrip(orig);
+        }
+        roundtrip(&nest_bidi(17));
+        roundtrip("\u{1F600}\x00\u{85}\u{2018}\u{201C}");
 
-/// U+2028 LINE SEPARATOR and U+2029 PARAGRAPH SEPARATOR are currently the only
-/// in their categories. The terminals I tried don't treat them very specially,
-/// but gedit does.
-fn is_separator(ch: char) -> bool {
-    ch == '\u{2028}' || ch == '\u{2029}'
-}
+        for &(orig, _) in WINDOWS_RAW {
+            let quoted = Quoted::windows_raw(orig).to_string();
+            assert_eq!(unquote_windows_raw(&quoted).unwrap(), orig);
+            assert_eq!(unquote_windows(&quoted), Err(ParseError::InvalidUtf16));
+        }
 
-/// These two ranges in PropList.txt:
-/// LEFT-TO-RIGHT EMBEDDING..RIGHT-TO-LEFT OVERRIDE
-/// LEFT-TO-RIGHT ISOLATE..POP DIRECTIONAL ISOLATE
-fn is_bidi(ch: char) -> bool {
-    match ch {
-        '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}' => true,
-        _ => false,
+        const CASES: &[(&str, &str)] = &[
+            ("foo` bar", "foo bar"),
+            ("'a''b'", "a'b"),
+            ("'a\u{2019}\u{2018}b\u{2019}", "a\u{2018}b"),
+            ("\"a\"\"b`\"c\"", "a\"b\"c"),
+            ("\"$x`$y\"", "$x$y"),
+            ("\"`0`a`b`e`f`n`r`t`v`q\"", "\0\x07\x08\x1B\x0C\n\r\t\x0Bq"),
+            ("\"`u{41}`u{1F600}\"", "A\u{1F600}"),
+            ("''\"\"", ""),
+        ];
+        for &(quoted, expected) in CASES {
+            assert_eq!(unquote_windows(quoted).unwrap(), expected);
+        }
+
+        const ERRORS: &[(&str, ParseError)] = &[
+            ("'foo", ParseError::UnterminatedQuote),
+            ("'foo''", ParseError::UnterminatedQuote),
+            ("\"foo", ParseError::UnterminatedQuote),
+            ("\"foo`\"", ParseError::UnterminatedQuote),
+            ("foo`", ParseError::UnterminatedEscape),
+            ("\"`u{}\"", ParseError::InvalidEscape),
+            ("\"`u{41\"", ParseError::InvalidEscape),
+            ("\"`u{110000}\"", ParseError::InvalidEscape),
+        ];
+        for &(quoted, err) in ERRORS {
+            assert_eq!(unquote_windows(quoted), Err(err));
+        }
     }
-}
 
-/// Check whether text uses bidi in a potentially problematic way.
-///
-/// See https://trojansource.codes/ and
-/// https://www.unicode.org/reports/tr9/tr9-42.html.
-///
-/// If text fails this check then it's handled by write_escaped(), which
-/// escapes these bidi control characters no matter what.
-///
-/// We can safely assume that there are no newlines (or unicode separators)
-/// in the text because those would get it sent to write_escaped() earlier.
-/// In unicode terms, this is all a single paragraph.
-#[inline(never)]
-fn is_suspicious_bidi(text: &str) -> bool {
-    #[derive(Clone, Copy, PartialEq)]
-    enum Kind {
-        Formatting,
-        Isolate,
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_prefer() {
+        const DOUBLE: &[(&str, &str)] = &[
+            ("can't", r#""can't""#),
+            ("foo", r#""foo""#),
+            ("it's \"$x\"", r##""it's `"`$x`"""##),
+            ("can't\n", r#""can't`n""#),
+            ("can't\\", r#"'can''t\'"#),
+            ("C:\\dir", r#""C:\dir""#),
+            ("C:\\dir\\", r#"'C:\dir\'"#),
+        ];
+        const SINGLE: &[(&str, &str)] = &[
+            ("can't", "'can''t'"),
+            ("foo", "'foo'"),
+            ("it's \"$x\"", r#"'it''s "$x"'"#),
+            ("can't\n", r#""can't`n""#),
+            ("C:\\dir\\", r#"'C:\dir\'"#),
+        ];
+        for &(orig, expected) in DOUBLE {
+            assert_eq!(
+                Quoted::windows(orig).prefer(QuoteChar::Double).to_string(),
+                expected
+            );
+        }
+        for &(orig, expected) in SINGLE {
+            assert_eq!(
+                Quoted::windows(orig).prefer(QuoteChar::Single).to_string(),
+                expected
+            );
+        }
     }
-    const STACK_SIZE: usize = 16;
-    // Can't use a Vec because of no_std
-    let mut stack: [Option<Kind>; STACK_SIZE] = [None; STACK_SIZE];
-    let mut pos = 0;
-    for ch in text.chars() {
-        match ch {
-            '\u{202A}' | '\u{202B}' | '\u{202D}' | '\u{202E}' => {
-                if pos >= STACK_SIZE {
-                    // Suspicious amount of nesting.
-                    return true;
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_raw_valid() {
+        let tables = [WINDOWS_ALWAYS, WINDOWS_MAYBE, BOTH_ALWAYS, BOTH_MAYBE];
+        for &(orig, _) in tables.iter().flat_map(|table| table.iter()) {
+            let units: Vec<u16> = orig.encode_utf16().collect();
+            for &force in &[true, false] {
+                for &prefer in &[QuoteChar::Single, QuoteChar::Double] {
+                    assert_eq!(
+                        Quoted::windows_raw(&units).force(force).to_string(),
+                        Quoted::windows(orig).force(force).to_string()
+                    );
+                    assert_eq!(
+                        Quoted::windows_raw(&units)
+                            .force(force)
+                            .prefer(prefer)
+                            .to_string(),
+                        Quoted::windows(orig)
+                            .force(force)
+                            .prefer(prefer)
+                            .to_string()
+                    );
                 }
-                stack[pos] = Some(Kind::Formatting);
-                pos += 1;
             }
-            '\u{202C}' => {
-                if pos == 0 {
-                    // Unpaired terminator.
-                    // Not necessarily dangerous, but suspicious and
-                    // could disrupt preceding text.
-                    return true;
-                }
-                pos -= 1;
-                if stack[pos] != Some(Kind::Formatting) {
-                    // Terminator doesn't match.
-                    // UAX #9 says to pop the stack until we find a match.
-                    // But we'll keep things simple and cautious.
-                    return true;
-                }
+        }
+        let bidi = nest_bidi(17);
+        let units: Vec<u16> = bidi.encode_utf16().collect();
+        assert_eq!(
+            Quoted::windows_raw(&units).to_string(),
+            Quoted::windows(&bidi).to_string()
+        );
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_raw_utf8() {
+        let tables = [WINDOWS_ALWAYS, WINDOWS_MAYBE, BOTH_ALWAYS, BOTH_MAYBE];
+        for &(orig, _) in tables.iter().flat_map(|table| table.iter()) {
+            for &force in &[true, false] {
+                assert_eq!(
+                    Quoted::windows_raw_utf8(orig.as_bytes())
+                        .force(force)
+                        .to_string(),
+                    Quoted::windows(orig).force(force).to_string()
+                );
             }
-            '\u{2066}' | '\u{2067}' | '\u{2068}' => {
-                if pos >= STACK_SIZE {
-                    return true;
-                }
-                stack[pos] = Some(Kind::Isolate);
-                pos += 1;
+        }
+
+        const CASES: &[(&[u8], &str)] = &[
+            (b"foo\xFF", "foo\u{FFFD}"),
+            (b"\xFF\xFE", "\u{FFFD}\u{FFFD}"),
+            (b"a b\xE2\x80", "'a b\u{FFFD}'"),
+            (b"\xFFa\tb", "\"\u{FFFD}a`tb\""),
+            (b"$\xC3(", "'$\u{FFFD}('"),
+        ];
+        for &(orig, expected) in CASES {
+            let lossy = String::from_utf8_lossy(orig);
+            for &force in &[true, false] {
+                assert_eq!(
+                    Quoted::windows_raw_utf8(orig).force(force).to_string(),
+                    Quoted::windows(&lossy).force(force).to_string()
+                );
             }
-            '\u{2069}' => {
-                if pos == 0 {
-                    return true;
+            assert_eq!(
+                Quoted::windows_raw_utf8(orig).force(false).to_string(),
+                expected
+            );
+            assert!(!Quoted::windows_raw_utf8(orig).is_safe_bare());
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_wtf8() {
+        fn encode(units: &[u16]) -> Vec<u8> {
+            let mut bytes = Vec::new();
+            for res in core::char::decode_utf16(units.iter().cloned()) {
+                match res {
+                    Ok(ch) => bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+                    Err(err) => {
+                        let unit = err.unpaired_surrogate();
+                        bytes.push(0xE0 | (unit >> 12) as u8);
+                        bytes.push(0x80 | (unit >> 6 & 0x3F) as u8);
+                        bytes.push(0x80 | (unit & 0x3F) as u8);
+                    }
                 }
-                pos -= 1;
-                if stack[pos] != Some(Kind::Isolate) {
-                    return true;
+            }
+            bytes
+        }
+
+        const CASES: &[(&[u16], &str)] = &[
+            (&[0x78, 0xD800], r#""x`u{D800}""#),
+            (&[0xDC00, 0x61, 0x20, 0x62], r#""`u{DC00}a b""#),
+            (&[0xD800, 0xD800], r#""`u{D800}`u{D800}""#),
+            (&[0x61, 0xD83D, 0xDE00], "a\u{1F600}"),
+            (&[0x24, 0xDBFF], r#""`$`u{DBFF}""#),
+        ];
+        for &(units, expected) in CASES {
+            let bytes = encode(units);
+            assert_eq!(Quoted::wtf8(&bytes).force(false).to_string(), expected);
+            for &policy in &[
+                InvalidPolicy::Escape,
+                InvalidPolicy::Replace,
+                InvalidPolicy::Strip,
+            ] {
+                for &force in &[true, false] {
+                    assert_eq!(
+                        Quoted::wtf8(&bytes)
+                            .invalid(policy)
+                            .force(force)
+                            .to_string(),
+                        Quoted::windows_raw(units)
+                            .invalid(policy)
+                            .force(force)
+                            .to_string()
+                    );
                 }
             }
-            _ => (),
         }
+
+        // A surrogate is never cut in half.
+        let bytes = encode(&[0x61, 0x62, 0xD800, 0x63]);
+        for len in 0..bytes.len() + 10 {
+            let quoted = Quoted::wtf8(&bytes).max_output_len(len).to_string();
+            assert!(!quoted.contains('\u{FFFD}'));
+        }
+
+        // Bytes that aren't WTF-8 at all are replaced.
+        assert_eq!(Quoted::wtf8(b"a\xFF").force(false).to_string(), "a\u{FFFD}");
     }
-    pos != 0
-}
 
-#[cfg(feature = "native")]
-mod native {
-    use super::*;
+    #[cfg(feature = "native")]
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_raw_quotable() {
+        for &(orig, expected) in WINDOWS_RAW {
+            assert_eq!(orig.quote().to_string(), expected);
+            assert_eq!(Quoted::from(orig).to_string(), expected);
+        }
+    }
 
-    /// An extension trait to apply quoting to strings.
-    ///
-    /// This is implemented on [`str`], [`OsStr`] and [`Path`].
-    ///
-    /// For finer control, see the constructors on [`Quoted`].
-    pub trait Quotable {
-        /// Returns an object that implements [`Display`] for printing strings with
-        /// proper quoting and escaping for the platform.
-        ///
-        /// On Unix this corresponds to bash/ksh syntax, on Windows PowerShell syntax
-        /// is used.
-        ///
-        /// # Examples
-        ///
-        /// ```
-        /// use std::path::Path;
-        /// use os_display::Quotable;
-        ///
-        /// let path = Path::new("foo/bar.baz");
-        ///
-        /// println!("Found file {}", path.quote()); // Prints "Found file 'foo/bar.baz'"
-        /// ```
-        fn quote(&self) -> Quoted<'_>;
+    #[cfg(feature = "c")]
+    #[test]
+    fn c() {
+        const CASES: &[(&str, &str)] = &[
+            ("foo", r#""foo""#),
+            ("", r#""""#),
+            ("a\nb\"c", r#""a\nb\"c""#),
+            ("a\\b\t\r", r#""a\\b\t\r""#),
+            ("a\0b", r#""a\0b""#),
+            ("a\x001", r#""a\0""1""#),
+            ("\x02AB", r#""\x02""AB""#),
+            ("\x02GH", r#""\x02GH""#),
+            ("it's $x", r#""it's $x""#),
+            ("??/", r#""?\?/""#),
+            ("???/", r#""?\?\?/""#),
+            ("????=", r#""?\?\?\?=""#),
+            ("\u{2028}", r#""\xE2\x80\xA8""#),
+            ("café", r#""café""#),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::c(orig).to_string(), expected);
+            assert_eq!(Quoted::c(orig).force(false).to_string(), expected);
+        }
+        assert_eq!(Quoted::c_raw(b"foo\xFF").to_string(), r#""foo\xFF""#);
+        assert_eq!(Quoted::c_raw(b"\xFFbar").to_string(), r#""\xFF""bar""#);
+        assert_eq!(
+            Quoted::c("\0\x07").symbolic_escapes(false).to_string(),
+            r#""\x00\x07""#
+        );
+        assert_eq!(
+            Quoted::c("\0\x07").symbolic_escapes(true).to_string(),
+            r#""\0\a""#
+        );
+    }
 
-        /// Like `quote()`, but don't actually add quotes unless necessary because of
-        /// whitespace or special characters.
-        ///
-        /// # Examples
-        ///
-        /// ```
-        /// use std::path::Path;
-        /// use os_display::Quotable;
-        ///
-        /// let foo = Path::new("foo/bar.baz");
-        /// let bar = "foo bar";
-        ///
-        /// println!("{}: Not found", foo.maybe_quote()); // Prints "foo/bar.baz: Not found"
-        /// println!("{}: Not found", bar.maybe_quote()); // Prints "'foo bar': Not found"
-        /// ```
-        fn maybe_quote(&self) -> Quoted<'_> {
-            let mut quoted = self.quote();
-            quoted.force_quote = false;
-            quoted
+    #[cfg(feature = "json")]
+    #[test]
+    fn json() {
+        // serde_json::to_string() gives the same output. It uses lowercase hex.
+        const SERDE: &[(&str, &str)] = &[
+            ("foo", r#""foo""#),
+            ("", r#""""#),
+            ("a\nb\"c", r#""a\nb\"c""#),
+            ("\\\t\r\x08\x0C", r#""\\\t\r\b\f""#),
+            ("\0\x1F", r#""\u0000\u001f""#),
+            ("it's $x/y", r#""it's $x/y""#),
+            ("café \u{1F600}", "\"café \u{1F600}\""),
+        ];
+        for &(orig, expected) in SERDE {
+            assert_eq!(Quoted::json(orig).lowercase_hex(true).to_string(), expected);
+        }
+        // serde_json leaves these alone.
+        const EXTRA: &[(&str, &str)] = &[
+            ("\x7F", r#""\u007F""#),
+            ("\u{2028}", r#""\u2028""#),
+            ("user\u{202E}", r#""user\u202E""#),
+        ];
+        for &(orig, expected) in EXTRA {
+            assert_eq!(Quoted::json(orig).to_string(), expected);
         }
+        assert_eq!(
+            Quoted::json("\u{1F600}").ascii_only(true).to_string(),
+            r#""\uD83D\uDE00""#
+        );
+        assert_eq!(Quoted::json_raw(b"x\xFFy").to_string(), r#""x\uDCFFy""#);
+        assert_eq!(
+            Quoted::json("\n").symbolic_escapes(false).to_string(),
+            r#""\u000A""#
+        );
     }
 
-    impl Quotable for str {
-        fn quote(&self) -> Quoted<'_> {
-            Quoted::native(self)
+    #[cfg(feature = "python")]
+    #[test]
+    fn python() {
+        // These match repr().
+        const CASES: &[(&str, &str)] = &[
+            ("foo", "'foo'"),
+            ("", "''"),
+            ("can't", r#""can't""#),
+            ("a\"b", r#"'a"b'"#),
+            ("a'b\"c", r#"'a\'b"c'"#),
+            ("a\\b\nc", r#"'a\\b\nc'"#),
+            ("\t\r\0\x1B\x7F", r#"'\t\r\x00\x1b\x7f'"#),
+            ("a\u{A0}b\u{85}", r#"'a\xa0b\x85'"#),
+            ("\u{2028}\u{202E}", r#"'\u2028\u202e'"#),
+            ("café \u{1F600} $x", "'café \u{1F600} $x'"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::python(orig).to_string(), expected);
+            assert_eq!(Quoted::python(orig).force(false).to_string(), expected);
         }
+        assert_eq!(
+            Quoted::python("\u{1F600}").ascii_only(true).to_string(),
+            r#"'\U0001f600'"#
+        );
+        assert_eq!(
+            Quoted::python("\x7F").lowercase_hex(false).to_string(),
+            r#"'\x7F'"#
+        );
+        assert_eq!(
+            Quoted::python("can't")
+                .prefer(QuoteChar::Single)
+                .to_string(),
+            r#"'can\'t'"#
+        );
+        assert_eq!(
+            Quoted::python("a\"b").prefer(QuoteChar::Double).to_string(),
+            r#""a\"b""#
+        );
     }
 
-    #[cfg(feature = "std")]
-    impl Quotable for OsStr {
-        fn quote(&self) -> Quoted<'_> {
-            Quoted::native_raw(self)
+    #[cfg(feature = "xonsh")]
+    #[test]
+    fn xonsh() {
+        // (orig, maybe, always)
+        const CASES: &[(&str, &str, &str)] = &[
+            ("foo", "foo", "'foo'"),
+            ("-v", "-v", "'-v'"),
+            ("a/b.txt", "a/b.txt", "'a/b.txt'"),
+            ("x=1,2:3", "x=1,2:3", "'x=1,2:3'"),
+            ("café", "café", "'café'"),
+            ("", "''", "''"),
+            ("$HOME", "'$HOME'", "'$HOME'"),
+            ("${HOME}", "'${HOME}'", "'${HOME}'"),
+            ("$(ls)", "'$(ls)'", "'$(ls)'"),
+            ("@(x)", "'@(x)'", "'@(x)'"),
+            ("{a,b}", "'{a,b}'", "'{a,b}'"),
+            ("my file", "'my file'", "'my file'"),
+            ("~", "'~'", "'~'"),
+            ("*.txt", "'*.txt'", "'*.txt'"),
+            ("a|b", "'a|b'", "'a|b'"),
+            ("can't", r#""can't""#, r#""can't""#),
+            ("can't $x", r#"'can\'t $x'"#, r#"'can\'t $x'"#),
+            ("a\\b", r#"'a\\b'"#, r#"'a\\b'"#),
+            ("a\nb", r#"'a\nb'"#, r#"'a\nb'"#),
+            ("a\u{202E}", r#"'a\u202e'"#, r#"'a\u202e'"#),
+        ];
+        for &(orig, maybe, always) in CASES {
+            assert_eq!(Quoted::xonsh(orig).force(false).to_string(), maybe);
+            assert_eq!(Quoted::xonsh(orig).to_string(), always);
+            assert_eq!(Quoted::xonsh(orig).is_safe_bare(), orig == maybe);
         }
+        assert_eq!(
+            Quoted::xonsh("$x").prefer(QuoteChar::Double).to_string(),
+            r#""$x""#
+        );
     }
 
-    #[cfg(feature = "std")]
-    impl Quotable for Path {
-        fn quote(&self) -> Quoted<'_> {
-            Quoted::native_raw(self.as_ref())
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml() {
+        // These match the toml crate, except that it leaves tabs alone.
+        const CASES: &[(&str, &str)] = &[
+            ("foo", r#""foo""#),
+            ("", r#""""#),
+            ("a\tb", r#""a\tb""#),
+            ("a\"b", r#"'a"b'"#),
+            (r"C:\Users", r"'C:\Users'"),
+            ("it's", r#""it's""#),
+            ("it's \"x\"", r#""it's \"x\"""#),
+            ("a\"b\n", r#""a\"b\n""#),
+            ("\r\x08\x0C\0\x1B\x7F", r#""\r\b\f\u0000\u001B\u007F""#),
+            ("\u{85}\u{2028}\u{202E}", r#""\u0085\u2028\u202E""#),
+            ("café \u{1F600} $x", "\"café \u{1F600} $x\""),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::toml(orig).to_string(), expected);
+            assert_eq!(Quoted::toml(orig).force(false).to_string(), expected);
         }
+        assert_eq!(
+            Quoted::toml("\u{1F600}").ascii_only(true).to_string(),
+            r#""\U0001F600""#
+        );
+        assert_eq!(
+            Quoted::toml("foo").prefer(QuoteChar::Single).to_string(),
+            "'foo'"
+        );
+        assert_eq!(
+            Quoted::toml("a\"b").prefer(QuoteChar::Double).to_string(),
+            r#""a\"b""#
+        );
+        assert_eq!(
+            Quoted::toml("a\tb").prefer(QuoteChar::Single).to_string(),
+            r#""a\tb""#
+        );
     }
 
-    impl<'a, T: Quotable + ?Sized> From<&'a T> for Quoted<'a> {
-        fn from(val: &'a T) -> Self {
-            val.quote()
+    #[cfg(feature = "yaml")]
+    #[test]
+    fn yaml() {
+        // (orig, maybe, always)
+        const CASES: &[(&str, &str, &str)] = &[
+            ("foo", "foo", "'foo'"),
+            ("foo bar", "foo bar", "'foo bar'"),
+            ("", "''", "''"),
+            ("a: b", "'a: b'", "'a: b'"),
+            ("a:b", "a:b", "'a:b'"),
+            ("a:", "'a:'", "'a:'"),
+            ("- x", "'- x'", "'- x'"),
+            ("-x", "'-x'", "'-x'"),
+            ("? x", "'? x'", "'? x'"),
+            ("a #b", "'a #b'", "'a #b'"),
+            ("a#b", "a#b", "'a#b'"),
+            ("[a]", "'[a]'", "'[a]'"),
+            ("a, b", "'a, b'", "'a, b'"),
+            ("*ref", "'*ref'", "'*ref'"),
+            (" x", "' x'", "' x'"),
+            ("x ", "'x '", "'x '"),
+            ("true", "'true'", "'true'"),
+            ("Off", "'Off'", "'Off'"),
+            ("~", "'~'", "'~'"),
+            ("null", "'null'", "'null'"),
+            ("123", "'123'", "'123'"),
+            ("-1.5", "'-1.5'", "'-1.5'"),
+            (".5", "'.5'", "'.5'"),
+            ("2001-12-14", "'2001-12-14'", "'2001-12-14'"),
+            ("it's", "it's", r#""it's""#),
+            ("'x'", r#""'x'""#, r#""'x'""#),
+            ("it's \"x\"", "it's \"x\"", "'it''s \"x\"'"),
+            ("line1\nline2", r#""line1\nline2""#, r#""line1\nline2""#),
+            ("a\tb\\\"", r#""a\tb\\\"""#, r#""a\tb\\\"""#),
+            (
+                "\x1B\u{85}\u{2028}",
+                r#""\x1B\x85\u2028""#,
+                r#""\x1B\x85\u2028""#,
+            ),
+            ("a\u{A0}b", "'a\u{A0}b'", "'a\u{A0}b'"),
+            ("café", "café", "'café'"),
+        ];
+        for &(orig, maybe, always) in CASES {
+            assert_eq!(Quoted::yaml(orig).force(false).to_string(), maybe);
+            assert_eq!(Quoted::yaml(orig).to_string(), always);
+            assert_eq!(Quoted::yaml(orig).is_safe_bare(), orig == maybe);
         }
+        assert_eq!(
+            Quoted::yaml("\u{1F600}").ascii_only(true).to_string(),
+            r#""\U0001F600""#
+        );
+        assert_eq!(
+            Quoted::yaml("foo").prefer(QuoteChar::Double).to_string(),
+            r#""foo""#
+        );
+        assert_eq!(
+            Quoted::yaml("it's").prefer(QuoteChar::Single).to_string(),
+            "'it''s'"
+        );
     }
-}
 
-#[cfg(feature = "native")]
-pub use crate::native::Quotable;
+    #[cfg(feature = "csv")]
+    #[test]
+    fn csv() {
+        const CASES: &[(&str, &str)] = &[
+            ("foo", "foo"),
+            ("", r#""""#),
+            ("a,b", r#""a,b""#),
+            ("a\"b", r#""a""b""#),
+            ("\"", r#""""""#),
+            ("a\nb", "\"a\nb\""),
+            ("a\r\nb", "\"a\r\nb\""),
+            ("a;b", "a;b"),
+            ("a b\t", "a b\t"),
+            (" a ", " a "),
+            ("'a'", "'a'"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::csv(orig).to_string(), expected);
+            assert_eq!(Quoted::csv(orig).is_safe_bare(), orig == expected);
+        }
+        assert_eq!(Quoted::csv("foo").force(true).to_string(), r#""foo""#);
+        assert_eq!(Quoted::csv("a;b").delimiter(';').to_string(), r#""a;b""#);
+        assert_eq!(Quoted::csv("a,b").delimiter(';').to_string(), "a,b");
+        assert_eq!(Quoted::csv("a\tb").delimiter('\t').to_string(), "\"a\tb\"");
 
-#[cfg(feature = "std")]
-#[cfg(test)]
-mod tests {
-    #![allow(unused)]
+        // Control codes aren't escaped, but they still can't hide text from
+        // max_width.
+        let quoted = Quoted::csv("\x1BABCDEFGHIJKLmnop");
+        assert_eq!(quoted.to_string(), "\x1BABCDEFGHIJKLmnop");
+        assert_eq!(quoted.max_width(6).to_string(), "\x1BABCDE…");
+    }
 
-    use super::*;
+    #[cfg(feature = "csv")]
+    #[test]
+    #[should_panic]
+    fn csv_delimiter_quote() {
+        let _ = Quoted::csv("foo").delimiter('"');
+    }
 
-    use std::string::{String, ToString};
+    #[cfg(all(feature = "heapless", feature = "unix"))]
+    #[test]
+    fn quote_into_heapless() {
+        let mut buf = heapless::String::<32>::new();
+        Quoted::unix("foo bar")
+            .quote_into_heapless(&mut buf)
+            .unwrap();
+        assert_eq!(buf, "'foo bar'");
+        buf.clear();
+        Quoted::unix("a\tb").quote_into_heapless(&mut buf).unwrap();
+        assert_eq!(buf, r"$'a\tb'");
 
-    const BOTH_ALWAYS: &[(&str, &str)] = &[
-        ("foo", "'foo'"),
-        ("", "''"),
-        ("foo/bar.baz", "'foo/bar.baz'"),
-        ("can't", r#""can't""#),
-    ];
-    const BOTH_MAYBE: &[(&str, &str)] = &[
-        ("foo", "foo"),
-        ("", "''"),
-        ("foo bar", "'foo bar'"),
-        ("$foo", "'$foo'"),
-        ("-", "-"),
-        ("a#b", "a#b"),
-        ("#ab", "'#ab'"),
-        ("a~b", "a~b"),
-        ("!", "'!'"),
-        ("}", ("'}'")),
-        ("\u{200B}", "'\u{200B}'"),
-        ("\u{200B}a", "'\u{200B}a'"),
-        ("a\u{200B}", "a\u{200B}"),
-        ("\u{2000}", "'\u{2000}'"),
-        // Odd but safe bidi
-        (
-            "\u{2067}\u{2066}abc\u{2069}\u{2066}def\u{2069}\u{2069}",
-            "'\u{2067}\u{2066}abc\u{2069}\u{2066}def\u{2069}\u{2069}'",
-        ),
-    ];
+        // An exact fit.
+        let text = "x".repeat(30);
+        buf.clear();
+        Quoted::unix(&text).quote_into_heapless(&mut buf).unwrap();
+        assert_eq!(buf.len(), 32);
+
+        // Too long, including after escaping.
+        buf.clear();
+        buf.push_str("ls ").unwrap();
+        let text = "x".repeat(40);
+        assert_eq!(
+            Quoted::unix(&text).quote_into_heapless(&mut buf),
+            Err(crate::CapacityError)
+        );
+        assert_eq!(buf, "ls ");
+        let text = "\0".repeat(10);
+        assert_eq!(
+            Quoted::unix(&text).quote_into_heapless(&mut buf),
+            Err(crate::CapacityError)
+        );
+        assert_eq!(buf, "ls ");
+    }
 
-    const UNIX_ALWAYS: &[(&str, &str)] = &[
-        (r#"can'"t"#, r#"'can'\''"t'"#),
-        (r#"can'$t"#, r#"'can'\''$t'"#),
-        ("foo\nb\ta\r\\\0`r", r#"$'foo\nb\ta\r\\\x00`r'"#),
-        ("foo\x02", r#"$'foo\x02'"#),
-        (r#"'$''"#, r#"\''$'\'\'"#),
-    ];
-    const UNIX_MAYBE: &[(&str, &str)] = &[
-        ("-x", "-x"),
-        ("a,b", "a,b"),
-        ("a\\b", "'a\\b'"),
-        ("\x02AB", "$'\\x02'$'AB'"),
-        ("\x02GH", "$'\\x02GH'"),
-        ("\t", r#"$'\t'"#),
-        ("\r", r#"$'\r'"#),
-        ("\u{85}", r#"$'\xC2\x85'"#),
-        ("\u{85}a", r#"$'\xC2\x85'$'a'"#),
-        ("\u{2028}", r#"$'\xE2\x80\xA8'"#),
-        // Dangerous bidi
-        (
-            "user\u{202E} \u{2066}// Check if admin\u{2069} \u{2066}",
-            r#"$'user\xE2\x80\xAE \xE2\x81\xA6// Check if admin\xE2\x81\xA9 \xE2\x81\xA6'"#,
-        ),
-    ];
-    const UNIX_RAW: &[(&[u8], &str)] = &[
-        (b"foo\xFF", r#"$'foo\xFF'"#),
-        (b"foo\xFFbar", r#"$'foo\xFF'$'bar'"#),
-    ];
+    #[cfg(feature = "verbatim")]
+    #[test]
+    fn verbatim() {
+        const CASES: &[(&str, &str)] = &[
+            ("foo", "«foo»"),
+            ("", "«»"),
+            ("a b", "«a b»"),
+            ("a\tb\n", "«a\tb\n»"),
+            ("\x1B[31mred\x1B[0m", "«\x1B[31mred\x1B[0m»"),
+            ("\0\x7F\u{202E}", "«\0\x7F\u{202E}»"),
+            ("'\"\\$", "«'\"\\$»"),
+            ("a»b«", "«a»»b«»"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(Quoted::verbatim(orig).to_string(), expected);
+            assert_eq!(Quoted::verbatim(orig).force(false).to_string(), expected);
+        }
+
+        // A raw escape character isn't mistaken for one of our color codes.
+        let quoted = Quoted::verbatim("\x1BABCDEFGHIJKLmnop");
+        assert_eq!(quoted.display_width(), 18);
+        assert_eq!(quoted.max_width(6).to_string(), "«\x1BABC»…");
+        assert_eq!(quoted.color(true).display_width(), 18);
+        assert_eq!(quoted.color(true).rendering().delimiter, Some('«'));
+    }
 
     #[cfg(feature = "unix")]
     #[test]
-    fn unix() {
-        for &(orig, expected) in UNIX_ALWAYS.iter().chain(BOTH_ALWAYS) {
-            assert_eq!(Quoted::unix(orig).to_string(), expected);
+    fn unix_env_assignment() {
+        const CASES: &[(&str, &str)] = &[
+            ("bar", "FOO=bar"),
+            ("a b", "FOO='a b'"),
+            ("it's", r#"FOO="it's""#),
+            ("a\nb", r#"FOO=$'a\nb'"#),
+            ("~x", "FOO='~x'"),
+            ("", "FOO=''"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(
+                Quoted::unix(orig)
+                    .force(false)
+                    .env_assignment("FOO")
+                    .unwrap()
+                    .to_string(),
+                expected
+            );
         }
-        for &(orig, expected) in UNIX_MAYBE.iter().chain(BOTH_MAYBE) {
-            assert_eq!(Quoted::unix(orig).force(false).to_string(), expected);
+        assert_eq!(
+            Quoted::unix("bar")
+                .env_assignment("_foo_1")
+                .unwrap()
+                .to_string(),
+            "_foo_1='bar'"
+        );
+        for &key in &["", "1FOO", "FOO BAR", "FO-O", "FÖÖ", "FOO=", "$FOO"] {
+            assert!(Quoted::unix("bar").env_assignment(key).is_none());
         }
-        for &(orig, expected) in UNIX_RAW {
-            assert_eq!(Quoted::unix_raw(orig).to_string(), expected);
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_env_assignment() {
+        const CASES: &[(&str, &str)] = &[
+            ("bar", "$env:FOO='bar'"),
+            ("a b", "$env:FOO='a b'"),
+            ("it's", r#"$env:FOO="it's""#),
+            ("a\nb", r#"$env:FOO="a`nb""#),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(
+                Quoted::windows(orig)
+                    .force(false)
+                    .env_assignment("FOO")
+                    .unwrap()
+                    .to_string(),
+                expected
+            );
         }
-        let bidi_ok = nest_bidi(16);
+        assert!(Quoted::windows("bar").env_assignment("FOO BAR").is_none());
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_command_line() {
+        let args: Vec<&str> = vec!["rm", "-rf", "my dir", "a b", "", "it's"];
         assert_eq!(
-            Quoted::unix(&bidi_ok).to_string(),
-            "'".to_string() + &bidi_ok + "'"
+            CommandLine::new(args.iter().map(|arg| Quoted::unix(arg))).to_string(),
+            r#"rm -rf 'my dir' 'a b' '' "it's""#
+        );
+        let args: Vec<&str> = vec![];
+        assert_eq!(
+            CommandLine::new(args.iter().map(|arg| Quoted::unix(arg))).to_string(),
+            ""
+        );
+        let args: Vec<&str> = vec![""];
+        assert_eq!(
+            CommandLine::new(args.iter().map(|arg| Quoted::unix(arg))).to_string(),
+            "''"
         );
-        let bidi_too_deep = nest_bidi(17);
-        assert!(Quoted::unix(&bidi_too_deep).to_string().starts_with('$'));
     }
 
-    const WINDOWS_ALWAYS: &[(&str, &str)] = &[
-        (r#"foo\bar"#, r#"'foo\bar'"#),
-        (r#"can'"t"#, r#"'can''"t'"#),
-        (r#"can'$t"#, r#"'can''$t'"#),
-        ("foo\nb\ta\r\\\0`r", r#""foo`nb`ta`r\`0``r""#),
-        ("foo\x02", r#""foo`u{02}""#),
-        (r#"'$''"#, r#"'''$'''''"#),
-    ];
-    const WINDOWS_MAYBE: &[(&str, &str)] = &[
-        ("-x", "'-x'"),
-        ("—x", "'—x'"),
-        ("a,b", "'a,b'"),
-        ("a\\b", "a\\b"),
-        ("‘", r#""‘""#),
-        ("‘\"", r#"''‘"'"#),
-        ("„\0", r#""`„`0""#),
-        ("\t", r#""`t""#),
-        ("\r", r#""`r""#),
-        ("\u{85}", r#""`u{85}""#),
-        ("\u{2028}", r#""`u{2028}""#),
-        (
-            "user\u{202E} \u{2066}// Check if admin\u{2069} \u{2066}",
-            r#""user`u{202E} `u{2066}// Check if admin`u{2069} `u{2066}""#,
-        ),
-    ];
-    const WINDOWS_RAW: &[(&[u16], &str)] = &[(&[b'x' as u16, 0xD800], r#""x`u{D800}""#)];
+    #[cfg(feature = "unix")]
+    #[test]
+    fn command_line_separator() {
+        let args = ["a", "b c", "d"];
+        let line = CommandLine::new(args.iter().map(|arg| Quoted::unix(arg)));
+        assert_eq!(line.clone().separator("\n").to_string(), "a\n'b c'\nd");
+        assert_eq!(line.clone().separator("; ").to_string(), "a; 'b c'; d");
+        assert_eq!(line.to_string(), "a 'b c' d");
+    }
 
     #[cfg(feature = "windows")]
     #[test]
-    fn windows() {
-        for &(orig, expected) in WINDOWS_ALWAYS.iter().chain(BOTH_ALWAYS) {
-            assert_eq!(Quoted::windows(orig).to_string(), expected);
-        }
-        for &(orig, expected) in WINDOWS_MAYBE.iter().chain(BOTH_MAYBE) {
-            assert_eq!(Quoted::windows(orig).force(false).to_string(), expected);
-        }
-        for &(orig, expected) in WINDOWS_RAW {
-            assert_eq!(Quoted::windows_raw(orig).to_string(), expected);
+    fn windows_command_line() {
+        let args: Vec<&str> = vec!["Remove-Item", "-Recurse", "my dir", "", "it's"];
+        assert_eq!(
+            CommandLine::new(args.iter().map(|arg| Quoted::windows(arg))).to_string(),
+            r#"Remove-Item '-Recurse' 'my dir' '' "it's""#
+        );
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn native_command_line() {
+        let args: Vec<&str> = vec!["ls", "my dir", ""];
+        let expected = args
+            .iter()
+            .map(|arg| arg.maybe_quote().to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        assert_eq!(CommandLine::new(args.clone()).to_string(), expected);
+        assert_eq!(CommandLine::new(args.iter().cloned()).to_string(), expected);
+    }
+
+    #[cfg(feature = "makefile")]
+    #[test]
+    fn makefile() {
+        const CASES: &[(&str, &str, &str)] = &[
+            ("foo", "foo", "'foo'"),
+            ("a$b", "a$$b", "'a$$b'"),
+            ("a#b", r#"a\#b"#, r#"'a\#b'"#),
+            ("a b", "a b", "'a b'"),
+            (" x", "$() x", "' x'"),
+            (r#"a\b"#, r#"a\b"#, r#"'a\b'"#),
+            (r#"a\#b"#, r#"a\\\#b"#, r#"'a\\\#b'"#),
+            (r#"a\\"#, r#"a\\$()"#, r#"'a\\'"#),
+            ("a\nb", r#"$$'a\nb'"#, r#"$$'a\nb'"#),
+            ("it's", "it's", r#""it's""#),
+            ("", "", "''"),
+        ];
+        for &(orig, make, shell) in CASES {
+            assert_eq!(Quoted::makefile(orig).to_string(), make);
+            assert_eq!(Quoted::makefile_shell(orig).to_string(), shell);
         }
-        let bidi_ok = nest_bidi(16);
         assert_eq!(
-            Quoted::windows(&bidi_ok).to_string(),
-            "'".to_string() + &bidi_ok + "'"
+            Quoted::makefile_shell("$HOME").force(false).to_string(),
+            "'$$HOME'"
+        );
+        assert_eq!(
+            Quoted::makefile_shell("foo").force(false).to_string(),
+            "foo"
         );
-        let bidi_too_deep = nest_bidi(17);
-        assert!(Quoted::windows(&bidi_too_deep).to_string().contains('`'));
     }
 
     #[cfg(feature = "native")]
@@ -541,6 +6683,10 @@ mod tests {
 
         assert_eq!("'\"".quote().to_string(), r#"'''"'"#);
         assert_eq!("x\0".quote().to_string(), r#""x`0""#);
+        assert_eq!('x'.quote().to_string(), "'x'");
+        assert_eq!('x'.maybe_quote().to_string(), "x");
+        assert_eq!('\n'.quote().to_string(), r#""`n""#);
+        assert_eq!('\''.quote().to_string(), r#"''''"#);
         assert_eq!(
             OsString::from_wide(&[b'x' as u16, 0xD800])
                 .quote()
@@ -560,10 +6706,36 @@ mod tests {
 
         assert_eq!("'\"".quote().to_string(), r#"\''"'"#);
         assert_eq!("x\0".quote().to_string(), r#"$'x\x00'"#);
+        assert_eq!('x'.quote().to_string(), "'x'");
+        assert_eq!('x'.maybe_quote().to_string(), "x");
+        assert_eq!('\n'.quote().to_string(), r#"$'\n'"#);
+        assert_eq!('\''.quote().to_string(), r#""'""#);
         assert_eq!(
             OsStr::from_bytes(b"x\xFF").quote().to_string(),
             r#"$'x\xFF'"#
         );
+        assert_eq!(
+            OsStr::from_bytes(b"x\xFF")
+                .maybe_quote()
+                .control_style(ControlStyle::Caret)
+                .to_string(),
+            "xM-^?"
+        );
+    }
+
+    #[cfg(feature = "unix")]
+    #[cfg(target_os = "wasi")]
+    #[test]
+    fn wasi_raw() {
+        use std::os::wasi::ffi::OsStrExt;
+
+        assert_eq!(Quoted::unix_raw(b"x\xFF").to_string(), r#"$'x\xFF'"#);
+        #[cfg(feature = "native")]
+        #[cfg(feature = "std")]
+        assert_eq!(
+            Quoted::native_raw(OsStr::from_bytes(b"x\xFF")).to_string(),
+            Quoted::unix_raw(b"x\xFF").to_string()
+        );
     }
 
     #[cfg(feature = "native")]
@@ -572,6 +6744,92 @@ mod tests {
     fn native() {
         assert_eq!("'\"".quote().to_string(), r#"\''"'"#);
         assert_eq!("x\0".quote().to_string(), r#"$'x\x00'"#);
+        assert_eq!('\n'.quote().to_string(), r#"$'\n'"#);
+    }
+
+    #[cfg(feature = "command")]
+    #[cfg(windows)]
+    #[test]
+    fn quote_command() {
+        use std::process::Command;
+
+        let mut cmd = Command::new("my prog");
+        cmd.args(&["-x", "a b", ""])
+            .env("FOO", "bar")
+            .env_remove("BAR");
+        assert_eq!(
+            crate::quote_command(&cmd),
+            "$env:FOO='bar'; & 'my prog' '-x' 'a b' ''"
+        );
+        assert_eq!(crate::quote_command(&Command::new("ls")), "ls");
+    }
+
+    #[cfg(feature = "command")]
+    #[cfg(not(windows))]
+    #[test]
+    fn quote_command() {
+        use std::process::Command;
+
+        let mut cmd = Command::new("my prog");
+        cmd.args(&["-x", "a b", ""])
+            .env("FOO", "a b")
+            .env("LC_ALL", "C")
+            .env_remove("BAR");
+        cmd.env("NOT-VALID", "x");
+        assert_eq!(
+            crate::quote_command(&cmd),
+            "FOO='a b' LC_ALL=C 'my prog' -x 'a b' ''"
+        );
+        assert_eq!(crate::quote_command(&Command::new("ls")), "ls");
+    }
+
+    #[cfg(feature = "command")]
+    #[cfg(unix)]
+    #[test]
+    fn quote_command_raw() {
+        use std::os::unix::ffi::OsStrExt;
+        use std::process::Command;
+
+        let mut cmd = Command::new("cat");
+        cmd.arg(OsStr::from_bytes(b"x\xFF"));
+        assert_eq!(crate::quote_command(&cmd), r#"cat $'x\xFF'"#);
+    }
+
+    #[cfg(feature = "native")]
+    #[cfg(unix)]
+    #[test]
+    fn quote_components() {
+        const CASES: &[(&str, &str)] = &[
+            ("/home/user/my file", "/home/user/'my file'"),
+            ("/", "/"),
+            ("", ""),
+            ("foo", "foo"),
+            ("my file/", "'my file'/"),
+            ("//a//b c/", "//a//'b c'/"),
+            ("./$x/../~", "./'$x'/../'~'"),
+            ("a\nb/c", "$'a\\nb'/c"),
+            // Not a separator here.
+            (r"C:\Users\my file", r"'C:\Users\my file'"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(crate::quote_components(Path::new(orig)), expected);
+        }
+    }
+
+    #[cfg(feature = "native")]
+    #[cfg(windows)]
+    #[test]
+    fn quote_components_windows() {
+        const CASES: &[(&str, &str)] = &[
+            (r"C:\Users\my file", r"C:\Users\'my file'"),
+            (r"C:\", r"C:\"),
+            (r"C:/a b/c\", r"C:/'a b'/c\"),
+            (r"\\server\share\x y", r"\\server\share\'x y'"),
+            (r"dir\$x", r"dir\'$x'"),
+        ];
+        for &(orig, expected) in CASES {
+            assert_eq!(crate::quote_components(Path::new(orig)), expected);
+        }
     }
 
     #[cfg(feature = "native")]
@@ -590,6 +6848,403 @@ mod tests {
         Path::new("foo").quote();
         Path::new("foo").to_owned().quote();
         Cow::Borrowed(Path::new("foo")).quote();
+
+        'x'.quote();
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn quoted_of() {
+        assert_eq!(Quoted::of("foo").to_string(), "foo".quote().to_string());
+        assert_eq!(
+            Quoted::of(Path::new("x")).to_string(),
+            Path::new("x").quote().to_string()
+        );
+        assert_eq!(Quoted::of(&'x').to_string(), 'x'.quote().to_string());
+        assert_eq!(
+            Quoted::of("a b".maybe_quote()).to_string(),
+            "a b".maybe_quote().to_string()
+        );
+        #[cfg(feature = "unix")]
+        assert_eq!(Quoted::of(&b"foo\xFF"[..]).to_string(), r"$'foo\xFF'");
+        #[cfg(feature = "unix")]
+        {
+            let text = std::ffi::CStr::from_bytes_with_nul(b"caf\xC3\xA9 \xFF\0").unwrap();
+            assert_eq!(text.quote().to_string(), r"$'café \xFF'");
+            assert_eq!(Quoted::of(text).to_string(), text.quote().to_string());
+        }
+        #[cfg(feature = "windows")]
+        assert_eq!(
+            Quoted::of(&[0x66_u16, 0xD800][..]).to_string(),
+            "\"f`u{D800}\""
+        );
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn quoted_slice() {
+        use std::ffi::OsString;
+
+        let args: Vec<OsString> = vec!["foo".into(), "my file".into(), "a b c".into()];
+        assert_eq!(
+            format!("args: {}", QuotedSlice::new(&args)),
+            "args: foo 'my file' 'a b c'"
+        );
+        assert_eq!(QuotedSlice::<OsString>::new(&[]).to_string(), "");
+        let names = ["x", ""];
+        assert_eq!(QuotedSlice::new(&names).to_string(), "x ''");
+        let owned = [String::from("a b"), String::from("c")];
+        assert_eq!(QuotedSlice::new(&owned).to_string(), "'a b' c");
+        // Each item is quoted like it would be on its own.
+        let paths = [Path::new("my dir"), Path::new("-v")];
+        assert_eq!(
+            QuotedSlice::new(&paths).to_string(),
+            format!("{} {}", paths[0].maybe_quote(), paths[1].maybe_quote())
+        );
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn quoted_slice_separator() {
+        let values = ["a", "b c", "d"];
+        let list = QuotedSlice::new(&values);
+        assert_eq!(list.separator("\n").to_string(), "a\n'b c'\nd");
+        assert_eq!(list.separator("; ").to_string(), "a; 'b c'; d");
+        // The separator isn't quoted, even if it's special.
+        assert_eq!(list.separator("").to_string(), "a'b c'd");
+        assert_eq!(QuotedSlice::new(&["a"]).separator("; ").to_string(), "a");
+    }
+
+    #[cfg(feature = "native")]
+    #[test]
+    fn owned() {
+        fn make(name: &str) -> QuotedBuf {
+            let text = String::from(name) + " bar";
+            text.maybe_quote().into_owned()
+        }
+
+        let quoted = make("foo");
+        assert_eq!(quoted.to_string(), "foo bar".maybe_quote().to_string());
+        assert_eq!(quoted.clone().to_string(), quoted.to_string());
+        assert_eq!(make("").to_string(), " bar".maybe_quote().to_string());
+
+        let path = std::path::PathBuf::from("foo");
+        let quoted = path.quote().into_owned();
+        drop(path);
+        assert_eq!(quoted.to_string(), "'foo'");
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_owned() {
+        let quoted = {
+            let bytes = std::vec![b'x', 0xFF];
+            Quoted::unix_raw(&bytes).into_owned()
+        };
+        assert_eq!(quoted.to_string(), r#"$'x\xFF'"#);
+        let quoted = {
+            let text = String::from("foo");
+            Quoted::unix(&text).force(false).into_owned()
+        };
+        assert_eq!(quoted.to_string(), "foo");
+        let quoted = {
+            let bytes = std::vec![b'a', b' ', 0xFF];
+            let quoted = Quoted::unix_raw(&bytes);
+            let owned = quoted.to_owned();
+            assert_eq!(quoted.to_string(), owned.to_string());
+            owned
+        };
+        assert_eq!(quoted.to_string(), r#"$'a \xFF'"#);
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_owned() {
+        let quoted = {
+            let units = std::vec![b'x' as u16, 0xD800];
+            Quoted::windows_raw(&units).into_owned()
+        };
+        assert_eq!(quoted.to_string(), r#""x`u{D800}""#);
+        let quoted = {
+            let units = std::vec![b'x' as u16, 0xD800];
+            Quoted::windows_raw(&units).to_owned()
+        };
+        assert_eq!(quoted.to_string(), r#""x`u{D800}""#);
+    }
+
+    #[test]
+    fn find_bidi() {
+        assert!(!contains_bidi(""));
+        assert!(!contains_bidi("foo bar"));
+        assert!(contains_bidi("\u{202E}abc"));
+        assert!(contains_bidi("a\u{2066}b\u{2069}"));
+
+        #[cfg(feature = "unix")]
+        {
+            assert!(!Quoted::unix("foo").contains_bidi());
+            assert!(Quoted::unix("foo\u{202E}").contains_bidi());
+            assert!(Quoted::unix_raw(b"\xFF\xE2\x80\xAE").contains_bidi());
+            assert!(!Quoted::unix_raw(b"\xFF\xE2\x80").contains_bidi());
+        }
+        #[cfg(feature = "windows")]
+        {
+            assert!(!Quoted::windows("foo").contains_bidi());
+            assert!(Quoted::windows_raw(&[0xD800, 0x202E]).contains_bidi());
+            assert!(!Quoted::windows_raw(&[0xD800, u16::from(b'x')]).contains_bidi());
+        }
+        #[cfg(feature = "native")]
+        {
+            assert!('\u{202E}'.quote().contains_bidi());
+            assert!(!'x'.quote().contains_bidi());
+        }
+        #[cfg(all(feature = "native", feature = "std"))]
+        {
+            assert!(OsStr::new("a\u{2067}").quote().contains_bidi());
+            assert!(!OsStr::new("a").quote().contains_bidi());
+        }
+        #[cfg(feature = "c")]
+        assert!(Quoted::c("\u{202E}").contains_bidi());
+        #[cfg(feature = "json")]
+        assert!(Quoted::json("\u{202E}").contains_bidi());
+        #[cfg(feature = "python")]
+        assert!(Quoted::python("\u{202E}").contains_bidi());
+        #[cfg(feature = "makefile")]
+        assert!(Quoted::makefile("\u{202E}").contains_bidi());
+    }
+
+    #[test]
+    fn trojan_source() {
+        // Modeled on the examples in https://trojansource.codes/trojan-source.pdf.
+        const SUSPICIOUS: &[&str] = &[
+            // Early return.
+            "\t\"\"\"\u{202E} return\u{2066}\n",
+            // Commenting out.
+            "/*\u{202E} } \u{2066}if (isAdmin)\u{2069} \u{2066} begin admins only */",
+            // Stretched string.
+            "if access_level != \"user\u{202E} \u{2066}// Check if admin\u{2069} \u{2066}\" {",
+            // Unopened.
+            "abc\u{202C}",
+            "abc\u{2069}",
+            // Mismatched.
+            "\u{2066}abc\u{202C}",
+            // Closed on the next line, too late.
+            "\u{202E}abc\n\u{202C}",
+        ];
+        for text in SUSPICIOUS {
+            assert!(is_suspicious(text), "{:?}", text);
+            assert!(contains_bidi(text));
+        }
+
+        const FINE: &[&str] = &[
+            "",
+            "plain",
+            "\u{2067}abc\u{2069}",
+            "\u{202B}abc\u{202C}",
+            "\u{202E}abc\u{202C}\n\u{2066}def\u{2069}",
+        ];
+        for text in FINE {
+            assert!(!is_suspicious(text), "{:?}", text);
+        }
+        assert!(!is_suspicious(&nest_bidi(16)));
+        assert!(is_suspicious(&nest_bidi(17)));
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_analyze() {
+        fn quote(whitespace: bool, special: bool, leading: bool) -> Classification {
+            Classification::NeedsQuote {
+                whitespace,
+                special,
+                leading,
+            }
+        }
+        fn escape(control: bool, invalid_unicode: bool, bidi: bool) -> Classification {
+            Classification::NeedsEscape {
+                control,
+                invalid_unicode,
+                bidi,
+            }
+        }
+
+        let cases = [
+            ("foo", Classification::Safe),
+            ("foo.txt", Classification::Safe),
+            ("", quote(false, false, false)),
+            ("foo bar", quote(true, false, false)),
+            ("foo\u{3000}bar", quote(true, false, false)),
+            ("foo$bar", quote(false, true, false)),
+            ("~foo", quote(false, false, true)),
+            ("#foo bar's", quote(true, true, true)),
+            ("foo\tbar", escape(true, false, false)),
+            ("foo\u{202E}bar", escape(false, false, true)),
+            ("\u{202E}foo\u{202C}", quote(false, false, true)),
+            ("\x1B[31m\u{202E}", escape(true, false, true)),
+        ];
+        for &(orig, expected) in cases.iter() {
+            assert_eq!(Quoted::unix(orig).analyze(), expected, "{:?}", orig);
+        }
+
+        assert_eq!(
+            Quoted::unix_raw(b"foo\xFF\n").analyze(),
+            escape(true, true, false)
+        );
+        assert_eq!(
+            Quoted::unix_raw(b"foo\xFF")
+                .invalid(InvalidPolicy::Replace)
+                .analyze(),
+            Classification::Safe
+        );
+        assert_eq!(
+            Quoted::unix("foo\u{202E}")
+                .bidi(BidiPolicy::Allow)
+                .analyze(),
+            Classification::Safe
+        );
+        assert_eq!(
+            Quoted::unix("foo").force(true).analyze(),
+            Classification::Safe
+        );
+        assert_eq!(
+            Quoted::unix("a*b").glob_safe(true).analyze(),
+            quote(false, true, false)
+        );
+
+        #[cfg(feature = "windows")]
+        {
+            assert_eq!(Quoted::windows("foo").analyze(), Classification::Safe);
+            assert_eq!(Quoted::windows("a b").analyze(), quote(true, false, false));
+            assert_eq!(Quoted::windows("1a").analyze(), quote(false, false, true));
+            assert_eq!(
+                Quoted::windows("a\u{201C}b").analyze(),
+                quote(false, true, false)
+            );
+            assert_eq!(
+                Quoted::windows("a\tb").analyze(),
+                escape(true, false, false)
+            );
+            assert_eq!(
+                Quoted::windows_raw(&[0x66, 0xD800]).analyze(),
+                escape(false, true, false)
+            );
+        }
+
+        #[cfg(feature = "json")]
+        {
+            assert_eq!(Quoted::json("foo").analyze(), quote(false, false, false));
+            assert_eq!(Quoted::json("a\nb").analyze(), escape(true, false, false));
+        }
+    }
+
+    #[test]
+    fn find_confusables() {
+        const CONFUSABLE: &[&str] = &[
+            "p\u{430}ypal",
+            "\u{430}pple",
+            "www.g\u{43E}\u{43E}gle.com",
+            "\u{3BF}k",
+            "x \u{41C}oscow",
+            "a1\u{430}",
+        ];
+        for text in CONFUSABLE {
+            assert!(contains_confusables(text), "{:?}", text);
+        }
+
+        const FINE: &[&str] = &[
+            "",
+            "paypal",
+            "café",
+            "\u{41C}\u{43E}\u{441}\u{43A}\u{432}\u{430} (Moscow)",
+            "\u{3B1}\u{3B2}\u{3B3}-abc",
+            "\u{430}_a",
+            "\u{65E5}\u{672C}abc",
+            "1\u{430}",
+        ];
+        for text in FINE {
+            assert!(!contains_confusables(text), "{:?}", text);
+        }
+
+        #[cfg(feature = "unix")]
+        {
+            assert!(!Quoted::unix("paypal").contains_confusables());
+            assert!(Quoted::unix("p\u{430}ypal").contains_confusables());
+            assert!(Quoted::unix_raw(b"p\xD0\xB0ypal\xFF").contains_confusables());
+        }
+        #[cfg(feature = "windows")]
+        assert!(Quoted::windows("p\u{430}ypal").contains_confusables());
+    }
+
+    #[cfg(any(
+        feature = "unix",
+        feature = "c",
+        feature = "json",
+        feature = "makefile",
+        all(feature = "native", not(windows))
+    ))]
+    #[test]
+    fn test_utf8_iter() {
+        type ByteStr = &'static [u8];
+        type Chunk = Result<&'static str, u8>;
+        const CASES: &[(ByteStr, &[Chunk])] = &[
+            (b"", &[]),
+            (b"hello", &[Ok("hello")]),
+            // Immediately invalid
+            (b"\xFF", &[Err(b'\xFF')]),
+            // Incomplete UTF-8
+            (b"\xC2", &[Err(b'\xC2')]),
+            (b"\xF4\x8F", &[Err(b'\xF4'), Err(b'\x8F')]),
+            (b"\xFF\xFF", &[Err(b'\xFF'), Err(b'\xFF')]),
+            (b"hello\xC2", &[Ok("hello"), Err(b'\xC2')]),
+            (b"\xFFhello", &[Err(b'\xFF'), Ok("hello")]),
+            (b"\xFF\xC2hello", &[Err(b'\xFF'), Err(b'\xC2'), Ok("hello")]),
+            (b"foo\xFFbar", &[Ok("foo"), Err(b'\xFF'), Ok("bar")]),
+            (
+                b"foo\xF4\x8Fbar",
+                &[Ok("foo"), Err(b'\xF4'), Err(b'\x8F'), Ok("bar")],
+            ),
+            (
+                b"foo\xFF\xC2bar",
+                &[Ok("foo"), Err(b'\xFF'), Err(b'\xC2'), Ok("bar")],
+            ),
+        ];
+        for &(case, expected) in CASES {
+            assert_eq!(
+                from_utf8_iter(case).collect::<Vec<_>>().as_slice(),
+                expected
+            );
+        }
+    }
+
+    #[cfg(feature = "unix")]
+    #[test]
+    fn unix_byte_sets() {
+        // These are the byte strings the sets replaced.
+        for byte in 0..=255 {
+            assert_eq!(
+                unix::SPECIAL_SHELL_CHARS.contains(byte),
+                b"|&;<>()$`\\\"'=^ ".contains(&byte)
+            );
+            assert_eq!(unix::GLOB_CHARS.contains(byte), b"*?[]{}".contains(&byte));
+            assert_eq!(
+                unix::DOUBLE_UNSAFE.contains(byte),
+                b"\"`$\\".contains(&byte)
+            );
+        }
+    }
+
+    #[cfg(feature = "windows")]
+    #[test]
+    fn windows_byte_sets() {
+        for byte in 0..=255 {
+            assert_eq!(
+                windows::SPECIAL_SHELL_CHARS.contains(byte),
+                b"|&;<>()$`\"'*?[]=,{} ".contains(&byte)
+            );
+            assert_eq!(
+                windows::DOUBLE_UNSAFE.contains(byte),
+                b"\"`$".contains(&byte)
+            );
+        }
     }
 
     fn nest_bidi(n: usize) -> String {
@@ -0,0 +1,125 @@
+use core::fmt::{self, Display, Formatter, Write};
+
+use crate::{unix, Options};
+
+/// Write text for a Makefile, without quoting it for the shell.
+///
+/// This is meant for variable assignments and prerequisite lists. In
+/// recipe lines # doesn't start a comment, so the \# escape would be
+/// passed on to the shell. Use a variable there instead.
+///
+/// Make has no syntax for control codes, so if there are any (or if there's
+/// suspicious bidi) we quote for the shell after all. That only works if the
+/// shell understands $'', which /bin/sh might not.
+pub(crate) fn write(f: &mut Formatter<'_>, text: &str, opts: &Options) -> fmt::Result {
+    // Isolate marks would become part of the value, so we escape instead.
+    if text.chars().any(|ch| opts.requires_escape(ch))
+        || opts.escapes_bidi(text.chars())
+        || opts.isolates_bidi(text.chars())
+    {
+        return write_shell(f, text, opts);
+    }
+    let mut out = MakeEscape::new(f, opts);
+    out.write_str(text)?;
+    out.finish()
+}
+
+/// Write text for a Makefile, quoted for the shell that runs the recipes.
+pub(crate) fn write_shell(f: &mut Formatter<'_>, text: &str, opts: &Options) -> fmt::Result {
+    struct Shell<'a> {
+        text: &'a str,
+        opts: &'a Options,
+    }
+
+    impl<'a> Display for Shell<'a> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            unix::write(f, self.text, self.opts)
+        }
+    }
+
+    let mut out = MakeEscape::new(f, opts);
+    write!(out, "{}", Shell { text, opts })?;
+    out.finish()
+}
+
+/// Escapes everything written to it for Make.
+///
+/// https://www.gnu.org/software/make/manual/html_node/Reference.html
+/// https://www.gnu.org/software/make/manual/html_node/Makefile-Contents.html
+struct MakeEscape<'a, 'b> {
+    f: &'a mut Formatter<'b>,
+    opts: &'a Options,
+    at_start: bool,
+    /// Backslashes are only special before # and at the end of the line,
+    /// so we hold on to them until we know what comes next.
+    backslashes: usize,
+}
+
+impl<'a, 'b> MakeEscape<'a, 'b> {
+    fn new(f: &'a mut Formatter<'b>, opts: &'a Options) -> Self {
+        MakeEscape {
+            f,
+            opts,
+            at_start: true,
+            backslashes: 0,
+        }
+    }
+
+    fn write_backslashes(&mut self, count: usize) -> fmt::Result {
+        for _ in 0..count {
+            self.f.write_char('\\')?;
+        }
+        self.backslashes = 0;
+        Ok(())
+    }
+
+    fn finish(mut self) -> fmt::Result {
+        if self.backslashes > 0 {
+            // A backslash at the end of a line continues it on the next one.
+            // $() expands to nothing, so it's a convenient way to avoid that.
+            let count = self.backslashes;
+            self.write_backslashes(count)?;
+            self.opts.write_syntax(self.f, |f| f.write_str("$()"))?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a, 'b> Write for MakeEscape<'a, 'b> {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        for ch in text.chars() {
+            if self.at_start {
+                self.at_start = false;
+                // Leading whitespace is stripped from variable values.
+                if ch == ' ' || ch == '\t' {
+                    self.opts.write_syntax(self.f, |f| f.write_str("$()"))?;
+                }
+            }
+            match ch {
+                '\\' => self.backslashes += 1,
+                '#' => {
+                    // Backslashes before \# have to be escaped as well.
+                    let count = self.backslashes;
+                    self.backslashes = 0;
+                    self.opts.write_syntax(self.f, |f| {
+                        for _ in 0..2 * count + 1 {
+                            f.write_char('\\')?;
+                        }
+                        f.write_char('#')
+                    })?;
+                }
+                '$' => {
+                    let count = self.backslashes;
+                    self.write_backslashes(count)?;
+                    self.opts.write_syntax(self.f, |f| f.write_str("$$"))?;
+                }
+                ch => {
+                    let count = self.backslashes;
+                    self.write_backslashes(count)?;
+                    self.f.write_char(ch)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
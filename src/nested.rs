@@ -0,0 +1,88 @@
+use crate::alloc::string::{String, ToString};
+
+use crate::Quoted;
+
+/// A quoting style for [`quote_nested`] and [`quote_all`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QuoteStyle {
+    /// bash/ksh syntax, as in [`Quoted::unix`].
+    #[cfg(feature = "unix")]
+    Unix,
+    /// PowerShell syntax, as in [`Quoted::windows`].
+    #[cfg(feature = "windows")]
+    Windows,
+}
+
+impl QuoteStyle {
+    fn quote(self, text: &str) -> Quoted<'_> {
+        match self {
+            #[cfg(feature = "unix")]
+            QuoteStyle::Unix => Quoted::unix(text),
+            #[cfg(feature = "windows")]
+            QuoteStyle::Windows => Quoted::windows(text),
+        }
+    }
+}
+
+/// Quote text `levels` times, so that it survives being parsed by that
+/// many shells.
+///
+/// This is for commands that pass a command line on to another shell, like
+/// `sh -c '...'` or `ssh host '...'`. Each level quotes the result of the
+/// level below it as a whole, only adding quotes where necessary. Zero
+/// levels leaves the text as it is.
+///
+/// Text with control codes gets `$'...'` syntax on Unix, which a plain
+/// POSIX `sh` at one of the inner levels won't understand.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "unix")] {
+/// use os_display::{quote_nested, QuoteStyle};
+///
+/// assert_eq!(quote_nested("my file", 1, QuoteStyle::Unix), "'my file'");
+/// assert_eq!(quote_nested("my file", 2, QuoteStyle::Unix), r#""'my file'""#);
+/// # }
+/// ```
+///
+/// # Optional
+/// This requires the (default) `alloc` feature.
+pub fn quote_nested(text: &str, levels: usize, style: QuoteStyle) -> String {
+    let mut text = text.to_string();
+    for _ in 0..levels {
+        text = style.quote(&text).force(false).to_string();
+    }
+    text
+}
+
+/// Quote each item of an iterator as it's needed, without collecting them
+/// first.
+///
+/// Each item is quoted only if necessary, as with
+/// [`Quotable::maybe_quote`][crate::Quotable::maybe_quote]. To join them
+/// into a single line, see [`CommandLine`][crate::CommandLine].
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "unix")] {
+/// use os_display::{quote_all, QuoteStyle};
+///
+/// let names = vec!["foo".to_string(), "my file".to_string()];
+/// let mut quoted = quote_all(&names, QuoteStyle::Unix);
+/// assert_eq!(quoted.next().unwrap().to_string(), "foo");
+/// assert_eq!(quoted.next().unwrap().to_string(), "'my file'");
+/// assert!(quoted.next().is_none());
+/// # }
+/// ```
+///
+/// # Optional
+/// This requires the (default) `alloc` feature.
+pub fn quote_all<'a, I, T>(items: I, style: QuoteStyle) -> impl Iterator<Item = Quoted<'a>>
+where
+    I: IntoIterator<Item = &'a T>,
+    T: AsRef<str> + ?Sized + 'a,
+{
+    items
+        .into_iter()
+        .map(move |item| style.quote(item.as_ref()).force(false))
+}
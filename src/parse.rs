@@ -0,0 +1,366 @@
+use core::fmt::{self, Display, Formatter};
+use core::iter::Peekable;
+use core::str::Chars;
+
+use crate::alloc::string::String;
+use crate::alloc::vec::Vec;
+#[cfg(feature = "roundtrip")]
+use crate::QuoteStyle;
+
+/// An error from parsing quoted text.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// A quote was opened but never closed.
+    UnterminatedQuote,
+    /// The text ended in the middle of an escape.
+    UnterminatedEscape,
+    /// An escape doesn't stand for a valid character.
+    InvalidEscape,
+    /// The escapes produced bytes that aren't valid UTF-8.
+    InvalidUtf8,
+    /// The escapes produced code units that aren't valid UTF-16.
+    InvalidUtf16,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ParseError::UnterminatedQuote => "unterminated quote",
+            ParseError::UnterminatedEscape => "unterminated escape",
+            ParseError::InvalidEscape => "invalid escape",
+            ParseError::InvalidUtf8 => "invalid UTF-8",
+            ParseError::InvalidUtf16 => "invalid UTF-16",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+/// Parse a single word of bash/ksh syntax, undoing the quoting of
+/// [`Quoted::unix`][crate::Quoted::unix].
+///
+/// This understands single quotes, double quotes, `$''` strings and
+/// backslashes. It doesn't do any kind of expansion, so `$foo` and `*` are
+/// kept as they are, and it doesn't split on whitespace.
+///
+/// # Examples
+/// ```
+/// use os_display::unquote_unix;
+///
+/// assert_eq!(unquote_unix(r"'foo bar'").unwrap(), "foo bar");
+/// assert_eq!(unquote_unix(r"$'foo\nbar'").unwrap(), "foo\nbar");
+/// assert!(unquote_unix("'foo").is_err());
+/// ```
+#[cfg(feature = "unix")]
+pub fn unquote_unix(text: &str) -> Result<String, ParseError> {
+    // \x escapes can produce any byte, so we can only check for valid UTF-8
+    // at the end.
+    String::from_utf8(unquote_unix_bytes(text)?).map_err(|_| ParseError::InvalidUtf8)
+}
+
+/// Like [`unquote_unix`], but return bytes, which may not be valid UTF-8.
+#[cfg(feature = "unix")]
+fn unquote_unix_bytes(text: &str) -> Result<Vec<u8>, ParseError> {
+    let mut out = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\'' => loop {
+                match chars.next() {
+                    Some('\'') => break,
+                    Some(ch) => push_char(&mut out, ch),
+                    None => return Err(ParseError::UnterminatedQuote),
+                }
+            },
+            '"' => unix_double_quoted(&mut chars, &mut out)?,
+            '$' if chars.peek() == Some(&'\'') => {
+                chars.next();
+                unix_ansi_c(&mut chars, &mut out)?;
+            }
+            '\\' => match chars.next() {
+                // A line continuation.
+                Some('\n') => {}
+                Some(ch) => push_char(&mut out, ch),
+                None => return Err(ParseError::UnterminatedEscape),
+            },
+            ch => push_char(&mut out, ch),
+        }
+    }
+    Ok(out)
+}
+
+/// The part of a "" string after the opening quote.
+#[cfg(feature = "unix")]
+fn unix_double_quoted(
+    chars: &mut Peekable<Chars<'_>>,
+    out: &mut Vec<u8>,
+) -> Result<(), ParseError> {
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(()),
+            Some('\\') => match chars.next() {
+                Some('\n') => {}
+                Some(ch) if "$`\"\\".contains(ch) => push_char(out, ch),
+                // Other backslashes are kept.
+                Some(ch) => {
+                    out.push(b'\\');
+                    push_char(out, ch);
+                }
+                None => return Err(ParseError::UnterminatedQuote),
+            },
+            Some(ch) => push_char(out, ch),
+            None => return Err(ParseError::UnterminatedQuote),
+        }
+    }
+}
+
+/// The part of a $'' string after the opening quote.
+///
+/// The escapes are the ones bash supports:
+/// https://www.gnu.org/software/bash/manual/html_node/ANSI_002dC-Quoting.html
+#[cfg(feature = "unix")]
+fn unix_ansi_c(chars: &mut Peekable<Chars<'_>>, out: &mut Vec<u8>) -> Result<(), ParseError> {
+    loop {
+        match chars.next() {
+            Some('\\') => {}
+            Some('\'') => return Ok(()),
+            Some(ch) => {
+                push_char(out, ch);
+                continue;
+            }
+            None => return Err(ParseError::UnterminatedQuote),
+        }
+        // \nnn is a byte in octal. Bash ignores the bits that don't fit.
+        if let Some(value) = take_digits(chars, 8, 3) {
+            out.push(value as u8);
+            continue;
+        }
+        match chars.next().ok_or(ParseError::UnterminatedQuote)? {
+            'a' => out.push(b'\x07'),
+            'b' => out.push(b'\x08'),
+            'e' | 'E' => out.push(b'\x1B'),
+            'f' => out.push(b'\x0C'),
+            'n' => out.push(b'\n'),
+            'r' => out.push(b'\r'),
+            't' => out.push(b'\t'),
+            'v' => out.push(b'\x0B'),
+            'x' => match take_digits(chars, 16, 2) {
+                Some(value) => out.push(value as u8),
+                None => out.extend_from_slice(b"\\x"),
+            },
+            escape @ 'u' | escape @ 'U' => {
+                let max = if escape == 'u' { 4 } else { 8 };
+                match take_digits(chars, 16, max) {
+                    Some(value) => {
+                        let ch = core::char::from_u32(value).ok_or(ParseError::InvalidEscape)?;
+                        push_char(out, ch);
+                    }
+                    None => {
+                        out.push(b'\\');
+                        push_char(out, escape);
+                    }
+                }
+            }
+            // A control code, like \cA for \x01.
+            'c' => match chars.next() {
+                Some(ch) if ch.is_ascii() => out.push(ch as u8 & 0x1F),
+                Some(_) => return Err(ParseError::InvalidEscape),
+                None => return Err(ParseError::UnterminatedQuote),
+            },
+            // \\, \', \" and \? stand for themselves. Unknown escapes keep
+            // their backslash.
+            ch => {
+                if !"\\'\"?".contains(ch) {
+                    out.push(b'\\');
+                }
+                push_char(out, ch);
+            }
+        }
+    }
+}
+
+/// Parse a single word of PowerShell syntax, undoing the quoting of
+/// [`Quoted::windows`][crate::Quoted::windows].
+///
+/// This understands single quotes, double quotes and backtick escapes,
+/// including the unicode quotes that PowerShell accepts. Variables aren't
+/// expanded, so `$foo` is kept as it is.
+///
+/// If the text contains escapes for unpaired surrogates, as produced by
+/// [`Quoted::windows_raw`][crate::Quoted::windows_raw], use
+/// [`unquote_windows_raw`] instead.
+///
+/// # Examples
+/// ```
+/// use os_display::unquote_windows;
+///
+/// assert_eq!(unquote_windows("'it''s'").unwrap(), "it's");
+/// assert_eq!(unquote_windows("\"foo`nbar\"").unwrap(), "foo\nbar");
+/// assert!(unquote_windows("'foo").is_err());
+/// ```
+#[cfg(feature = "windows")]
+pub fn unquote_windows(text: &str) -> Result<String, ParseError> {
+    String::from_utf16(&unquote_windows_raw(text)?).map_err(|_| ParseError::InvalidUtf16)
+}
+
+/// Like [`unquote_windows`], but return UTF-16 code units, which may not
+/// be valid UTF-16.
+#[cfg(feature = "windows")]
+pub fn unquote_windows_raw(text: &str) -> Result<Vec<u16>, ParseError> {
+    use crate::windows::unicode::{is_double_quote, is_single_quote};
+
+    let mut out = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            // Any kind of quote can close the string, and two quotes in a row
+            // stand for the second one.
+            ch if is_single_quote(ch) => loop {
+                match chars.next() {
+                    Some(ch) if is_single_quote(ch) => match chars.peek() {
+                        Some(&next) if is_single_quote(next) => {
+                            push_unit(&mut out, next);
+                            chars.next();
+                        }
+                        _ => break,
+                    },
+                    Some(ch) => push_unit(&mut out, ch),
+                    None => return Err(ParseError::UnterminatedQuote),
+                }
+            },
+            ch if is_double_quote(ch) => loop {
+                match chars.next() {
+                    Some(ch) if is_double_quote(ch) => match chars.peek() {
+                        Some(&next) if is_double_quote(next) => {
+                            push_unit(&mut out, next);
+                            chars.next();
+                        }
+                        _ => break,
+                    },
+                    Some('`') => match chars.next() {
+                        Some(ch) => windows_escape(ch, &mut chars, &mut out)?,
+                        None => return Err(ParseError::UnterminatedQuote),
+                    },
+                    Some(ch) => push_unit(&mut out, ch),
+                    None => return Err(ParseError::UnterminatedQuote),
+                }
+            },
+            '`' => match chars.next() {
+                Some(ch) => windows_escape(ch, &mut chars, &mut out)?,
+                None => return Err(ParseError::UnterminatedEscape),
+            },
+            ch => push_unit(&mut out, ch),
+        }
+    }
+    Ok(out)
+}
+
+/// A backtick escape, starting after the backtick.
+///
+/// https://learn.microsoft.com/en-us/powershell/module/microsoft.powershell.core/about/about_special_characters
+#[cfg(feature = "windows")]
+fn windows_escape(
+    ch: char,
+    chars: &mut Peekable<Chars<'_>>,
+    out: &mut Vec<u16>,
+) -> Result<(), ParseError> {
+    match ch {
+        '0' => out.push(0),
+        'a' => out.push(0x07),
+        'b' => out.push(0x08),
+        'e' => out.push(0x1B),
+        'f' => out.push(0x0C),
+        'n' => out.push(u16::from(b'\n')),
+        'r' => out.push(u16::from(b'\r')),
+        't' => out.push(u16::from(b'\t')),
+        'v' => out.push(0x0B),
+        // `u{1F600} is a code point, but unpaired surrogates are allowed too.
+        'u' if chars.peek() == Some(&'{') => {
+            chars.next();
+            let value = take_digits(chars, 16, 6).ok_or(ParseError::InvalidEscape)?;
+            if chars.next() != Some('}') {
+                return Err(ParseError::InvalidEscape);
+            }
+            match core::char::from_u32(value) {
+                Some(ch) => push_unit(out, ch),
+                None if value <= 0xFFFF => out.push(value as u16),
+                None => return Err(ParseError::InvalidEscape),
+            }
+        }
+        ch => push_unit(out, ch),
+    }
+    Ok(())
+}
+
+/// Check that quoting `input` and parsing it back gives the same text, with
+/// and without forced quoting.
+///
+/// This is the contract between [`Quoted`][crate::Quoted] and the
+/// `unquote_*` functions, as a single entry point for fuzz targets.
+///
+/// In the Unix style `input` can be any bytes. Windows text can't hold
+/// bytes that aren't valid UTF-8, so in the Windows style those are
+/// skipped and this returns `true`.
+///
+/// # Examples
+/// ```
+/// # #[cfg(feature = "unix")] {
+/// use os_display::{verify_roundtrip, QuoteStyle};
+///
+/// assert!(verify_roundtrip(b"it's \xFF\n", QuoteStyle::Unix));
+/// # }
+/// ```
+///
+/// # Optional
+/// This requires the optional `roundtrip` feature.
+#[cfg(feature = "roundtrip")]
+pub fn verify_roundtrip(input: &[u8], style: QuoteStyle) -> bool {
+    use crate::alloc::string::ToString;
+    use crate::Quoted;
+
+    match style {
+        #[cfg(feature = "unix")]
+        QuoteStyle::Unix => [true, false].iter().all(|&force| {
+            let quoted = Quoted::unix_raw(input).force(force).to_string();
+            match unquote_unix_bytes(&quoted) {
+                Ok(out) => out == input,
+                Err(_) => false,
+            }
+        }),
+        #[cfg(feature = "windows")]
+        QuoteStyle::Windows => match core::str::from_utf8(input) {
+            Ok(text) => [true, false].iter().all(|&force| {
+                let quoted = Quoted::windows(text).force(force).to_string();
+                match unquote_windows(&quoted) {
+                    Ok(out) => out == text,
+                    Err(_) => false,
+                }
+            }),
+            Err(_) => true,
+        },
+    }
+}
+
+/// Read up to `max` digits, if there are any.
+fn take_digits(chars: &mut Peekable<Chars<'_>>, radix: u32, max: usize) -> Option<u32> {
+    let mut value = None;
+    for _ in 0..max {
+        match chars.peek().and_then(|ch| ch.to_digit(radix)) {
+            Some(digit) => value = Some(value.unwrap_or(0) * radix + digit),
+            None => break,
+        }
+        chars.next();
+    }
+    value
+}
+
+#[cfg(feature = "unix")]
+fn push_char(out: &mut Vec<u8>, ch: char) {
+    out.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+#[cfg(feature = "windows")]
+fn push_unit(out: &mut Vec<u16>, ch: char) {
+    out.extend_from_slice(ch.encode_utf16(&mut [0; 2]));
+}
@@ -0,0 +1,78 @@
+use core::fmt::{self, Formatter, Write};
+
+use crate::{Options, QuoteChar};
+
+/// Write a Python string literal, in the style of repr().
+///
+/// repr() escapes everything that isn't "printable" according to its own
+/// unicode tables. We don't have those, so we escape the same characters
+/// as the other styles, plus unusual whitespace. That means the output
+/// only differs from repr() for rare characters, like U+200B ZERO WIDTH SPACE.
+///
+/// https://github.com/python/cpython/blob/main/Objects/unicodeobject.c
+/// (see unicode_repr)
+pub(crate) fn write(f: &mut Formatter<'_>, text: &str, opts: &Options) -> fmt::Result {
+    // repr() uses lowercase.
+    let opts = &Options {
+        lowercase_hex: Some(opts.lowercase_hex.unwrap_or(true)),
+        ..*opts
+    };
+
+    // Like repr(), use single quotes unless that requires escapes and double
+    // quotes don't.
+    let quote = match opts.prefer {
+        Some(QuoteChar::Single) => '\'',
+        Some(QuoteChar::Double) => '"',
+        None if text.contains('\'') && !text.contains('"') => '"',
+        None => '\'',
+    };
+
+    opts.write_opening(f, quote, |f| f.write_char(quote))?;
+    for ch in text.chars() {
+        match symbolic_escape(ch, opts) {
+            Some(escape) => opts.write_syntax(f, |f| f.write_str(escape))?,
+            None if opts.escapes(ch) || (ch.is_whitespace() && ch != ' ') => {
+                opts.write_syntax(f, |f| {
+                    // \x, \u and \U take exactly 2, 4 and 8 digits, so unlike in
+                    // the Unix style we don't have to worry about what comes next.
+                    let code = ch as u32;
+                    if code < 0x100 {
+                        f.write_str("\\x")?;
+                        opts.write_hex(f, code, 2)?;
+                    } else if code < 0x10000 {
+                        f.write_str("\\u")?;
+                        opts.write_hex(f, code, 4)?;
+                    } else {
+                        f.write_str("\\U")?;
+                        opts.write_hex(f, code, 8)?;
+                    }
+                    Ok(())
+                })?
+            }
+            None if ch == quote || ch == '\\' => opts.write_syntax(f, |f| {
+                f.write_char('\\')?;
+                f.write_char(ch)
+            })?,
+            None => f.write_char(ch)?,
+        }
+    }
+    opts.write_syntax(f, |f| f.write_char(quote))?;
+    Ok(())
+}
+
+/// The short form of an escape, if there is one and we want to use it.
+///
+/// repr() only uses the first three.
+fn symbolic_escape(ch: char, opts: &Options) -> Option<&'static str> {
+    match (ch, opts.symbolic_escapes) {
+        (_, Some(false)) => None,
+        ('\n', _) => Some("\\n"),
+        ('\t', _) => Some("\\t"),
+        ('\r', _) => Some("\\r"),
+        ('\x07', Some(true)) => Some("\\a"),
+        ('\x08', Some(true)) => Some("\\b"),
+        ('\x0B', Some(true)) => Some("\\v"),
+        ('\x0C', Some(true)) => Some("\\f"),
+        _ => None,
+    }
+}
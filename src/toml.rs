@@ -0,0 +1,67 @@
+use core::fmt::{self, Formatter, Write};
+
+use crate::{Options, QuoteChar};
+
+/// Write a TOML string.
+///
+/// https://toml.io/en/v1.0.0#string
+///
+/// A literal string (between single quotes) has no escapes at all, so it can
+/// only be used if the text has no single quotes and nothing that needs to
+/// be escaped. We use one if it saves us from escaping a " or \, like most
+/// TOML encoders. Otherwise we write a basic string (between double quotes).
+pub(crate) fn write(f: &mut Formatter<'_>, text: &str, opts: &Options) -> fmt::Result {
+    let is_literal_safe = || !text.chars().any(|ch| ch == '\'' || opts.escapes(ch));
+    let literal = match opts.prefer {
+        Some(QuoteChar::Double) => false,
+        Some(QuoteChar::Single) => is_literal_safe(),
+        None => text.contains(|ch| ch == '"' || ch == '\\') && is_literal_safe(),
+    };
+    if literal {
+        opts.write_opening(f, '\'', |f| f.write_char('\''))?;
+        f.write_str(text)?;
+        return opts.write_syntax(f, |f| f.write_char('\''));
+    }
+
+    opts.write_opening(f, '"', |f| f.write_char('"'))?;
+    for ch in text.chars() {
+        match symbolic_escape(ch, opts) {
+            Some(escape) => opts.write_syntax(f, |f| f.write_str(escape))?,
+            None if opts.escapes(ch) => opts.write_syntax(f, |f| {
+                // \u and \U take exactly 4 and 8 digits.
+                let code = ch as u32;
+                if code < 0x10000 {
+                    f.write_str("\\u")?;
+                    opts.write_hex(f, code, 4)
+                } else {
+                    f.write_str("\\U")?;
+                    opts.write_hex(f, code, 8)
+                }
+            })?,
+            None if ch == '"' || ch == '\\' => opts.write_syntax(f, |f| {
+                f.write_char('\\')?;
+                f.write_char(ch)
+            })?,
+            None => f.write_char(ch)?,
+        }
+    }
+    opts.write_syntax(f, |f| f.write_char('"'))?;
+    Ok(())
+}
+
+/// The short form of an escape, if there is one and we want to use it.
+///
+/// These are all the ones TOML has.
+fn symbolic_escape(ch: char, opts: &Options) -> Option<&'static str> {
+    if opts.symbolic_escapes == Some(false) {
+        return None;
+    }
+    match ch {
+        '\n' => Some("\\n"),
+        '\t' => Some("\\t"),
+        '\r' => Some("\\r"),
+        '\x08' => Some("\\b"),
+        '\x0C' => Some("\\f"),
+        _ => None,
+    }
+}
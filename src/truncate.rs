@@ -0,0 +1,363 @@
+use core::fmt::{self, Display, Formatter, Write};
+use core::str;
+
+use crate::width::width;
+use crate::{Kind, Quoted};
+
+/// Write the text, cut short if necessary so that it takes up at most
+/// `max_width` columns.
+///
+/// Cutting the output itself could split an escape or leave a quote open.
+/// So instead we look for the longest part of the original text that still
+/// fits after quoting, and put an ellipsis after that.
+pub(crate) fn write(f: &mut Formatter<'_>, quoted: &Quoted<'_>, max_width: usize) -> fmt::Result {
+    let mut quoted = *quoted;
+    quoted.opts.max_width = None;
+    let east_asian = quoted.opts.east_asian;
+    if width(&measured(quoted), east_asian) <= max_width {
+        return quoted.fmt(f);
+    }
+    if max_width == 0 {
+        return Ok(());
+    }
+
+    // Leave room for the ellipsis.
+    let budget = max_width - 1;
+    write_prefix(f, quoted, |prefix| width(prefix, east_asian) <= budget)
+}
+
+/// Write the text, cut short if necessary so that the output is at most
+/// `max_len` bytes long. This works the same way as `write`.
+pub(crate) fn write_len(f: &mut Formatter<'_>, quoted: &Quoted<'_>, max_len: usize) -> fmt::Result {
+    let mut quoted = *quoted;
+    quoted.opts.max_output_len = None;
+    if len_within(&measured(quoted), max_len) {
+        return quoted.fmt(f);
+    }
+    let budget = match max_len.checked_sub('…'.len_utf8()) {
+        Some(budget) => budget,
+        None => return Ok(()),
+    };
+    write_prefix(f, quoted, |prefix| len_within(prefix, budget))
+}
+
+/// The same text, for formatting it in full just to measure it.
+///
+/// Quoted::rendering() stops formatting at the opening quote, which would
+/// throw off the measurements.
+fn measured(mut quoted: Quoted<'_>) -> Quoted<'_> {
+    quoted.opts.find_delimiter = false;
+    quoted
+}
+
+/// Check whether something takes up at most `max_len` bytes when formatted.
+///
+/// This gives up as soon as the output gets too long, so checking a huge
+/// text costs about as much as checking a short one.
+fn len_within(value: &impl Display, max_len: usize) -> bool {
+    struct Counter {
+        len: usize,
+        max_len: usize,
+    }
+
+    impl Write for Counter {
+        fn write_str(&mut self, text: &str) -> fmt::Result {
+            self.len += text.len();
+            if self.len > self.max_len {
+                Err(fmt::Error)
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    let mut counter = Counter { len: 0, max_len };
+    write!(counter, "{}", value).is_ok()
+}
+
+/// Write the longest part of the text that `fits` after quoting, followed
+/// by an ellipsis.
+fn write_prefix<'a>(
+    f: &mut Formatter<'_>,
+    quoted: Quoted<'a>,
+    fits: impl Fn(&Prefix<'a>) -> bool,
+) -> fmt::Result {
+    let fits = |end: usize| {
+        let end = quoted.source.floor_boundary(end);
+        let quoted = measured(quoted);
+        end > 0 && fits(&Prefix { quoted, end })
+    };
+
+    // Quoting more text doesn't (usually) take up less room, so we can do
+    // a binary search. `lo` always fits (or is zero), `hi` never does.
+    let mut lo = 0;
+    let mut hi = quoted.source.len();
+    while lo + 1 < hi {
+        let mid = lo + (hi - lo) / 2;
+        if fits(mid) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+
+    let end = quoted.source.floor_boundary(lo);
+    if end > 0 {
+        Prefix { quoted, end }.fmt(f)?;
+    }
+    f.write_char('…')
+}
+
+/// The first `end` units of the text, quoted.
+struct Prefix<'a> {
+    quoted: Quoted<'a>,
+    end: usize,
+}
+
+impl<'a> Display for Prefix<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let opts = self.quoted.opts;
+        let end = self.end;
+        let source = match self.quoted.source {
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => {
+                #[cfg(unix)]
+                use std::os::unix::ffi::OsStrExt;
+                #[cfg(target_os = "wasi")]
+                use std::os::wasi::ffi::OsStrExt;
+                #[cfg(windows)]
+                use std::os::windows::ffi::OsStrExt;
+
+                #[cfg(any(unix, target_os = "wasi"))]
+                let source = Kind::NativeRaw(std::ffi::OsStr::from_bytes(&text.as_bytes()[..end]));
+                #[cfg(windows)]
+                let source = match text.to_str() {
+                    Some(text) => Kind::Windows(&text[..end]),
+                    None => {
+                        // There's no way to borrow part of the text, so
+                        // this is our only chance to allocate.
+                        let units: std::vec::Vec<u16> = text.encode_wide().take(end).collect();
+                        return crate::windows::write_raw(f, &units, &opts);
+                    }
+                };
+                #[cfg(not(any(windows, unix, target_os = "wasi")))]
+                let source = match text.to_str() {
+                    Some(text) => Kind::Unix(&text[..end]),
+                    None => Kind::NativeRaw(text),
+                };
+                source
+            }
+            #[cfg(feature = "native")]
+            Kind::NativeChar(ch) => Kind::NativeChar(ch),
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => Kind::Unix(&text[..end]),
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) => Kind::UnixRaw(&bytes[..end]),
+            #[cfg(feature = "unix")]
+            Kind::Printf(bytes) => Kind::Printf(&bytes[..end]),
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => Kind::Windows(&text[..end]),
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(units) => Kind::WindowsRaw(&units[..end]),
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) => Kind::WindowsUtf8(&bytes[..end]),
+            #[cfg(feature = "windows")]
+            Kind::Wtf8(bytes) => Kind::Wtf8(&bytes[..end]),
+            #[cfg(feature = "c")]
+            Kind::C(bytes) => Kind::C(&bytes[..end]),
+            #[cfg(feature = "json")]
+            Kind::Json(bytes) => Kind::Json(&bytes[..end]),
+            #[cfg(feature = "python")]
+            Kind::Python(text) => Kind::Python(&text[..end]),
+            #[cfg(feature = "xonsh")]
+            Kind::Xonsh(text) => Kind::Xonsh(&text[..end]),
+            #[cfg(feature = "toml")]
+            Kind::Toml(text) => Kind::Toml(&text[..end]),
+            #[cfg(feature = "yaml")]
+            Kind::Yaml(text) => Kind::Yaml(&text[..end]),
+            #[cfg(feature = "csv")]
+            Kind::Csv(text) => Kind::Csv(&text[..end]),
+            #[cfg(feature = "makefile")]
+            Kind::Makefile(text) => Kind::Makefile(&text[..end]),
+            #[cfg(feature = "makefile")]
+            Kind::MakefileShell(text) => Kind::MakefileShell(&text[..end]),
+            #[cfg(feature = "verbatim")]
+            Kind::Verbatim(text) => Kind::Verbatim(&text[..end]),
+        };
+        Quoted { source, opts }.fmt(f)
+    }
+}
+
+impl<'a> Kind<'a> {
+    /// The length of the text, in bytes or UTF-16 code units.
+    pub(crate) fn len(&self) -> usize {
+        match *self {
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => {
+                #[cfg(windows)]
+                use std::os::windows::ffi::OsStrExt;
+
+                #[cfg(windows)]
+                return match text.to_str() {
+                    Some(text) => text.len(),
+                    None => text.encode_wide().count(),
+                };
+                #[cfg(not(windows))]
+                return text.len();
+            }
+            #[cfg(feature = "native")]
+            Kind::NativeChar(_) => 1,
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => text.len(),
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) | Kind::Printf(bytes) => bytes.len(),
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => text.len(),
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(units) => units.len(),
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) | Kind::Wtf8(bytes) => bytes.len(),
+            #[cfg(feature = "c")]
+            Kind::C(bytes) => bytes.len(),
+            #[cfg(feature = "json")]
+            Kind::Json(bytes) => bytes.len(),
+            #[cfg(feature = "python")]
+            Kind::Python(text) => text.len(),
+            #[cfg(feature = "xonsh")]
+            Kind::Xonsh(text) => text.len(),
+            #[cfg(feature = "toml")]
+            Kind::Toml(text) => text.len(),
+            #[cfg(feature = "yaml")]
+            Kind::Yaml(text) => text.len(),
+            #[cfg(feature = "csv")]
+            Kind::Csv(text) => text.len(),
+            #[cfg(feature = "makefile")]
+            Kind::Makefile(text) | Kind::MakefileShell(text) => text.len(),
+            #[cfg(feature = "verbatim")]
+            Kind::Verbatim(text) => text.len(),
+        }
+    }
+
+    /// Move `end` back until it doesn't split a character.
+    fn floor_boundary(&self, end: usize) -> usize {
+        #[allow(dead_code)]
+        fn floor_str(text: &str, mut end: usize) -> usize {
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            end
+        }
+
+        #[allow(dead_code)]
+        fn floor_bytes(bytes: &[u8], end: usize) -> usize {
+            // Invalid bytes are escaped one by one, so it's fine to split
+            // those up. But valid UTF-8 has to stay together.
+            for start in (end.saturating_sub(3)..end).rev() {
+                let len = match bytes[start] {
+                    0xC0..=0xDF => 2,
+                    0xE0..=0xEF => 3,
+                    0xF0..=0xF7 => 4,
+                    _ => continue,
+                };
+                let next = start + len;
+                if next > end && next <= bytes.len() && str::from_utf8(&bytes[start..next]).is_ok()
+                {
+                    return start;
+                }
+            }
+            end
+        }
+
+        #[allow(dead_code)]
+        fn floor_units(units: &[u16], end: usize) -> usize {
+            let is_high = |unit: u16| unit & 0xFC00 == 0xD800;
+            let is_low = |unit: u16| unit & 0xFC00 == 0xDC00;
+            if end > 0 && end < units.len() && is_high(units[end - 1]) && is_low(units[end]) {
+                end - 1
+            } else {
+                end
+            }
+        }
+
+        #[allow(dead_code)]
+        fn floor_wtf8(bytes: &[u8], end: usize) -> usize {
+            // Surrogates aren't valid UTF-8, so floor_bytes() doesn't keep
+            // them together.
+            for start in (end.saturating_sub(2)..end).rev() {
+                if start + 3 <= bytes.len() {
+                    if let (0xED, 0xA0..=0xBF, 0x80..=0xBF) =
+                        (bytes[start], bytes[start + 1], bytes[start + 2])
+                    {
+                        return start;
+                    }
+                }
+            }
+            floor_bytes(bytes, end)
+        }
+
+        match *self {
+            #[cfg(feature = "native")]
+            #[cfg(feature = "std")]
+            Kind::NativeRaw(text) => {
+                #[cfg(unix)]
+                use std::os::unix::ffi::OsStrExt;
+                #[cfg(target_os = "wasi")]
+                use std::os::wasi::ffi::OsStrExt;
+                #[cfg(windows)]
+                use std::os::windows::ffi::OsStrExt;
+
+                #[cfg(any(unix, target_os = "wasi"))]
+                return floor_bytes(text.as_bytes(), end);
+                #[cfg(windows)]
+                return match text.to_str() {
+                    Some(text) => floor_str(text, end),
+                    None => {
+                        let units: std::vec::Vec<u16> = text.encode_wide().collect();
+                        floor_units(&units, end)
+                    }
+                };
+                #[cfg(not(any(windows, unix, target_os = "wasi")))]
+                return match text.to_str() {
+                    Some(text) => floor_str(text, end),
+                    // Can't be cut.
+                    None => text.len(),
+                };
+            }
+            // A single character can't be cut.
+            #[cfg(feature = "native")]
+            Kind::NativeChar(_) => core::cmp::min(end, 1),
+            #[cfg(any(feature = "unix", all(feature = "native", not(windows))))]
+            Kind::Unix(text) => floor_str(text, end),
+            #[cfg(feature = "unix")]
+            Kind::UnixRaw(bytes) | Kind::Printf(bytes) => floor_bytes(bytes, end),
+            #[cfg(any(feature = "windows", all(feature = "native", windows)))]
+            Kind::Windows(text) => floor_str(text, end),
+            #[cfg(feature = "windows")]
+            Kind::WindowsRaw(units) => floor_units(units, end),
+            #[cfg(feature = "windows")]
+            Kind::WindowsUtf8(bytes) => floor_bytes(bytes, end),
+            #[cfg(feature = "windows")]
+            Kind::Wtf8(bytes) => floor_wtf8(bytes, end),
+            #[cfg(feature = "c")]
+            Kind::C(bytes) => floor_bytes(bytes, end),
+            #[cfg(feature = "json")]
+            Kind::Json(bytes) => floor_bytes(bytes, end),
+            #[cfg(feature = "python")]
+            Kind::Python(text) => floor_str(text, end),
+            #[cfg(feature = "xonsh")]
+            Kind::Xonsh(text) => floor_str(text, end),
+            #[cfg(feature = "toml")]
+            Kind::Toml(text) => floor_str(text, end),
+            #[cfg(feature = "yaml")]
+            Kind::Yaml(text) => floor_str(text, end),
+            #[cfg(feature = "csv")]
+            Kind::Csv(text) => floor_str(text, end),
+            #[cfg(feature = "makefile")]
+            Kind::Makefile(text) | Kind::MakefileShell(text) => floor_str(text, end),
+            #[cfg(feature = "verbatim")]
+            Kind::Verbatim(text) => floor_str(text, end),
+        }
+    }
+}
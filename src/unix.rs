@@ -1,14 +1,25 @@
 use core::fmt::{self, Formatter, Write};
-use core::str::from_utf8;
 
 use unicode_width::UnicodeWidthChar;
 
+use crate::byte_set::{ByteSet, ASCII_CONTROL};
+use crate::{
+    from_utf8_iter, Classification, ControlStyle, EscapeDialect, InvalidPolicy, NewlineStyle,
+    Options, QuoteChar, QuotePreference,
+};
+
 /// Characters with special meaning outside quotes.
 /// https://pubs.opengroup.org/onlinepubs/9699919799/utilities/V3_chap02.html#tag_18_02
 /// I don't know why % is in there. GNU doesn't quote it either.
 /// zsh and fish have trouble with standalone {}.
 /// ^ was used for piping in old shells and GNU quotes it.
-const SPECIAL_SHELL_CHARS: &[u8] = b"|&;<>()$`\\\"'*?[]=^{} ";
+pub(crate) const SPECIAL_SHELL_CHARS: ByteSet = byte_set![
+    b'|', b'&', b';', b'<', b'>', b'(', b')', b'$', b'`', b'\\', b'"', b'\'', b'=', b'^', b' ',
+];
+
+/// Characters used for globbing and brace expansion.
+/// These can be left out if the text will only be read by humans.
+pub(crate) const GLOB_CHARS: ByteSet = byte_set![b'*', b'?', b'[', b']', b'{', b'}'];
 
 /// Characters with a special meaning at the beginning of a name.
 /// ~ expands a home directory.
@@ -16,113 +27,605 @@ const SPECIAL_SHELL_CHARS: &[u8] = b"|&;<>()$`\\\"'*?[]=^{} ";
 /// ! is a common extension for expanding the shell history.
 const SPECIAL_SHELL_CHARS_START: &[char] = &['~', '#', '!'];
 
+/// Reserved words in bash, which are only recognized if they're not quoted.
+/// The POSIX ones are a subset.
+const KEYWORDS: &[&str] = &[
+    "!", "[[", "]]", "{", "}", "case", "coproc", "do", "done", "elif", "else", "esac", "fi", "for",
+    "function", "if", "in", "select", "then", "time", "until", "while",
+];
+
 /// Characters that are interpreted specially in a double-quoted string.
-const DOUBLE_UNSAFE: &[u8] = &[b'"', b'`', b'$', b'\\'];
-
-pub(crate) fn write(f: &mut Formatter<'_>, text: &str, force_quote: bool) -> fmt::Result {
-    let mut is_single_safe = true;
-    let mut is_double_safe = true;
-    let mut requires_quote = force_quote;
-    let mut is_bidi = false;
-
-    if !requires_quote {
-        if let Some(first) = text.chars().next() {
-            if SPECIAL_SHELL_CHARS_START.contains(&first) {
-                requires_quote = true;
-            }
+pub(crate) const DOUBLE_UNSAFE: ByteSet = byte_set![b'"', b'`', b'$', b'\\'];
 
-            // gnome-terminal (VTE), xterm, urxvt, tmux, screen, and VS Code's
-            // builtin terminal all include zero-width characters at the end of the
-            // selection but not at the start.
-            // terminology and st seem to have trouble displaying them at all.
-            // So if there's a zero-width character at the start we need quotes, but
-            // if it's at the end we don't need to bother.
-            // (This also ensures non-empty zero-width strings end up quoted.)
-            if !requires_quote && first.width().unwrap_or(0) == 0 {
-                // .width() returns Some(1) for unassigned codepoints.
-                // This means we can't pre-emptively quote unknown codepoints in
-                // case they become zero-width in the future.
-                // (None is only returned for certain ASCII characters.)
-                requires_quote = true;
-            }
-        } else {
-            // Empty string
-            requires_quote = true;
+/// Bytes that might need quoting or escaping somewhere in the text, even if
+/// only with certain options.
+const NOT_PLAIN: ByteSet = SPECIAL_SHELL_CHARS
+    .union(GLOB_CHARS)
+    .union(byte_set![b'!'])
+    .union(ASCII_CONTROL);
+
+/// Check for ASCII text without special characters, which is most text.
+/// We can write it out as is without a closer look.
+fn is_plain(text: &str, opts: &Options) -> bool {
+    if opts.quote_keywords && KEYWORDS.contains(&text) {
+        return false;
+    }
+    let not_plain = NOT_PLAIN.union(opts.extra_specials);
+    match text.as_bytes().first() {
+        Some(b'~') | Some(b'#') | Some(b'!') | Some(b'-') | None => false,
+        Some(_) => text.bytes().all(|byte| {
+            byte.is_ascii()
+                && !not_plain.contains(byte)
+                && opts.allowed.map_or(true, |allowed| allowed(byte.into()))
+        }),
+    }
+}
+
+/// What we learn about a text by looking at every character once.
+struct Analysis {
+    requires_quote: bool,
+    is_single_safe: bool,
+    is_double_safe: bool,
+    is_bidi: bool,
+    is_history_unsafe: bool,
+    /// If this is set we stopped looking early, so the rest is incomplete.
+    requires_escape: bool,
+    // The reasons for the above, for Quoted::analyze().
+    quote_leading: bool,
+    quote_whitespace: bool,
+    quote_special: bool,
+    escape_control: bool,
+    escape_invalid: bool,
+}
+
+/// Look at the text to decide how to quote it. Unless `full` is set, this
+/// stops as soon as it's clear that the text has to be escaped.
+fn analyze(text: &[u8], opts: &Options, full: bool) -> Analysis {
+    let mut analysis = Analysis {
+        // Empty strings need quotes.
+        requires_quote: opts.force_quote || chunks(text, opts).next().is_none(),
+        is_single_safe: true,
+        is_double_safe: true,
+        is_bidi: false,
+        is_history_unsafe: false,
+        requires_escape: false,
+        quote_leading: false,
+        quote_whitespace: false,
+        quote_special: false,
+        escape_control: false,
+        escape_invalid: false,
+    };
+
+    if opts.quote_keywords && KEYWORDS.iter().any(|keyword| keyword.as_bytes() == text) {
+        analysis.requires_quote = true;
+        analysis.quote_special = true;
+    }
+
+    // An invalid byte at the start doesn't need any special care.
+    let first = chunks(text, opts)
+        .next()
+        .and_then(Result::ok)
+        .and_then(|chunk| chunk.chars().next());
+    if let Some(first) = first {
+        if SPECIAL_SHELL_CHARS_START.contains(&first) {
+            analysis.requires_quote = true;
+            analysis.quote_leading = true;
+        }
+
+        // Quoting doesn't keep a program from seeing this as an option,
+        // but it does make it stand out. A lone "-" is left alone, as on
+        // Windows.
+        if opts.protect_dashes && first == '-' && text.len() > 1 {
+            analysis.requires_quote = true;
+            analysis.quote_leading = true;
+        }
+
+        // gnome-terminal (VTE), xterm, urxvt, tmux, screen, and VS Code's
+        // builtin terminal all include zero-width characters at the end of the
+        // selection but not at the start.
+        // terminology and st seem to have trouble displaying them at all.
+        // So if there's a zero-width character at the start we need quotes, but
+        // if it's at the end we don't need to bother.
+        // (This also ensures non-empty zero-width strings end up quoted.)
+        // Caret notation makes control codes visible.
+        let is_caret = opts.control_style == ControlStyle::Caret && first.is_ascii_control();
+        if !analysis.requires_quote && first.width().unwrap_or(0) == 0 && !is_caret {
+            // .width() returns Some(1) for unassigned codepoints.
+            // This means we can't pre-emptively quote unknown codepoints in
+            // case they become zero-width in the future.
+            // (None is only returned for certain ASCII characters.)
+            analysis.requires_quote = true;
+            analysis.quote_leading = true;
         }
     }
 
-    for ch in text.chars() {
-        if ch.is_ascii() {
-            let ch = ch as u8;
-            if ch == b'\'' {
-                is_single_safe = false;
-            }
-            if is_double_safe && DOUBLE_UNSAFE.contains(&ch) {
-                is_double_safe = false;
-            }
-            if !requires_quote && SPECIAL_SHELL_CHARS.contains(&ch) {
-                requires_quote = true;
+    for chunk in chunks(text, opts) {
+        match chunk {
+            Ok(chunk) => {
+                for ch in chunk.chars() {
+                    analyze_char(&mut analysis, ch, opts);
+                    if analysis.requires_escape && !full {
+                        break;
+                    }
+                }
             }
-            if ch.is_ascii_control() {
-                return write_escaped(f, text.as_bytes());
+            Err(_) => {
+                if opts.control_style != ControlStyle::Caret {
+                    analysis.requires_escape = true;
+                    analysis.escape_invalid = true;
+                }
             }
-        } else {
-            if !requires_quote && ch.is_whitespace() {
-                // yash splits on unicode whitespace.
-                // fish ignores unicode whitespace at the start of a bare string.
-                // Therefore we quote unicode whitespace.
-                // This has benefits for readability, but some blank characters
-                // are not considered whitespace, like U+2800 BRAILLE PATTERN BLANK.
-                // This check goes stale when new whitespace codepoints are assigned.
-                requires_quote = true;
+        }
+        if analysis.requires_escape && !full {
+            break;
+        }
+    }
+
+    analysis
+}
+
+fn analyze_char(analysis: &mut Analysis, ch: char, opts: &Options) {
+    if ch.is_ascii() {
+        let ch = ch as u8;
+        if ch == b'\'' {
+            analysis.is_single_safe = false;
+        }
+        if analysis.is_double_safe && DOUBLE_UNSAFE.contains(ch) {
+            analysis.is_double_safe = false;
+        }
+        if opts.interactive && ch == b'!' {
+            // bash does history expansion even between double quotes,
+            // and escaping it with a backslash leaves the backslash in.
+            // Only single quotes are safe.
+            analysis.is_double_safe = false;
+            analysis.is_history_unsafe = true;
+            analysis.requires_quote = true;
+            analysis.quote_special = true;
+        }
+        if SPECIAL_SHELL_CHARS.contains(ch)
+            || (opts.glob_safe && GLOB_CHARS.contains(ch))
+            || opts.extra_specials.contains(ch)
+        {
+            analysis.requires_quote = true;
+            if ch == b' ' {
+                analysis.quote_whitespace = true;
+            } else {
+                analysis.quote_special = true;
             }
-            if crate::is_bidi(ch) {
-                is_bidi = true;
+        }
+        if ch.is_ascii_control() && opts.control_style == ControlStyle::Escape {
+            analysis.requires_escape = true;
+            analysis.escape_control = true;
+        } else if !ch.is_ascii_control() && opts.requires_escape(ch.into()) {
+            // Ruled out by Quoted::allowed().
+            analysis.requires_escape = true;
+            analysis.escape_control = true;
+        }
+    } else {
+        if crate::whitespace::is_whitespace(ch) {
+            analysis.requires_quote = true;
+            analysis.quote_whitespace = true;
+        }
+        if crate::is_bidi(ch) {
+            analysis.is_bidi = true;
+        }
+        if opts.requires_escape(ch) {
+            analysis.requires_escape = true;
+            analysis.escape_control = true;
+        }
+    }
+}
+
+/// Find out why text has to be quoted or escaped, for Quoted::analyze().
+pub(crate) fn classify(text: &[u8], opts: &Options) -> Classification {
+    let analysis = analyze(text, opts, true);
+    let bidi = analysis.is_bidi
+        && opts.escapes_bidi(chunks(text, opts).flat_map(|chunk| chunk.unwrap_or("").chars()));
+    if analysis.requires_escape || bidi {
+        Classification::NeedsEscape {
+            control: analysis.escape_control,
+            invalid_unicode: analysis.escape_invalid,
+            bidi,
+        }
+    } else if analysis.requires_quote {
+        Classification::NeedsQuote {
+            whitespace: analysis.quote_whitespace,
+            special: analysis.quote_special,
+            leading: analysis.quote_leading,
+        }
+    } else {
+        Classification::Safe
+    }
+}
+
+pub(crate) fn write(f: &mut Formatter<'_>, text: &str, opts: &Options) -> fmt::Result {
+    if is_plain(text, opts) {
+        return match (opts.force_quote, opts.prefer) {
+            (false, _) => f.write_str(text),
+            (true, _) if opts.always_ansi_c => write_escaped(f, text.as_bytes(), opts),
+            (true, _) if opts.quote_order.is_some() => write_bytes(f, text.as_bytes(), opts),
+            (true, Some(QuoteChar::Double)) => write_simple(f, text.as_bytes(), '"', opts),
+            (true, _) => write_simple(f, text.as_bytes(), '\'', opts),
+        };
+    }
+
+    write_bytes(f, text.as_bytes(), opts)
+}
+
+/// Write text that may not be valid UTF-8.
+pub(crate) fn write_bytes(f: &mut Formatter<'_>, text: &[u8], opts: &Options) -> fmt::Result {
+    if opts.heredoc && text.contains(&b'\n') {
+        if let Some(text) = heredoc_text(text, opts) {
+            return write_heredoc(f, text, opts);
+        }
+    }
+
+    let analysis = analyze(text, opts, false);
+
+    let bidi_chars = || chunks(text, opts).flat_map(|chunk| chunk.unwrap_or("").chars());
+    if analysis.requires_escape || (analysis.is_bidi && opts.escapes_bidi(bidi_chars())) {
+        return write_escaped(f, text, opts);
+    }
+    if analysis.is_bidi && opts.isolates_bidi(bidi_chars()) {
+        return opts.write_isolated(f, |f, opts| write_bytes(f, text, opts));
+    }
+
+    if !analysis.requires_quote {
+        return write_text(f, text, opts);
+    }
+    if opts.always_ansi_c {
+        return write_escaped(f, text, opts);
+    }
+    if opts.minimize && !analysis.is_single_safe && !analysis.is_double_safe {
+        return write_shortest(f, text, &analysis, opts);
+    }
+    if let Some(order) = opts.quote_order {
+        for &style in order.iter() {
+            match style {
+                QuotePreference::Single if analysis.is_single_safe => {
+                    return write_simple(f, text, '\'', opts)
+                }
+                QuotePreference::Double if analysis.is_double_safe => {
+                    return write_simple(f, text, '"', opts)
+                }
+                // See write_shortest().
+                QuotePreference::AnsiC if !analysis.is_history_unsafe => {
+                    return write_escaped(f, text, opts)
+                }
+                _ => (),
             }
-            if crate::requires_escape(ch) {
-                return write_escaped(f, text.as_bytes());
+        }
+        return match order.first() {
+            QuotePreference::Double if !analysis.is_history_unsafe => {
+                write_double_escaped(f, text, opts)
             }
+            _ => write_single_escaped(f, text, opts),
+        };
+    }
+    match opts.prefer {
+        Some(QuoteChar::Single) if analysis.is_single_safe => write_simple(f, text, '\'', opts),
+        Some(QuoteChar::Single) => write_single_escaped(f, text, opts),
+        Some(QuoteChar::Double) if analysis.is_double_safe => write_simple(f, text, '"', opts),
+        Some(QuoteChar::Double) if analysis.is_history_unsafe => {
+            write_single_escaped(f, text, opts)
         }
+        Some(QuoteChar::Double) => write_double_escaped(f, text, opts),
+        None if analysis.is_single_safe => write_simple(f, text, '\'', opts),
+        None if analysis.is_double_safe => write_simple(f, text, '"', opts),
+        None => write_single_escaped(f, text, opts),
     }
+}
 
-    if is_bidi && crate::is_suspicious_bidi(text) {
-        return write_escaped(f, text.as_bytes());
+/// Try every way of quoting text that has both kinds of quotes in it (or
+/// characters that aren't safe in double quotes) and use the shortest.
+fn write_shortest(
+    f: &mut Formatter<'_>,
+    text: &[u8],
+    analysis: &Analysis,
+    opts: &Options,
+) -> fmt::Result {
+    #[derive(Copy, Clone)]
+    enum Style {
+        Single,
+        Double,
+        AnsiC,
     }
 
-    if !requires_quote {
-        f.write_str(text)
-    } else if is_single_safe {
-        write_simple(f, text, '\'')
-    } else if is_double_safe {
-        write_simple(f, text, '\"')
-    } else {
-        write_single_escaped(f, text)
+    let write_style = |f: &mut Formatter<'_>, style: Style, opts: &Options| match style {
+        Style::Single => write_single_escaped(f, text, opts),
+        Style::Double => write_double_escaped(f, text, opts),
+        Style::AnsiC => write_escaped(f, text, opts),
+    };
+
+    // Earlier styles win ties. bash's history expansion doesn't know about
+    // escaped quotes inside $'', so only single quotes are safe for !.
+    let styles: &[Style] = match (opts.prefer, analysis.is_history_unsafe) {
+        (_, true) => &[Style::Single],
+        (Some(QuoteChar::Double), false) => &[Style::Double, Style::Single, Style::AnsiC],
+        (_, false) => &[Style::Single, Style::Double, Style::AnsiC],
+    };
+    let plain = opts.plain();
+    let mut best = (usize::max_value(), Style::Single);
+    for &style in styles {
+        // Shells only understand escapes in the $'' dialect.
+        if let (Style::AnsiC, EscapeDialect::DoubleQuoteBackslash) = (style, opts.escape_dialect) {
+            continue;
+        }
+        let len = crate::width::char_count(|f| write_style(f, style, &plain));
+        if len < best.0 {
+            best = (len, style);
+        }
     }
+    write_style(f, best.1, opts)
 }
 
-fn write_simple(f: &mut Formatter<'_>, text: &str, quote: char) -> fmt::Result {
-    f.write_char(quote)?;
+/// Check whether text can go in a quoted here-document as is, and if so
+/// return it as a string.
+///
+/// Nothing is expanded in there, but control codes and invalid bytes would
+/// still be hidden from the reader. And bash does history expansion inside
+/// here-documents when it reads them interactively.
+fn heredoc_text<'a>(text: &'a [u8], opts: &Options) -> Option<&'a str> {
+    let text = core::str::from_utf8(text).ok()?;
+    // The line breaks would still be there.
+    if opts.newline_style == NewlineStyle::Strip {
+        return None;
+    }
+    if opts.interactive && text.contains('!') {
+        return None;
+    }
+    if opts.escapes_bidi(text.chars())
+        || opts.isolates_bidi(text.chars())
+        || text
+            .chars()
+            .any(|ch| ch != '\n' && (ch.is_control() || opts.requires_escape(ch)))
+    {
+        return None;
+    }
+    Some(text)
+}
+
+/// Write a quoted here-document, like <<'EOF' followed by the lines.
+///
+/// The delimiter can't be a line of the text, so we try EOF, EOF1, EOF2 and
+/// so on. Some number is free, since there can't be more matching lines than
+/// there are lines.
+fn write_heredoc(f: &mut Formatter<'_>, text: &str, opts: &Options) -> fmt::Result {
+    let is_delimiter = |line: &str, n: usize| {
+        if !line.starts_with("EOF") {
+            return false;
+        }
+        let digits = &line[3..];
+        if n == 0 {
+            return digits.is_empty();
+        }
+        // Leading zeros, signs and the like don't count.
+        !digits.starts_with('0')
+            && digits.bytes().all(|byte| byte.is_ascii_digit())
+            && digits.parse() == Ok(n)
+    };
+    let mut n = 0;
+    while text.split('\n').any(|line| is_delimiter(line, n)) {
+        n += 1;
+    }
+    let write_delimiter = |f: &mut Formatter<'_>| {
+        f.write_str("EOF")?;
+        if n > 0 {
+            write!(f, "{}", n)?;
+        }
+        Ok(())
+    };
+
+    opts.write_opening(f, '\'', |f| {
+        f.write_str("<<'")?;
+        write_delimiter(f)?;
+        f.write_str("'\n")
+    })?;
     f.write_str(text)?;
-    f.write_char(quote)?;
+    opts.write_syntax(f, |f| {
+        // The last line always ends with a line break.
+        if !text.ends_with('\n') {
+            f.write_char('\n')?;
+        }
+        write_delimiter(f)
+    })?;
+    Ok(())
+}
+
+fn write_simple(f: &mut Formatter<'_>, text: &[u8], quote: char, opts: &Options) -> fmt::Result {
+    opts.write_opening(f, quote, |f| f.write_char(quote))?;
+    write_text(f, text, opts)?;
+    opts.write_syntax(f, |f| f.write_char(quote))?;
     Ok(())
 }
 
-fn write_single_escaped(f: &mut Formatter<'_>, text: &str) -> fmt::Result {
-    let mut iter = text.split('\'');
+fn write_single_escaped(f: &mut Formatter<'_>, text: &[u8], opts: &Options) -> fmt::Result {
+    let mut iter = text.split(|&byte| byte == b'\'');
     if let Some(chunk) = iter.next() {
         if !chunk.is_empty() {
-            write_simple(f, chunk, '\'')?;
+            write_simple(f, chunk, '\'', opts)?;
         }
     }
     for chunk in iter {
-        f.write_str("\\'")?;
+        opts.write_syntax(f, |f| f.write_str("\\'"))?;
         if !chunk.is_empty() {
-            write_simple(f, chunk, '\'')?;
+            write_simple(f, chunk, '\'', opts)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_double_escaped(f: &mut Formatter<'_>, text: &[u8], opts: &Options) -> fmt::Result {
+    opts.write_opening(f, '"', |f| f.write_char('"'))?;
+    for chunk in chunks(text, opts) {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(byte) => {
+                write_meta(f, byte, opts)?;
+                continue;
+            }
+        };
+        for ch in chunk.chars() {
+            if ch.is_ascii() && DOUBLE_UNSAFE.contains(ch as u8) {
+                opts.write_syntax(f, |f| {
+                    f.write_char('\\')?;
+                    f.write_char(ch)
+                })?;
+            } else if ch.is_ascii_control() {
+                opts.write_syntax(f, |f| write_caret(f, ch as u8))?;
+            } else {
+                f.write_char(ch)?;
+            }
         }
     }
+    opts.write_syntax(f, |f| f.write_char('"'))?;
     Ok(())
 }
 
+/// Write text that doesn't need escaping. If it has control codes or
+/// invalid bytes then we're using caret notation.
+fn write_text(f: &mut Formatter<'_>, text: &[u8], opts: &Options) -> fmt::Result {
+    for chunk in chunks(text, opts) {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(byte) => {
+                write_meta(f, byte, opts)?;
+                continue;
+            }
+        };
+        let mut start = 0;
+        // Control codes are always a single byte, so we can't split a character.
+        for (i, byte) in chunk.bytes().enumerate() {
+            if byte.is_ascii_control() {
+                f.write_str(&chunk[start..i])?;
+                opts.write_syntax(f, |f| write_caret(f, byte))?;
+                start = i + 1;
+            }
+        }
+        f.write_str(&chunk[start..])?;
+    }
+    Ok(())
+}
+
+/// Split possibly invalid UTF-8 into valid parts and invalid bytes.
+///
+/// Depending on [`InvalidPolicy`], invalid bytes may be replaced by U+FFFD
+/// (or another chosen character) or left out instead.
+///
+/// Line breaks are changed according to [`NewlineStyle`]. With
+/// [`ControlStyle::Strip`] and [`ControlStyle::Replace`], control codes
+/// are then left out of the valid parts or replaced by `?`, so nothing else
+/// has to deal with them.
+fn chunks<'a>(text: &'a [u8], opts: &'a Options) -> impl Iterator<Item = Result<&'a str, u8>> + 'a {
+    let policy = opts.invalid;
+    let newline_style = opts.newline_style;
+    let style = opts.control_style;
+    from_utf8_iter(text)
+        .filter_map(move |chunk| match (chunk, policy) {
+            (Err(_), InvalidPolicy::Replace) => Some(Ok(opts.replacement.as_str())),
+            (Err(_), InvalidPolicy::Strip) => None,
+            (chunk, _) => Some(chunk),
+        })
+        .flat_map(move |chunk| LineBreaks {
+            chunk: Some(chunk),
+            style: newline_style,
+        })
+        .flat_map(move |chunk| ControlRuns {
+            chunk: Some(chunk),
+            style,
+        })
+}
+
+/// Split a chunk around line breaks, if the newline style calls for
+/// changing them.
+struct LineBreaks<'a> {
+    chunk: Option<Result<&'a str, u8>>,
+    style: NewlineStyle,
+}
+
+impl<'a> Iterator for LineBreaks<'a> {
+    type Item = Result<&'a str, u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let text = match (self.chunk.take()?, self.style) {
+                (chunk, NewlineStyle::Control) | (chunk @ Err(_), _) => return Some(chunk),
+                (Ok(text), _) => text,
+            };
+            if text.is_empty() {
+                return None;
+            }
+            let end = text
+                .find(|ch| ch == '\r' || ch == '\n')
+                .unwrap_or(text.len());
+            if end > 0 {
+                self.chunk = Some(Ok(&text[end..]));
+                return Some(Ok(&text[..end]));
+            }
+            let crlf = text.starts_with("\r\n");
+            let len = if crlf { 2 } else { 1 };
+            self.chunk = Some(Ok(&text[len..]));
+            match self.style {
+                NewlineStyle::Strip => (),
+                NewlineStyle::JoinCrlf if !crlf => return Some(Ok(&text[..len])),
+                _ => return Some(Ok("\n")),
+            }
+        }
+    }
+}
+
+/// Split a chunk around runs of control codes, if the control style calls
+/// for that.
+struct ControlRuns<'a> {
+    chunk: Option<Result<&'a str, u8>>,
+    style: ControlStyle,
+}
+
+impl<'a> Iterator for ControlRuns<'a> {
+    type Item = Result<&'a str, u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let is_control = |ch: char| ch.is_ascii_control();
+        loop {
+            let text = match (self.chunk.take()?, self.style) {
+                (Ok(text), ControlStyle::Strip) | (Ok(text), ControlStyle::Replace) => text,
+                (chunk, _) => return Some(chunk),
+            };
+            if text.is_empty() {
+                return None;
+            }
+            let end = text.find(is_control).unwrap_or(text.len());
+            if end > 0 {
+                self.chunk = Some(Ok(&text[end..]));
+                return Some(Ok(&text[..end]));
+            }
+            let end = text.find(|ch| !is_control(ch)).unwrap_or(text.len());
+            self.chunk = Some(Ok(&text[end..]));
+            if self.style == ControlStyle::Replace {
+                return Some(Ok("?"));
+            }
+        }
+    }
+}
+
+/// Write a control code in caret notation, e.g. `^I` for a tab.
+fn write_caret(f: &mut Formatter<'_>, byte: u8) -> fmt::Result {
+    f.write_char('^')?;
+    f.write_char((byte ^ 0x40) as char)
+}
+
+/// Write a byte above 0x7F in meta notation, like `cat -v`: `M-` followed
+/// by the byte without its high bit, e.g. `M-A` for 0xC1 and `M-^?` for 0xFF.
+fn write_meta(f: &mut Formatter<'_>, byte: u8, opts: &Options) -> fmt::Result {
+    opts.write_syntax(f, |f| {
+        f.write_str("M-")?;
+        match byte & 0x7F {
+            byte if byte.is_ascii_control() => write_caret(f, byte),
+            byte => f.write_char(byte as char),
+        }
+    })
+}
+
 /// Write using the syntax described here:
 /// https://www.gnu.org/software/bash/manual/html_node/ANSI_002dC-Quoting.html
 ///
@@ -140,46 +643,66 @@ fn write_single_escaped(f: &mut Formatter<'_>, text: &str) -> fmt::Result {
 ///
 /// There's a proposal to add it to POSIX:
 /// https://www.austingroupbugs.net/view.php?id=249
-pub(crate) fn write_escaped(f: &mut Formatter<'_>, text: &[u8]) -> fmt::Result {
-    f.write_str("$'")?;
+///
+/// With EscapeDialect::DoubleQuoteBackslash we use the same escapes between
+/// double quotes instead. Shells don't understand that, but other tools do.
+pub(crate) fn write_escaped(f: &mut Formatter<'_>, text: &[u8], opts: &Options) -> fmt::Result {
+    let (open, close, interrupt) = match opts.escape_dialect {
+        EscapeDialect::AnsiC if opts.escape_only => return write_double_body(f, text, opts),
+        EscapeDialect::AnsiC => ("$'", '\'', "'$'"),
+        EscapeDialect::DoubleQuoteBackslash => ("\"", '"', "\"\""),
+    };
+    if !opts.escape_only {
+        opts.write_opening(f, close, |f| f.write_str(open))?;
+    }
     // ksh variants accept more than two digits for a \x escape code,
     // e.g. \xA691. We have to take care to not accidentally output
     // something like that. If necessary we interrupt the quoting with
-    // `'$'`.
+    // `'$'`. (C has the same problem, and `""` works there.)
     let mut in_escape = false;
-    for chunk in from_utf8_iter(text) {
+    for chunk in chunks(text, opts) {
         match chunk {
             Ok(chunk) => {
                 for ch in chunk.chars() {
                     let was_escape = in_escape;
                     in_escape = false;
+                    if let Some(escape) = symbolic_escape(ch, opts) {
+                        opts.write_syntax(f, |f| f.write_str(escape))?;
+                        continue;
+                    }
                     match ch {
-                        '\n' => f.write_str("\\n")?,
-                        '\t' => f.write_str("\\t")?,
-                        '\r' => f.write_str("\\r")?,
-                        // We could do \a, \b, \f, \v, but those are
-                        // rare enough to be confusing.
-                        // \0 is actually a case of the octal \nnn syntax,
-                        // and null bytes can't appear in arguments anyway,
-                        // so let's stay clear of that.
-                        // Some but not all shells have \e for \x1B.
-                        ch if crate::requires_escape(ch) || crate::is_bidi(ch) => {
+                        ch if opts.escapes(ch) => {
                             // Most shells support \uXXXX escape codes, but busybox sh
                             // doesn't, so we always encode the raw UTF-8. Bit unfortunate,
                             // but GNU does the same.
-                            for &byte in ch.encode_utf8(&mut [0; 4]).as_bytes() {
-                                write!(f, "\\x{:02X}", byte)?;
-                            }
+                            opts.write_syntax(f, |f| {
+                                for &byte in ch.encode_utf8(&mut [0; 4]).as_bytes() {
+                                    f.write_str("\\x")?;
+                                    opts.write_hex(f, byte.into(), 2)?;
+                                }
+                                Ok(())
+                            })?;
                             in_escape = true;
                         }
-                        '\\' | '\'' => {
-                            // '?' and '"' can also be escaped this way
-                            // but AFAICT there's no reason to do so.
-                            f.write_char('\\')?;
-                            f.write_char(ch)?;
+                        '\\' => opts.write_syntax(f, |f| f.write_str("\\\\"))?,
+                        ch if ch == close => {
+                            // Inside $'' '?' and '"' can also be escaped this
+                            // way but AFAICT there's no reason to do so.
+                            opts.write_syntax(f, |f| {
+                                f.write_char('\\')?;
+                                f.write_char(ch)
+                            })?;
+                        }
+                        '$' | '`' if opts.escape_dialect == EscapeDialect::DoubleQuoteBackslash => {
+                            // Not strictly necessary, but it's better if the
+                            // result also works as a regular double-quoted string.
+                            opts.write_syntax(f, |f| {
+                                f.write_char('\\')?;
+                                f.write_char(ch)
+                            })?;
                         }
                         ch if was_escape && ch.is_ascii_hexdigit() => {
-                            f.write_str("'$'")?;
+                            opts.write_syntax(f, |f| f.write_str(interrupt))?;
                             f.write_char(ch)?;
                         }
                         ch => {
@@ -189,87 +712,149 @@ pub(crate) fn write_escaped(f: &mut Formatter<'_>, text: &[u8]) -> fmt::Result {
                 }
             }
             Err(unit) => {
-                write!(f, "\\x{:02X}", unit)?;
+                opts.write_syntax(f, |f| {
+                    f.write_str("\\x")?;
+                    opts.write_hex(f, unit.into(), 2)
+                })?;
                 in_escape = true;
             }
         }
     }
-    f.write_char('\'')?;
+    if !opts.escape_only {
+        opts.write_syntax(f, |f| f.write_char(close))?;
+    }
     Ok(())
 }
 
-fn from_utf8_iter(bytes: &[u8]) -> impl Iterator<Item = Result<&str, u8>> {
-    struct Iter<'a> {
-        bytes: &'a [u8],
+/// Write text for inside double quotes, without the quotes themselves.
+///
+/// Control codes and invalid bytes can't be written between double quotes,
+/// so for those we close the quotes for a moment and use $'...'. The same
+/// goes for ! if history expansion is a concern, with '...'.
+fn write_double_body(f: &mut Formatter<'_>, text: &[u8], opts: &Options) -> fmt::Result {
+    let mut in_ansi_c = false;
+    for chunk in chunks(text, opts) {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(byte) => {
+                opts.write_syntax(f, |f| {
+                    if !in_ansi_c {
+                        f.write_str("\"$'")?;
+                    }
+                    f.write_str("\\x")?;
+                    opts.write_hex(f, byte.into(), 2)
+                })?;
+                in_ansi_c = true;
+                continue;
+            }
+        };
+        for ch in chunk.chars() {
+            if opts.escapes(ch) {
+                opts.write_syntax(f, |f| {
+                    if !in_ansi_c {
+                        f.write_str("\"$'")?;
+                    }
+                    match symbolic_escape(ch, opts) {
+                        Some(escape) => f.write_str(escape),
+                        None => {
+                            for &byte in ch.encode_utf8(&mut [0; 4]).as_bytes() {
+                                f.write_str("\\x")?;
+                                opts.write_hex(f, byte.into(), 2)?;
+                            }
+                            Ok(())
+                        }
+                    }
+                })?;
+                in_ansi_c = true;
+                continue;
+            }
+            // Closing the $'...' also keeps a hex digit from extending the
+            // last \x escape.
+            if in_ansi_c {
+                opts.write_syntax(f, |f| f.write_str("'\""))?;
+                in_ansi_c = false;
+            }
+            if ch == '!' && opts.interactive {
+                opts.write_syntax(f, |f| f.write_str("\"'!'\""))?;
+            } else if ch.is_ascii() && DOUBLE_UNSAFE.contains(ch as u8) {
+                opts.write_syntax(f, |f| {
+                    f.write_char('\\')?;
+                    f.write_char(ch)
+                })?;
+            } else {
+                f.write_char(ch)?;
+            }
+        }
     }
+    if in_ansi_c {
+        opts.write_syntax(f, |f| f.write_str("'\""))?;
+    }
+    Ok(())
+}
 
-    impl<'a> Iterator for Iter<'a> {
-        type Item = Result<&'a str, u8>;
-
-        fn next(&mut self) -> Option<Self::Item> {
-            if self.bytes.is_empty() {
-                return None;
+/// Write text as an argument for `printf '%b'`.
+///
+/// This uses the octal escapes that every POSIX printf understands, so it
+/// works in shells that don't have $'...'. Those are `\0` followed by three
+/// digits, one for each byte. Backslashes have to be escaped too.
+#[cfg(feature = "unix")]
+pub(crate) fn write_printf(f: &mut Formatter<'_>, text: &[u8], opts: &Options) -> fmt::Result {
+    if !opts.force_quote {
+        if let Ok(text) = core::str::from_utf8(text) {
+            if is_plain(text, opts) {
+                return f.write_str(text);
             }
-            match from_utf8(self.bytes) {
-                Ok(text) => {
-                    self.bytes = &[];
-                    Some(Ok(text))
-                }
-                Err(err) if err.valid_up_to() == 0 => {
-                    let res = self.bytes[0];
-                    self.bytes = &self.bytes[1..];
-                    Some(Err(res))
-                }
-                Err(err) => {
-                    let (valid, rest) = self.bytes.split_at(err.valid_up_to());
-                    self.bytes = rest;
-                    Some(Ok(from_utf8(valid).unwrap()))
-                }
+        }
+    }
+    opts.write_opening(f, '\'', |f| f.write_char('\''))?;
+    for chunk in chunks(text, opts) {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(byte) => {
+                opts.write_syntax(f, |f| write_octal(f, byte))?;
+                continue;
+            }
+        };
+        for ch in chunk.chars() {
+            match ch {
+                '\'' => opts.write_syntax(f, |f| f.write_str("'\\''"))?,
+                '\\' => opts.write_syntax(f, |f| f.write_str("\\\\"))?,
+                _ if opts.escapes(ch) => opts.write_syntax(f, |f| {
+                    for &byte in ch.encode_utf8(&mut [0; 4]).as_bytes() {
+                        write_octal(f, byte)?;
+                    }
+                    Ok(())
+                })?,
+                _ => f.write_char(ch)?,
             }
         }
     }
+    opts.write_syntax(f, |f| f.write_char('\''))?;
+    Ok(())
+}
 
-    Iter { bytes }
+#[cfg(feature = "unix")]
+fn write_octal(f: &mut Formatter<'_>, byte: u8) -> fmt::Result {
+    write!(f, "\\0{:03o}", byte)
 }
 
-#[cfg(feature = "std")]
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    use std::vec::Vec;
-
-    #[test]
-    fn test_utf8_iter() {
-        type ByteStr = &'static [u8];
-        type Chunk = Result<&'static str, u8>;
-        const CASES: &[(ByteStr, &[Chunk])] = &[
-            (b"", &[]),
-            (b"hello", &[Ok("hello")]),
-            // Immediately invalid
-            (b"\xFF", &[Err(b'\xFF')]),
-            // Incomplete UTF-8
-            (b"\xC2", &[Err(b'\xC2')]),
-            (b"\xF4\x8F", &[Err(b'\xF4'), Err(b'\x8F')]),
-            (b"\xFF\xFF", &[Err(b'\xFF'), Err(b'\xFF')]),
-            (b"hello\xC2", &[Ok("hello"), Err(b'\xC2')]),
-            (b"\xFFhello", &[Err(b'\xFF'), Ok("hello")]),
-            (b"\xFF\xC2hello", &[Err(b'\xFF'), Err(b'\xC2'), Ok("hello")]),
-            (b"foo\xFFbar", &[Ok("foo"), Err(b'\xFF'), Ok("bar")]),
-            (
-                b"foo\xF4\x8Fbar",
-                &[Ok("foo"), Err(b'\xF4'), Err(b'\x8F'), Ok("bar")],
-            ),
-            (
-                b"foo\xFF\xC2bar",
-                &[Ok("foo"), Err(b'\xFF'), Err(b'\xC2'), Ok("bar")],
-            ),
-        ];
-        for &(case, expected) in CASES {
-            assert_eq!(
-                from_utf8_iter(case).collect::<Vec<_>>().as_slice(),
-                expected
-            );
-        }
+/// The short form of an escape, if there is one and we want to use it.
+fn symbolic_escape(ch: char, opts: &Options) -> Option<&'static str> {
+    match (ch, opts.symbolic_escapes) {
+        (_, Some(false)) => None,
+        ('\n', _) => Some("\\n"),
+        ('\t', _) => Some("\\t"),
+        ('\r', _) => Some("\\r"),
+        // These are rare enough to be confusing, so only use them on request.
+        // \0 is actually a case of the octal \nnn syntax,
+        // and null bytes can't appear in arguments anyway,
+        // so let's stay clear of that.
+        // Some but not all shells have \e for \x1B.
+        ('\x1B', Some(true)) => Some("\\e"),
+        ('\x07', Some(true)) => Some("\\a"),
+        ('\x08', Some(true)) => Some("\\b"),
+        ('\x0B', Some(true)) => Some("\\v"),
+        ('\x0C', Some(true)) => Some("\\f"),
+        _ => None,
     }
 }
@@ -0,0 +1,21 @@
+use core::fmt::{self, Formatter, Write};
+
+use crate::Options;
+
+/// Write text between « and », for debugging.
+///
+/// Nothing is escaped except for », which is doubled. Control codes are
+/// written as they are, so their effect on the terminal shows directly.
+pub(crate) fn write(f: &mut Formatter<'_>, text: &str, opts: &Options) -> fmt::Result {
+    opts.write_opening(f, '«', |f| f.write_char('«'))?;
+    let mut parts = text.split('»');
+    if let Some(part) = parts.next() {
+        f.write_str(part)?;
+    }
+    for part in parts {
+        opts.write_syntax(f, |f| f.write_str("»»"))?;
+        f.write_str(part)?;
+    }
+    opts.write_syntax(f, |f| f.write_char('»'))?;
+    Ok(())
+}
@@ -0,0 +1,23 @@
+/// Check whether a character is whitespace that a shell might split on or
+/// drop.
+///
+/// yash splits on unicode whitespace, fish ignores it at the start of a bare
+/// string, and PowerShell treats everything in the categories {space, line,
+/// paragraph} separator as whitespace. So the Unix and Windows styles both
+/// quote all of these.
+///
+/// This is the same set as char::is_whitespace(), but hardcoded so that both
+/// styles agree even if a future Rust version assigns new whitespace.
+/// Some blank characters are not whitespace, like U+2800 BRAILLE PATTERN
+/// BLANK.
+///
+/// curl -s https://www.unicode.org/Public/UCD/latest/ucd/UnicodeData.txt \
+///     | grep -e Zl -e Zp -e Zs | cut -d ';' -f 1
+pub(crate) fn is_whitespace(ch: char) -> bool {
+    match ch {
+        '\t' | '\n' | '\x0B' | '\x0C' | '\r' | ' ' => true,
+        '\u{0085}' | '\u{00A0}' | '\u{1680}' | '\u{2000}'..='\u{200A}' => true,
+        '\u{2028}' | '\u{2029}' | '\u{202F}' | '\u{205F}' | '\u{3000}' => true,
+        _ => false,
+    }
+}
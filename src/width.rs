@@ -0,0 +1,148 @@
+use core::fmt::{self, Display, Formatter, Write};
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::Quoted;
+
+/// The color codes that `Options::write_syntax` puts around syntax.
+const START_SYNTAX: &str = "\x1B[36m";
+const END_SYNTAX: &str = "\x1B[39m";
+
+/// If `text` starts with one of our own color codes, skip past it.
+///
+/// The codes are always written in one piece, so they can't be split
+/// between two chunks.
+///
+/// Other escape characters are left alone. They can come from styles that
+/// don't escape control codes, like `Quoted::verbatim`, and we can't tell
+/// what they'll do to the terminal.
+fn skip_color(text: &str) -> Option<&str> {
+    if text.starts_with(START_SYNTAX) {
+        Some(&text[START_SYNTAX.len()..])
+    } else if text.starts_with(END_SYNTAX) {
+        Some(&text[END_SYNTAX.len()..])
+    } else {
+        None
+    }
+}
+
+/// The number of columns that something takes up in a terminal.
+///
+/// If `east_asian` is set then ambiguous characters count as wide.
+pub(crate) fn width(value: &impl Display, east_asian: bool) -> usize {
+    struct Counter {
+        width: usize,
+        east_asian: bool,
+    }
+
+    impl Write for Counter {
+        fn write_str(&mut self, mut text: &str) -> fmt::Result {
+            while let Some(ch) = text.chars().next() {
+                if let Some(rest) = skip_color(text) {
+                    text = rest;
+                    continue;
+                }
+                if self.east_asian {
+                    self.width += ch.width_cjk().unwrap_or(0);
+                } else {
+                    self.width += ch.width().unwrap_or(0);
+                }
+                text = &text[ch.len_utf8()..];
+            }
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter {
+        width: 0,
+        east_asian,
+    };
+    // Our Display implementations only fail if the Formatter does.
+    let _ = write!(counter, "{}", value);
+    counter.width
+}
+
+/// The number of characters that `write` produces, not counting color.
+#[cfg(any(
+    feature = "unix",
+    feature = "windows",
+    feature = "makefile",
+    feature = "native"
+))]
+pub(crate) fn char_count(write: impl Fn(&mut Formatter<'_>) -> fmt::Result) -> usize {
+    struct Candidate<F>(F);
+
+    impl<F: Fn(&mut Formatter<'_>) -> fmt::Result> Display for Candidate<F> {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            (self.0)(f)
+        }
+    }
+
+    struct Counter(usize);
+
+    impl Write for Counter {
+        fn write_str(&mut self, text: &str) -> fmt::Result {
+            self.0 += text.chars().count();
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    let _ = write!(counter, "{}", Candidate(write));
+    counter.0
+}
+
+/// The quote that the output starts with, if any.
+///
+/// `value` has to be formatted with `Options::find_delimiter` set, so that
+/// formatting fails right after the opening quote. Only the quote itself is
+/// written then, without a prefix like the `$` of `$'...'`. So if anything
+/// else came before it, the output doesn't start with a quote. The marks
+/// from Quoted::bidi(BidiPolicy::Isolate) don't count.
+pub(crate) fn opening_quote(value: &impl Display) -> Option<char> {
+    struct Scanner {
+        written: usize,
+        last: Option<char>,
+    }
+
+    impl Write for Scanner {
+        fn write_str(&mut self, text: &str) -> fmt::Result {
+            for ch in text.chars().filter(|&ch| ch != '\u{2066}') {
+                self.written += 1;
+                self.last = Some(ch);
+            }
+            Ok(())
+        }
+    }
+
+    let mut scanner = Scanner {
+        written: 0,
+        last: None,
+    };
+    match write!(scanner, "{}", value) {
+        Err(_) if scanner.written == 1 => scanner.last,
+        _ => None,
+    }
+}
+
+/// Quoted text followed by enough spaces to fill `width` columns.
+pub(crate) struct Padded<'a> {
+    pub(crate) quoted: Quoted<'a>,
+    pub(crate) width: usize,
+}
+
+impl<'a> Display for Padded<'a> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // We have to measure the same thing that we write.
+        let quoted = if f.alternate() {
+            self.quoted.force(false)
+        } else {
+            self.quoted
+        };
+        quoted.fmt(f)?;
+        for _ in width(&quoted, quoted.opts.east_asian)..self.width {
+            f.write_char(' ')?;
+        }
+        Ok(())
+    }
+}
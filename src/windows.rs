@@ -1,7 +1,16 @@
+use core::char::{decode_utf16, DecodeUtf16, DecodeUtf16Error};
 use core::fmt::{self, Formatter, Write};
+use core::iter::{Cloned, FilterMap, Map, Peekable};
+use core::slice;
 
 use unicode_width::UnicodeWidthChar;
 
+use crate::byte_set::{ByteSet, ASCII_CONTROL};
+use crate::{
+    Classification, ControlStyle, InvalidPolicy, NewlineStyle, Options, PowerShellContext,
+    QuoteChar, QuotePreference,
+};
+
 // Much of this code is similar to the Unix version.
 // Not all comments are repeated, so read that first.
 
@@ -11,122 +20,819 @@ use unicode_width::UnicodeWidthChar;
 /// out for that if doing your own tests.
 /// Get-ChildItem seems unwilling to quote anything so it doesn't help.
 /// The omission of \ is important because it's used in file paths.
-const SPECIAL_SHELL_CHARS: &[u8] = b"|&;<>()$`\"'*?[]=,{} ";
+/// ( also takes care of the $( subexpression and @( array operators, which
+/// can be evaluated in the middle of a bare string. A lone @ is only special
+/// at the start.
+pub(crate) const SPECIAL_SHELL_CHARS: ByteSet = byte_set![
+    b'|', b'&', b';', b'<', b'>', b'(', b')', b'$', b'`', b'"', b'\'', b'*', b'?', b'[', b']',
+    b'=', b',', b'{', b'}', b' ',
+];
 
 /// A single stand-alone exclamation mark seems to have some special meaning.
 /// Tildes are unclear: In Powershell on Linux, quoting a tilde keeps it from
 /// expanding if passed to an external program, but not if passed to Get-ChildItem.
-const SPECIAL_SHELL_CHARS_START: &[char] = &['~', '#', '@', '!'];
+/// So we leave it alone for PowerShellContext::Cmdlet.
+/// = is special anywhere, but at the start it can also be read as part of an
+/// assignment or of @ splatting, so it's listed here as well.
+const SPECIAL_SHELL_CHARS_START: &[char] = &['~', '#', '@', '!', '='];
 
-const DOUBLE_UNSAFE: &[u8] = &[b'"', b'`', b'$'];
+/// Language keywords, which PowerShell matches in any case.
+const KEYWORDS: &[&str] = &[
+    "begin",
+    "break",
+    "catch",
+    "class",
+    "continue",
+    "data",
+    "define",
+    "do",
+    "dynamicparam",
+    "else",
+    "elseif",
+    "end",
+    "enum",
+    "exit",
+    "filter",
+    "finally",
+    "for",
+    "foreach",
+    "from",
+    "function",
+    "hidden",
+    "if",
+    "in",
+    "param",
+    "process",
+    "return",
+    "static",
+    "switch",
+    "throw",
+    "trap",
+    "try",
+    "until",
+    "using",
+    "var",
+    "while",
+];
 
-pub(crate) fn write(f: &mut Formatter<'_>, text: &str, force_quote: bool) -> fmt::Result {
-    let mut is_single_safe = true;
-    let mut is_double_safe = true;
-    let mut requires_quote = force_quote;
-    let mut is_bidi = false;
+fn is_keyword(text: impl Text) -> bool {
+    KEYWORDS.iter().any(|keyword| {
+        text.chars()
+            .map(|ch| ch.to_ascii_lowercase())
+            .eq(keyword.chars())
+    })
+}
 
-    if !requires_quote {
-        if let Some(first) = text.chars().next() {
-            if SPECIAL_SHELL_CHARS_START.contains(&first) {
-                requires_quote = true;
-            }
+/// Names that refer to a device in any directory, even with an extension.
+/// https://learn.microsoft.com/en-us/windows/win32/fileio/naming-a-file
+const DEVICE_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+fn is_device_name(text: impl Text) -> bool {
+    DEVICE_NAMES.iter().any(|name| {
+        text.chars()
+            .take_while(|&ch| ch != '.')
+            .map(|ch| ch.to_ascii_lowercase())
+            .eq(name.chars())
+    })
+}
+
+pub(crate) const DOUBLE_UNSAFE: ByteSet = byte_set![b'"', b'`', b'$'];
+
+/// Bytes that might need quoting or escaping somewhere in the text.
+const NOT_PLAIN: ByteSet = SPECIAL_SHELL_CHARS
+    .union(DOUBLE_UNSAFE)
+    .union(ASCII_CONTROL);
+
+/// Text that we can go over more than once, so that we can handle valid
+/// UTF-16 without converting it to a String first.
+pub(crate) trait Text: Copy {
+    type Chars: Iterator<Item = char>;
+
+    fn chars(self) -> Self::Chars;
+
+    fn ends_with_backslash(self) -> bool;
+
+    fn write_to(self, f: &mut Formatter<'_>) -> fmt::Result {
+        for ch in self.chars() {
+            f.write_char(ch)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Text for &'a str {
+    type Chars = core::str::Chars<'a>;
+
+    fn chars(self) -> Self::Chars {
+        str::chars(self)
+    }
+
+    fn ends_with_backslash(self) -> bool {
+        self.ends_with('\\')
+    }
+
+    fn write_to(self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(self)
+    }
+}
+
+/// Decoded text with errors replaced by a character, usually U+FFFD.
+#[derive(Debug, Clone)]
+pub(crate) struct Replaced<I> {
+    iter: I,
+    replacement: char,
+}
+
+impl<I: Iterator<Item = Result<char, E>>, E> Iterator for Replaced<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        self.iter.next().map(|res| res.unwrap_or(self.replacement))
+    }
+}
+
+/// UTF-16 that has already been checked to be valid, or where invalid code
+/// units should be replaced by the given character.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Utf16<'a>(pub(crate) &'a [u16], pub(crate) char);
+
+impl<'a> Text for Utf16<'a> {
+    type Chars = Replaced<DecodeUtf16<Cloned<slice::Iter<'a, u16>>>>;
+
+    fn chars(self) -> Self::Chars {
+        Replaced {
+            iter: decode_utf16(self.0.iter().cloned()),
+            replacement: self.1,
+        }
+    }
+
+    fn ends_with_backslash(self) -> bool {
+        self.0.last() == Some(&(b'\\' as u16))
+    }
+}
+
+/// UTF-16 where invalid code units should be left out.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Utf16Stripped<'a>(pub(crate) &'a [u16]);
+
+type DecodeStripped<'a> = FilterMap<
+    DecodeUtf16<Cloned<slice::Iter<'a, u16>>>,
+    fn(Result<char, DecodeUtf16Error>) -> Option<char>,
+>;
+
+impl<'a> Text for Utf16Stripped<'a> {
+    type Chars = DecodeStripped<'a>;
 
-            // PowerShell may parse bare strings as numbers in some contexts.
-            // `echo 1d` just outputs "1d", but `Set-Variable s 1d` assigns
-            // the number 1 to s.
-            if !requires_quote && first.is_ascii_digit() {
-                requires_quote = true;
+    fn chars(self) -> Self::Chars {
+        decode_utf16(self.0.iter().cloned()).filter_map(Result::ok as fn(_) -> _)
+    }
+
+    fn ends_with_backslash(self) -> bool {
+        self.chars().last() == Some('\\')
+    }
+}
+
+/// UTF-8 where invalid sequences should be replaced by the given
+/// character, like String::from_utf8_lossy() does with U+FFFD.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Utf8Lossy<'a>(pub(crate) &'a [u8], pub(crate) char);
+
+#[derive(Debug, Clone)]
+pub(crate) struct DecodeLossy<'a> {
+    valid: core::str::Chars<'a>,
+    rest: &'a [u8],
+    replacement: char,
+}
+
+impl<'a> Iterator for DecodeLossy<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            if let Some(ch) = self.valid.next() {
+                return Some(ch);
+            }
+            if self.rest.is_empty() {
+                return None;
+            }
+            match core::str::from_utf8(self.rest) {
+                Ok(text) => {
+                    self.valid = text.chars();
+                    self.rest = &[];
+                }
+                Err(err) if err.valid_up_to() == 0 => {
+                    // A truncated sequence at the end has no error_len().
+                    let len = err.error_len().unwrap_or(self.rest.len());
+                    self.rest = &self.rest[len..];
+                    return Some(self.replacement);
+                }
+                Err(err) => {
+                    let (valid, rest) = self.rest.split_at(err.valid_up_to());
+                    self.valid = core::str::from_utf8(valid).unwrap().chars();
+                    self.rest = rest;
+                }
             }
+        }
+    }
+}
+
+impl<'a> Text for Utf8Lossy<'a> {
+    type Chars = DecodeLossy<'a>;
+
+    fn chars(self) -> Self::Chars {
+        DecodeLossy {
+            valid: "".chars(),
+            rest: self.0,
+            replacement: self.1,
+        }
+    }
+
+    fn ends_with_backslash(self) -> bool {
+        self.0.last() == Some(&b'\\')
+    }
+}
+
+/// Decode WTF-8, the UTF-8 superset that Rust uses for OsStr on Windows.
+/// It can hold unpaired surrogates, which come out as errors like they do
+/// from decode_utf16(). Bytes that aren't valid WTF-8 either are replaced
+/// by the given character.
+#[derive(Debug, Clone)]
+pub(crate) struct DecodeWtf8<'a> {
+    valid: core::str::Chars<'a>,
+    rest: &'a [u8],
+    replacement: char,
+}
+
+pub(crate) fn decode_wtf8(bytes: &[u8], replacement: char) -> DecodeWtf8<'_> {
+    DecodeWtf8 {
+        valid: "".chars(),
+        rest: bytes,
+        replacement,
+    }
+}
 
-            // Annoyingly, .0d is another example.
-            // And filenames start with . commonly enough that we shouldn't quote
-            // too eagerly.
-            if !requires_quote && first == '.' {
-                if let Some(second) = text.chars().nth(1) {
-                    if second.is_ascii_digit() {
-                        requires_quote = true;
+impl<'a> Iterator for DecodeWtf8<'a> {
+    type Item = Result<char, u16>;
+
+    fn next(&mut self) -> Option<Result<char, u16>> {
+        loop {
+            if let Some(ch) = self.valid.next() {
+                return Some(Ok(ch));
+            }
+            if self.rest.is_empty() {
+                return None;
+            }
+            match core::str::from_utf8(self.rest) {
+                Ok(text) => {
+                    self.valid = text.chars();
+                    self.rest = &[];
+                }
+                Err(err) if err.valid_up_to() == 0 => {
+                    // Surrogates are encoded like any other code point in
+                    // the range, which UTF-8 doesn't allow.
+                    match (self.rest.first(), self.rest.get(1), self.rest.get(2)) {
+                        (Some(&0xED), Some(&b1 @ 0xA0..=0xBF), Some(&b2 @ 0x80..=0xBF)) => {
+                            self.rest = &self.rest[3..];
+                            let unit = 0xD000 | u16::from(b1 & 0x3F) << 6 | u16::from(b2 & 0x3F);
+                            return Some(Err(unit));
+                        }
+                        _ => {
+                            let len = err.error_len().unwrap_or(self.rest.len());
+                            self.rest = &self.rest[len..];
+                            return Some(Ok(self.replacement));
+                        }
                     }
                 }
+                Err(err) => {
+                    let (valid, rest) = self.rest.split_at(err.valid_up_to());
+                    self.valid = core::str::from_utf8(valid).unwrap().chars();
+                    self.rest = rest;
+                }
             }
+        }
+    }
+}
 
-            // (Negative numbers are covered below and seem to be treated differently anyway.)
+/// WTF-8 where unpaired surrogates should be replaced by the given
+/// character.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Wtf8<'a>(pub(crate) &'a [u8], pub(crate) char);
 
-            // Unlike in Unix, quoting an argument may stop it
-            // from being recognized as an option. I like that very much.
-            // But we don't want to quote "-" because that's a common
-            // special argument and PowerShell doesn't mind it.
-            if !requires_quote && unicode::is_dash(first) && text.len() > 1 {
-                requires_quote = true;
-            }
+impl<'a> Text for Wtf8<'a> {
+    type Chars = Replaced<DecodeWtf8<'a>>;
 
-            if !requires_quote && first.width().unwrap_or(0) == 0 {
-                requires_quote = true;
-            }
-        } else {
-            requires_quote = true;
+    fn chars(self) -> Self::Chars {
+        Replaced {
+            iter: decode_wtf8(self.0, self.1),
+            replacement: self.1,
         }
     }
 
-    for ch in text.chars() {
-        if ch.is_ascii() {
-            let ch = ch as u8;
-            if ch == b'\'' {
-                is_single_safe = false;
-            }
-            if is_double_safe && DOUBLE_UNSAFE.contains(&ch) {
-                is_double_safe = false;
+    fn ends_with_backslash(self) -> bool {
+        self.0.last() == Some(&b'\\')
+    }
+}
+
+/// WTF-8 where unpaired surrogates should be left out.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Wtf8Stripped<'a>(pub(crate) &'a [u8], pub(crate) char);
+
+type Wtf8Ok<'a> = FilterMap<DecodeWtf8<'a>, fn(Result<char, u16>) -> Option<char>>;
+
+impl<'a> Text for Wtf8Stripped<'a> {
+    type Chars = Wtf8Ok<'a>;
+
+    fn chars(self) -> Self::Chars {
+        decode_wtf8(self.0, self.1).filter_map(Result::ok as fn(_) -> _)
+    }
+
+    fn ends_with_backslash(self) -> bool {
+        self.chars().last() == Some('\\')
+    }
+}
+
+/// Text with ASCII control codes left out, or each run of them replaced by
+/// a `?`, for ControlStyle::Strip and ControlStyle::Replace.
+#[derive(Debug, Copy, Clone)]
+struct Controls<T> {
+    text: T,
+    replace: bool,
+}
+
+struct ControlChars<I: Iterator> {
+    chars: Peekable<I>,
+    replace: bool,
+}
+
+impl<I: Iterator<Item = char>> Iterator for ControlChars<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        loop {
+            let ch = self.chars.next()?;
+            if !ch.is_ascii_control() {
+                return Some(ch);
             }
-            if !requires_quote && SPECIAL_SHELL_CHARS.contains(&ch) {
-                requires_quote = true;
+            while self.chars.peek().map_or(false, char::is_ascii_control) {
+                self.chars.next();
             }
-            if ch.is_ascii_control() {
-                return write_escaped(f, text.chars().map(Ok));
+            if self.replace {
+                return Some('?');
             }
-        } else {
-            if !requires_quote && unicode::is_whitespace(ch) {
-                requires_quote = true;
+        }
+    }
+}
+
+impl<T: Text> Text for Controls<T> {
+    type Chars = ControlChars<T::Chars>;
+
+    fn chars(self) -> Self::Chars {
+        ControlChars {
+            chars: self.text.chars().peekable(),
+            replace: self.replace,
+        }
+    }
+
+    fn ends_with_backslash(self) -> bool {
+        self.chars().last() == Some('\\')
+    }
+}
+
+/// Text with line breaks changed, for NewlineStyle.
+#[derive(Debug, Copy, Clone)]
+struct LineBreaks<T> {
+    text: T,
+    style: NewlineStyle,
+}
+
+/// Line breaks changed according to a NewlineStyle.
+///
+/// This works on invalid text too, so that write_escaped() can use it.
+struct LineBreakChars<I: Iterator> {
+    chars: Peekable<I>,
+    style: NewlineStyle,
+}
+
+impl<I: Iterator> LineBreakChars<I> {
+    fn new(chars: I, style: NewlineStyle) -> Self {
+        LineBreakChars {
+            chars: chars.peekable(),
+            style,
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<char, u16>>> Iterator for LineBreakChars<I> {
+    type Item = Result<char, u16>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let ch = self.chars.next()?;
+            let crlf = match ch {
+                _ if self.style == NewlineStyle::Control => return Some(ch),
+                Ok('\r') => self.chars.peek() == Some(&Ok('\n')),
+                Ok('\n') => false,
+                _ => return Some(ch),
+            };
+            if crlf {
+                self.chars.next();
             }
-            if (!requires_quote || is_double_safe) && unicode::is_double_quote(ch) {
-                is_double_safe = false;
-                requires_quote = true;
+            match self.style {
+                NewlineStyle::Strip => (),
+                NewlineStyle::JoinCrlf if !crlf => return Some(ch),
+                _ => return Some(Ok('\n')),
             }
-            if (!requires_quote || is_single_safe) && unicode::is_single_quote(ch) {
-                is_single_safe = false;
-                requires_quote = true;
+        }
+    }
+}
+
+type LineBreakOk<I> = FilterMap<
+    LineBreakChars<Map<I, fn(char) -> Result<char, u16>>>,
+    fn(Result<char, u16>) -> Option<char>,
+>;
+
+impl<T: Text> Text for LineBreaks<T> {
+    type Chars = LineBreakOk<T::Chars>;
+
+    fn chars(self) -> Self::Chars {
+        let chars = self.text.chars().map(Ok as fn(_) -> _);
+        LineBreakChars::new(chars, self.style).filter_map(Result::ok as fn(_) -> _)
+    }
+
+    fn ends_with_backslash(self) -> bool {
+        self.chars().last() == Some('\\')
+    }
+}
+
+/// Check for ASCII text without special characters, which is most text.
+/// We can write it out as is without a closer look.
+fn is_plain(text: impl Text) -> bool {
+    let mut chars = text.chars();
+    // This is stricter than analyze() so that we don't have to repeat its
+    // rules for the start.
+    match (chars.next(), chars.next()) {
+        (Some('.'), Some(second)) if second.is_ascii_digit() => return false,
+        (Some(first), _) if first.is_ascii_alphabetic() || "._/\\".contains(first) => {}
+        _ => return false,
+    }
+    text.chars()
+        .all(|ch| ch.is_ascii() && !NOT_PLAIN.contains(ch as u8))
+}
+
+/// What we learn about a text by looking at every character once.
+struct Analysis {
+    requires_quote: bool,
+    is_single_safe: bool,
+    is_double_safe: bool,
+    is_bidi: bool,
+    /// If this is set we stopped looking early, so the rest is incomplete.
+    requires_escape: bool,
+    // The reasons for the above, for Quoted::analyze().
+    quote_leading: bool,
+    quote_whitespace: bool,
+    quote_special: bool,
+}
+
+/// Look at the text to decide how to quote it. Unless `full` is set, this
+/// stops as soon as it's clear that the text has to be escaped.
+fn analyze(text: impl Text, opts: &Options, full: bool) -> Analysis {
+    let mut analysis = Analysis {
+        requires_quote: opts.force_quote,
+        is_single_safe: true,
+        is_double_safe: true,
+        is_bidi: false,
+        requires_escape: false,
+        quote_leading: false,
+        quote_whitespace: false,
+        quote_special: false,
+    };
+
+    if opts.quote_keywords && is_keyword(text) {
+        analysis.requires_quote = true;
+        analysis.quote_special = true;
+    }
+
+    if opts.protect_devices && is_device_name(text) {
+        analysis.requires_quote = true;
+        analysis.quote_special = true;
+    }
+
+    let mut chars = text.chars();
+    if let Some(first) = chars.next() {
+        let second = chars.next();
+
+        let is_cmdlet_tilde = first == '~' && opts.powershell_context == PowerShellContext::Cmdlet;
+        if SPECIAL_SHELL_CHARS_START.contains(&first) && !is_cmdlet_tilde {
+            analysis.requires_quote = true;
+            analysis.quote_leading = true;
+        }
+
+        // PowerShell may parse bare strings as numbers in some contexts.
+        // `echo 1d` just outputs "1d", but `Set-Variable s 1d` assigns
+        // the number 1 to s.
+        if opts.quote_numbers && first.is_ascii_digit() {
+            analysis.requires_quote = true;
+            analysis.quote_leading = true;
+        }
+
+        // Annoyingly, .0d is another example.
+        // And filenames start with . commonly enough that we shouldn't quote
+        // too eagerly.
+        if opts.quote_numbers && first == '.' {
+            if let Some(second) = second {
+                if second.is_ascii_digit() {
+                    analysis.requires_quote = true;
+                    analysis.quote_leading = true;
+                }
             }
-            if crate::is_bidi(ch) {
-                is_bidi = true;
+        }
+
+        // (Negative numbers are covered below and seem to be treated differently anyway.)
+
+        // Unlike in Unix, quoting an argument may stop it
+        // from being recognized as an option. I like that very much.
+        // But we don't want to quote "-" because that's a common
+        // special argument and PowerShell doesn't mind it.
+        // This also covers --%, which makes PowerShell stop parsing
+        // and pass the rest of the line through verbatim.
+        if unicode::is_dash(first) && !(first == '-' && second.is_none()) {
+            analysis.requires_quote = true;
+            analysis.quote_leading = true;
+        }
+
+        if !analysis.requires_quote && first.width().unwrap_or(0) == 0 {
+            analysis.requires_quote = true;
+            analysis.quote_leading = true;
+        }
+    } else {
+        analysis.requires_quote = true;
+    }
+
+    for ch in text.chars() {
+        analyze_char(&mut analysis, ch, opts);
+        if analysis.requires_escape && !full {
+            break;
+        }
+    }
+
+    analysis
+}
+
+fn analyze_char(analysis: &mut Analysis, ch: char, opts: &Options) {
+    if ch.is_ascii() {
+        let ch = ch as u8;
+        if ch == b'\'' {
+            analysis.is_single_safe = false;
+        }
+        if analysis.is_double_safe && DOUBLE_UNSAFE.contains(ch) {
+            analysis.is_double_safe = false;
+        }
+        if SPECIAL_SHELL_CHARS.contains(ch) {
+            analysis.requires_quote = true;
+            if ch == b' ' {
+                analysis.quote_whitespace = true;
+            } else {
+                analysis.quote_special = true;
             }
-            if crate::requires_escape(ch) {
-                return write_escaped(f, text.chars().map(Ok));
+        }
+        if ch.is_ascii_control() || opts.requires_escape(ch.into()) {
+            // The latter can be because of Quoted::allowed().
+            analysis.requires_escape = true;
+        }
+    } else {
+        if unicode::is_whitespace(ch) {
+            analysis.requires_quote = true;
+            analysis.quote_whitespace = true;
+        }
+        if unicode::is_double_quote(ch) {
+            analysis.is_double_safe = false;
+            analysis.requires_quote = true;
+            analysis.quote_special = true;
+        }
+        if unicode::is_single_quote(ch) {
+            analysis.is_single_safe = false;
+            analysis.requires_quote = true;
+            analysis.quote_special = true;
+        }
+        if crate::is_bidi(ch) {
+            analysis.is_bidi = true;
+        }
+        if opts.requires_escape(ch) {
+            analysis.requires_escape = true;
+        }
+    }
+}
+
+pub(crate) fn write(f: &mut Formatter<'_>, text: impl Text, opts: &Options) -> fmt::Result {
+    match opts.newline_style {
+        NewlineStyle::Control => write_controls(f, text, opts),
+        style => write_controls(f, LineBreaks { text, style }, opts),
+    }
+}
+
+fn write_controls(f: &mut Formatter<'_>, text: impl Text, opts: &Options) -> fmt::Result {
+    match opts.control_style {
+        ControlStyle::Strip => write_text(
+            f,
+            Controls {
+                text,
+                replace: false,
+            },
+            opts,
+        ),
+        ControlStyle::Replace => write_text(
+            f,
+            Controls {
+                text,
+                replace: true,
+            },
+            opts,
+        ),
+        _ => write_text(f, text, opts),
+    }
+}
+
+fn write_text(f: &mut Formatter<'_>, text: impl Text, opts: &Options) -> fmt::Result {
+    if is_plain(text)
+        && !(opts.quote_keywords && is_keyword(text))
+        && !(opts.protect_devices && is_device_name(text))
+        && opts
+            .allowed
+            .map_or(true, |allowed| text.chars().all(allowed))
+    {
+        match (opts.force_quote, opts.prefer) {
+            (false, _) => return text.write_to(f),
+            (true, _) if opts.quote_order.is_some() => {}
+            // See below.
+            (true, Some(QuoteChar::Double)) if text.ends_with_backslash() => {}
+            (true, Some(QuoteChar::Double)) => return write_simple(f, text, '"', opts),
+            (true, _) => return write_simple(f, text, '\'', opts),
+        }
+    }
+
+    let mut analysis = analyze(text, opts, false);
+
+    if opts.here_string && text.chars().any(|ch| ch == '\n') {
+        if let Some(quote) = here_string_quote(text, opts) {
+            return write_here_string(f, text, quote, opts);
+        }
+    }
+
+    if analysis.requires_escape || (analysis.is_bidi && opts.escapes_bidi(text.chars())) {
+        return write_escaped(f, text.chars().map(Ok), opts);
+    }
+    if analysis.is_bidi && opts.isolates_bidi(text.chars()) {
+        return opts.write_isolated(f, |f, opts| write_text(f, text, opts));
+    }
+
+    if !analysis.requires_quote {
+        return text.write_to(f);
+    }
+
+    // PowerShell doesn't care about backslashes, but when it passes a
+    // double-quoted argument on to an external program it may do so as "x\",
+    // and many programs read \" as an escaped quote. So we avoid putting a
+    // backslash right before a closing double quote.
+    // We can't help it if there are also control codes, but those are rare.
+    let ends_in_backslash = text.ends_with_backslash();
+    if ends_in_backslash {
+        analysis.is_double_safe = false;
+    }
+
+    if opts.minimize && !analysis.is_single_safe && !analysis.is_double_safe && !ends_in_backslash {
+        // Single quotes are escaped by doubling them, double quotes (and a
+        // few other characters) with a backtick. Whichever is more common
+        // decides.
+        let plain = opts.plain();
+        let single = crate::width::char_count(|f| write_single_escaped(f, text, &plain));
+        let double = crate::width::char_count(|f| write_escaped(f, text.chars().map(Ok), &plain));
+        let prefer_double = opts.prefer == Some(QuoteChar::Double);
+        return if double < single || (double == single && prefer_double) {
+            write_escaped(f, text.chars().map(Ok), opts)
+        } else {
+            write_single_escaped(f, text, opts)
+        };
+    }
+
+    if let Some(order) = opts.quote_order {
+        for &style in order.iter() {
+            match style {
+                QuotePreference::Single if analysis.is_single_safe => {
+                    return write_simple(f, text, '\'', opts)
+                }
+                QuotePreference::Double if analysis.is_double_safe => {
+                    return write_simple(f, text, '"', opts)
+                }
+                QuotePreference::AnsiC if !ends_in_backslash => {
+                    return write_escaped(f, text.chars().map(Ok), opts)
+                }
+                _ => (),
             }
         }
+        return match order.first() {
+            QuotePreference::Double if !ends_in_backslash => {
+                write_escaped(f, text.chars().map(Ok), opts)
+            }
+            _ => write_single_escaped(f, text, opts),
+        };
     }
 
-    if is_bidi && crate::is_suspicious_bidi(text) {
-        return write_escaped(f, text.chars().map(Ok));
+    match opts.prefer {
+        Some(QuoteChar::Single) if analysis.is_single_safe => write_simple(f, text, '\'', opts),
+        Some(QuoteChar::Single) => write_single_escaped(f, text, opts),
+        Some(QuoteChar::Double) if analysis.is_double_safe => write_simple(f, text, '"', opts),
+        Some(QuoteChar::Double) if ends_in_backslash => write_single_escaped(f, text, opts),
+        // This is really meant for control codes but it does the job.
+        Some(QuoteChar::Double) => write_escaped(f, text.chars().map(Ok), opts),
+        None if analysis.is_single_safe => write_simple(f, text, '\'', opts),
+        None if analysis.is_double_safe => write_simple(f, text, '"', opts),
+        None => write_single_escaped(f, text, opts),
     }
+}
 
-    if !requires_quote {
-        f.write_str(text)
-    } else if is_single_safe {
-        write_simple(f, text, '\'')
-    } else if is_double_safe {
-        write_simple(f, text, '\"')
-    } else {
-        write_single_escaped(f, text)
+fn write_simple(
+    f: &mut Formatter<'_>,
+    text: impl Text,
+    quote: char,
+    opts: &Options,
+) -> fmt::Result {
+    opts.write_opening(f, quote, |f| f.write_char(quote))?;
+    text.write_to(f)?;
+    opts.write_syntax(f, |f| f.write_char(quote))?;
+    Ok(())
+}
+
+/// Pick the kind of here-string to use for text, if it fits in one at all.
+///
+/// A here-string ends at a line that starts with '@ or "@. PowerShell may
+/// allow whitespace before it, so to be safe we never put a quote and an @
+/// after whitespace. Line breaks are taken literally, but other control codes
+/// would be lost on the reader, and @"..."@ still expands $ and `.
+fn here_string_quote(text: impl Text, opts: &Options) -> Option<char> {
+    if opts.escapes_bidi(text.chars()) || opts.isolates_bidi(text.chars()) {
+        return None;
+    }
+    let mut single_safe = true;
+    let mut double_safe = true;
+    let mut after_blank = true;
+    let mut quote = None;
+    for ch in text.chars() {
+        if ch != '\n' && (ch.is_control() || opts.requires_escape(ch)) {
+            return None;
+        }
+        match quote {
+            Some('\'') if ch == '@' => single_safe = false,
+            Some('"') if ch == '@' => double_safe = false,
+            _ => (),
+        }
+        if ch == '`' || ch == '$' {
+            double_safe = false;
+        }
+        quote = if !after_blank {
+            None
+        } else if unicode::is_single_quote(ch) {
+            Some('\'')
+        } else if unicode::is_double_quote(ch) {
+            Some('"')
+        } else {
+            None
+        };
+        after_blank = ch == '\n' || unicode::is_whitespace(ch);
+    }
+    match opts.prefer {
+        Some(QuoteChar::Double) if double_safe => Some('"'),
+        _ if single_safe => Some('\''),
+        _ if double_safe => Some('"'),
+        _ => None,
     }
 }
 
-fn write_simple(f: &mut Formatter<'_>, text: &str, quote: char) -> fmt::Result {
-    f.write_char(quote)?;
-    f.write_str(text)?;
-    f.write_char(quote)?;
+fn write_here_string(
+    f: &mut Formatter<'_>,
+    text: impl Text,
+    quote: char,
+    opts: &Options,
+) -> fmt::Result {
+    // The opening quote has to end its line and the closing quote has to
+    // start one. Those line breaks aren't part of the string.
+    opts.write_opening(f, quote, |f| {
+        f.write_char('@')?;
+        f.write_char(quote)?;
+        f.write_char('\n')
+    })?;
+    text.write_to(f)?;
+    opts.write_syntax(f, |f| {
+        f.write_char('\n')?;
+        f.write_char(quote)?;
+        f.write_char('@')
+    })?;
     Ok(())
 }
 
-fn write_single_escaped(f: &mut Formatter<'_>, text: &str) -> fmt::Result {
+fn write_single_escaped(f: &mut Formatter<'_>, text: impl Text, opts: &Options) -> fmt::Result {
     // Quotes in PowerShell are escaped by doubling them.
     // The second quote is used, so '‘ becomes ‘.
     // Therefore we insert a ' before every quote we find.
@@ -137,100 +843,234 @@ fn write_single_escaped(f: &mut Formatter<'_>, text: &str) -> fmt::Result {
     // double quotes or in a bare string. We can't unquote, use a bare string,
     // then requote, as we would in Unix: PowerShell sees that as multiple
     // arguments.
-    f.write_char('\'')?;
-    let mut pos = 0;
-    for (index, _) in text.match_indices(unicode::is_single_quote) {
-        f.write_str(&text[pos..index])?;
-        f.write_char('\'')?;
-        pos = index;
-    }
-    f.write_str(&text[pos..])?;
-    f.write_char('\'')?;
+    opts.write_opening(f, '\'', |f| f.write_char('\''))?;
+    for ch in text.chars() {
+        if unicode::is_single_quote(ch) {
+            opts.write_syntax(f, |f| {
+                f.write_char('\'')?;
+                f.write_char(ch)
+            })?;
+        } else {
+            f.write_char(ch)?;
+        }
+    }
+    opts.write_syntax(f, |f| f.write_char('\''))?;
     Ok(())
 }
 
+/// Find out why text has to be quoted or escaped, for Quoted::analyze().
+///
+/// `invalid` says whether the text had invalid code units that have to be
+/// escaped.
+pub(crate) fn classify(text: impl Text, opts: &Options, invalid: bool) -> Classification {
+    match opts.newline_style {
+        NewlineStyle::Control => classify_controls(text, opts, invalid),
+        style => classify_controls(LineBreaks { text, style }, opts, invalid),
+    }
+}
+
+fn classify_controls(text: impl Text, opts: &Options, invalid: bool) -> Classification {
+    match opts.control_style {
+        ControlStyle::Strip => classify_text(
+            Controls {
+                text,
+                replace: false,
+            },
+            opts,
+            invalid,
+        ),
+        ControlStyle::Replace => classify_text(
+            Controls {
+                text,
+                replace: true,
+            },
+            opts,
+            invalid,
+        ),
+        _ => classify_text(text, opts, invalid),
+    }
+}
+
+fn classify_text(text: impl Text, opts: &Options, invalid: bool) -> Classification {
+    let analysis = analyze(text, opts, true);
+    let bidi = analysis.is_bidi && opts.escapes_bidi(text.chars());
+    if analysis.requires_escape || bidi || invalid {
+        Classification::NeedsEscape {
+            control: analysis.requires_escape,
+            invalid_unicode: invalid,
+            bidi,
+        }
+    } else if analysis.requires_quote {
+        Classification::NeedsQuote {
+            whitespace: analysis.quote_whitespace,
+            special: analysis.quote_special,
+            leading: analysis.quote_leading,
+        }
+    } else {
+        Classification::Safe
+    }
+}
+
+/// Like `classify`, for text that may not be valid UTF-16.
+pub(crate) fn classify_raw(units: &[u16], opts: &Options) -> Classification {
+    let replacement = opts.replacement.as_char();
+    let is_valid = decode_utf16(units.iter().cloned()).all(|res| res.is_ok());
+    match opts.invalid {
+        InvalidPolicy::Strip => classify(Utf16Stripped(units), opts, false),
+        InvalidPolicy::Escape => classify(Utf16(units, replacement), opts, !is_valid),
+        InvalidPolicy::Replace => classify(Utf16(units, replacement), opts, false),
+    }
+}
+
+/// Write UTF-16 that may not be valid.
+pub(crate) fn write_raw(f: &mut Formatter<'_>, units: &[u16], opts: &Options) -> fmt::Result {
+    // Quoted::strip_bom() only reaches this far for invalid native text.
+    let units = if opts.strip_bom && units.first() == Some(&0xFEFF) {
+        &units[1..]
+    } else {
+        units
+    };
+    let replacement = opts.replacement.as_char();
+    if decode_utf16(units.iter().cloned()).all(|res| res.is_ok()) {
+        return write(f, Utf16(units, replacement), opts);
+    }
+    match opts.invalid {
+        InvalidPolicy::Escape => write_escaped(f, crate::decode_utf16(units.iter().cloned()), opts),
+        InvalidPolicy::Replace => write(f, Utf16(units, replacement), opts),
+        InvalidPolicy::Strip => write(f, Utf16Stripped(units), opts),
+    }
+}
+
+/// Like `classify`, for WTF-8 that may hold unpaired surrogates.
+pub(crate) fn classify_wtf8(bytes: &[u8], opts: &Options) -> Classification {
+    let replacement = opts.replacement.as_char();
+    let is_valid = decode_wtf8(bytes, replacement).all(|res| res.is_ok());
+    match opts.invalid {
+        InvalidPolicy::Strip => classify(Wtf8Stripped(bytes, replacement), opts, false),
+        InvalidPolicy::Escape => classify(Wtf8(bytes, replacement), opts, !is_valid),
+        InvalidPolicy::Replace => classify(Wtf8(bytes, replacement), opts, false),
+    }
+}
+
+/// Like `write_raw`, for WTF-8 that may hold unpaired surrogates.
+pub(crate) fn write_wtf8(f: &mut Formatter<'_>, bytes: &[u8], opts: &Options) -> fmt::Result {
+    if let Ok(text) = core::str::from_utf8(bytes) {
+        return write(f, text, opts);
+    }
+    let replacement = opts.replacement.as_char();
+    if decode_wtf8(bytes, replacement).all(|res| res.is_ok()) {
+        return write(f, Wtf8(bytes, replacement), opts);
+    }
+    match opts.invalid {
+        InvalidPolicy::Escape => write_escaped(f, decode_wtf8(bytes, replacement), opts),
+        InvalidPolicy::Replace => write(f, Wtf8(bytes, replacement), opts),
+        InvalidPolicy::Strip => write(f, Wtf8Stripped(bytes, replacement), opts),
+    }
+}
+
 pub(crate) fn write_escaped(
     f: &mut Formatter<'_>,
     text: impl Iterator<Item = Result<char, u16>>,
+    opts: &Options,
 ) -> fmt::Result {
     // ` takes the role of \ since \ is already used as the path separator.
     // Things are UTF-16-oriented, so we escape bad code units as "`u{1234}".
 
-    f.write_char('"')?;
+    if !opts.escape_only {
+        opts.write_opening(f, '"', |f| f.write_char('"'))?;
+    }
+    // write() already took care of these, but invalid text and
+    // Quoted::escape_only() come straight here.
+    let text = LineBreakChars::new(text, opts.newline_style);
+    let drop_controls = match opts.control_style {
+        ControlStyle::Strip | ControlStyle::Replace => true,
+        _ => false,
+    };
+    let mut after_control = false;
     for ch in text {
         match ch {
-            Ok(ch) => match ch {
-                '\0' => f.write_str("`0")?,
-                '\r' => f.write_str("`r")?,
-                '\n' => f.write_str("`n")?,
-                '\t' => f.write_str("`t")?,
-                // Code unit escapes are only supported in PowerShell Core,
-                // so we're more willing to use weird escapes here than on Unix.
-                // There's also `e, for \x1B, but that one's Core-exclusive.
-                '\x07' => f.write_str("`a")?,
-                '\x08' => f.write_str("`b")?,
-                '\x0b' => f.write_str("`v")?,
-                '\x0c' => f.write_str("`f")?,
-                ch if crate::requires_escape(ch) || crate::is_bidi(ch) => {
-                    write!(f, "`u{{{:02X}}}", ch as u32)?
+            Ok(ch) if drop_controls && ch.is_ascii_control() => {
+                if !after_control && opts.control_style == ControlStyle::Replace {
+                    f.write_char('?')?;
                 }
-                '`' => f.write_str("``")?,
-                '$' => f.write_str("`$")?,
-                ch if unicode::is_double_quote(ch) => {
+                after_control = true;
+                continue;
+            }
+            _ => after_control = false,
+        }
+        match ch {
+            Ok(ch) => match symbolic_escape(ch, opts) {
+                Some(escape) => opts.write_syntax(f, |f| f.write_str(escape))?,
+                None if opts.escapes(ch) => opts.write_syntax(f, |f| {
+                    f.write_str("`u{")?;
+                    opts.write_hex(f, ch as u32, 2)?;
+                    f.write_char('}')
+                })?,
+                None if ch == '`' => opts.write_syntax(f, |f| f.write_str("``"))?,
+                None if ch == '$' => opts.write_syntax(f, |f| f.write_str("`$"))?,
+                None if unicode::is_double_quote(ch) => {
                     // We can quote this with either ` or ".
                     // But if we use " and the PowerShell version doesn't actually
                     // see this as a double quote then we're in trouble.
                     // ` is safer.
-                    f.write_char('`')?;
-                    f.write_char(ch)?;
+                    opts.write_syntax(f, |f| {
+                        f.write_char('`')?;
+                        f.write_char(ch)
+                    })?;
                 }
-                ch => f.write_char(ch)?,
+                None => f.write_char(ch)?,
             },
-            Err(unit) => write!(f, "`u{{{:04X}}}", unit)?,
+            Err(unit) => opts.write_syntax(f, |f| {
+                f.write_str("`u{")?;
+                opts.write_hex(f, unit.into(), 4)?;
+                f.write_char('}')
+            })?,
         }
     }
-    f.write_char('"')?;
+    if !opts.escape_only {
+        opts.write_syntax(f, |f| f.write_char('"'))?;
+    }
     Ok(())
 }
 
+/// The short form of an escape, if there is one and we want to use it.
+fn symbolic_escape(ch: char, opts: &Options) -> Option<&'static str> {
+    if opts.symbolic_escapes == Some(false) {
+        return None;
+    }
+    match ch {
+        '\0' => Some("`0"),
+        '\r' => Some("`r"),
+        '\n' => Some("`n"),
+        '\t' => Some("`t"),
+        // Code unit escapes are only supported in PowerShell Core,
+        // so we're more willing to use weird escapes here than on Unix.
+        // There's also `e, for \x1B, but that one's Core-exclusive.
+        '\x07' => Some("`a"),
+        '\x08' => Some("`b"),
+        '\x0b' => Some("`v"),
+        '\x0c' => Some("`f"),
+        _ => None,
+    }
+}
+
 /// PowerShell makes liberal use of Unicode:
 /// <https://github.com/PowerShell/PowerShell/blob/master/src/System.Management.Automation/engine/parser/CharTraits.cs>
 /// This may have to be updated in the future.
-mod unicode {
+pub(crate) mod unicode {
     /// PowerShell considers these to be whitespace:
     /// 1. ASCII: Space, Horizontal tab, Form feed, Carriage return
     /// 2. Unicode: No-break space, Next line
     /// 3. Everything that satisfies System.Char.IsSeparator, i.e. everything
     ///    in the categories {space, line, paragraph} separator
     ///
-    /// This overlaps with but is not identical to char::is_whitespace().
-    ///
-    /// There is some redundancy throughout this implementation. We already
-    /// know that ch is not ASCII, and \u{A0} is repeated. But that's all
-    /// optimized away in the end so no need to worry about it.
+    /// Outside ASCII that's the same set the Unix style uses, so that both
+    /// styles quote the same text.
     pub(crate) fn is_whitespace(ch: char) -> bool {
         match ch {
             ' ' | '\t' | '\x0B' | '\x0C' => true,
-            '\u{00A0}' | '\u{0085}' => true,
-            c => is_separator(c),
-        }
-    }
-
-    /// I don't want to add a dependency just for this, and
-    /// as of writing, the unicode_categories crate is out of
-    /// date anyway. So hardcode the category check.
-    ///
-    /// curl -s https://www.unicode.org/Public/UCD/latest/ucd/UnicodeData.txt \
-    ///     | grep -e Zl -e Zp -e Zs | cut -d ';' -f 1
-    ///
-    /// Unicode 15.0 will release on September 11, 2022.
-    fn is_separator(ch: char) -> bool {
-        match ch {
-            '\u{0020}' | '\u{00A0}' | '\u{1680}' | '\u{2000}' | '\u{2001}' | '\u{2002}'
-            | '\u{2003}' | '\u{2004}' | '\u{2005}' | '\u{2006}' | '\u{2007}' | '\u{2008}'
-            | '\u{2009}' | '\u{200A}' | '\u{2028}' | '\u{2029}' | '\u{202F}' | '\u{205F}'
-            | '\u{3000}' => true,
-            _ => false,
+            c if c.is_ascii() => false,
+            c => crate::whitespace::is_whitespace(c),
         }
     }
 
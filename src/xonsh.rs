@@ -0,0 +1,32 @@
+use core::fmt::{self, Formatter};
+
+use crate::{python, Options, QuoteChar};
+
+/// Write an argument for a command in xonsh's subprocess mode.
+///
+/// https://xon.sh/tutorial.html#string-literals-in-subprocess-mode
+///
+/// Bare words don't stay as they are: `$NAME` and `${...}` are expanded,
+/// `@(...)` and `$(...)` run code, `{a,b}` is a brace expansion and so on.
+/// So we only leave text bare if it's made of letters, digits and a few
+/// harmless punctuation characters. Anything else becomes a Python string
+/// literal, which xonsh passes on as a single argument.
+pub(crate) fn write(f: &mut Formatter<'_>, text: &str, opts: &Options) -> fmt::Result {
+    if !opts.force_quote && is_bare_safe(text, opts) {
+        return f.write_str(text);
+    }
+    // Environment variables are expanded inside double quotes, like in
+    // bash, so a $ has to go between single quotes.
+    let prefer = match opts.prefer {
+        None if text.contains('$') => Some(QuoteChar::Single),
+        prefer => prefer,
+    };
+    python::write(f, text, &Options { prefer, ..*opts })
+}
+
+fn is_bare_safe(text: &str, opts: &Options) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|ch| (ch.is_alphanumeric() || "-_./:,+=%".contains(ch)) && !opts.escapes(ch))
+}
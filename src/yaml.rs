@@ -0,0 +1,133 @@
+use core::fmt::{self, Formatter, Write};
+
+use crate::{Options, QuoteChar};
+
+/// Write a YAML scalar.
+///
+/// https://yaml.org/spec/1.2.2/#73-flow-scalar-styles
+///
+/// Plain scalars (without quotes) are only used if they can't be mistaken
+/// for anything else, including other types in YAML 1.1. Single-quoted
+/// scalars can't hold escapes, and line breaks inside them get folded into
+/// spaces, so anything that needs escaping goes between double quotes.
+pub(crate) fn write(f: &mut Formatter<'_>, text: &str, opts: &Options) -> fmt::Result {
+    let needs_escape = text.chars().any(|ch| opts.escapes(ch));
+    if !opts.force_quote && !needs_escape && is_plain_safe(text) {
+        return f.write_str(text);
+    }
+
+    let quote = match opts.prefer {
+        _ if needs_escape => '"',
+        Some(QuoteChar::Single) => '\'',
+        Some(QuoteChar::Double) => '"',
+        None if text.contains('\'') && !text.contains('"') => '"',
+        None => '\'',
+    };
+
+    opts.write_opening(f, quote, |f| f.write_char(quote))?;
+    for ch in text.chars() {
+        match symbolic_escape(ch, opts) {
+            Some(escape) => opts.write_syntax(f, |f| f.write_str(escape))?,
+            None if opts.escapes(ch) => opts.write_syntax(f, |f| {
+                // \x, \u and \U take exactly 2, 4 and 8 digits.
+                let code = ch as u32;
+                if code < 0x100 {
+                    f.write_str("\\x")?;
+                    opts.write_hex(f, code, 2)
+                } else if code < 0x10000 {
+                    f.write_str("\\u")?;
+                    opts.write_hex(f, code, 4)
+                } else {
+                    f.write_str("\\U")?;
+                    opts.write_hex(f, code, 8)
+                }
+            })?,
+            None if ch == '\'' && quote == '\'' => opts.write_syntax(f, |f| f.write_str("''"))?,
+            None if (ch == '"' || ch == '\\') && quote == '"' => opts.write_syntax(f, |f| {
+                f.write_char('\\')?;
+                f.write_char(ch)
+            })?,
+            None => f.write_char(ch)?,
+        }
+    }
+    opts.write_syntax(f, |f| f.write_char(quote))?;
+    Ok(())
+}
+
+/// Check whether text can be written without quotes and still be read back
+/// as the same string.
+///
+/// This is stricter than the spec, since we don't know whether the text
+/// ends up in a block or inside [] or {}.
+fn is_plain_safe(text: &str) -> bool {
+    let first = match text.chars().next() {
+        Some(first) => first,
+        None => return false,
+    };
+    // Indicators. -, ? and : are allowed if they're not followed by a
+    // space, but a value like "-foo" looks confusing anyway.
+    if "-?:,[]{}#&*!|>'\"%@`".contains(first) {
+        return false;
+    }
+    // Whitespace at the edges would get lost.
+    if first.is_whitespace() || text.ends_with(char::is_whitespace) {
+        return false;
+    }
+    // ": " starts a value and " #" starts a comment.
+    if text.contains(": ") || text.ends_with(':') || text.contains(" #") {
+        return false;
+    }
+    if text.contains(|ch| ",[]{}".contains(ch)) {
+        return false;
+    }
+    // Unicode whitespace is hard to see, and some parsers treat it as
+    // a separator.
+    if text.contains(|ch: char| ch.is_whitespace() && ch != ' ') {
+        return false;
+    }
+    !is_other_type(text)
+}
+
+/// Check whether a plain scalar would be read as something other than a
+/// string, like a number, a boolean or null.
+///
+/// YAML 1.1 has far more of these than YAML 1.2, like `yes` and `off`, and
+/// it's still widely used. So we err on the side of caution: everything that
+/// starts like a number is out, and so are dates and times.
+fn is_other_type(text: &str) -> bool {
+    const KEYWORDS: &[&str] = &[
+        "~", "null", "true", "false", "yes", "no", "y", "n", "on", "off", ".inf", "-.inf", "+.inf",
+        ".nan", "<<", "=",
+    ];
+    if KEYWORDS
+        .iter()
+        .any(|keyword| keyword.eq_ignore_ascii_case(text))
+    {
+        return true;
+    }
+    let mut bytes = text.bytes();
+    match (bytes.next(), bytes.next()) {
+        (Some(b'0'..=b'9'), _) => true,
+        (Some(b'+'), Some(b'0'..=b'9'))
+        | (Some(b'-'), Some(b'0'..=b'9'))
+        | (Some(b'.'), Some(b'0'..=b'9')) => true,
+        _ => false,
+    }
+}
+
+/// The short form of an escape, if there is one and we want to use it.
+fn symbolic_escape(ch: char, opts: &Options) -> Option<&'static str> {
+    match (ch, opts.symbolic_escapes) {
+        (_, Some(false)) => None,
+        ('\n', _) => Some("\\n"),
+        ('\t', _) => Some("\\t"),
+        ('\r', _) => Some("\\r"),
+        ('\0', Some(true)) => Some("\\0"),
+        ('\x07', Some(true)) => Some("\\a"),
+        ('\x08', Some(true)) => Some("\\b"),
+        ('\x0B', Some(true)) => Some("\\v"),
+        ('\x0C', Some(true)) => Some("\\f"),
+        ('\x1B', Some(true)) => Some("\\e"),
+        _ => None,
+    }
+}